@@ -0,0 +1,34 @@
+use std::{collections::HashMap, env, path::Path, process};
+
+use ray_tracer_challenge::*;
+
+// Tessellates a YAML scene and saves it as OBJ, glTF or GLB, for a look in other viewers before
+// rendering it. Scene parameters can be set with `--set name=value`.
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let mut parameters = HashMap::new();
+    while let Some(i) = args.iter().position(|a| a == "--set") {
+        let Some(p) = args.get(i + 1) else { break };
+        let (name, value) = parse_parameter(p).unwrap_or_else(|e| {
+            eprintln!("error: --set {p}: {e}");
+            process::exit(2);
+        });
+        parameters.insert(name, value);
+        args.drain(i..i + 2);
+    }
+    if args.len() != 3 {
+        eprintln!("usage: {} [--set name=value]... <scene.yml> <output.obj|output.gltf|output.glb>", args[0]);
+        process::exit(2);
+    }
+    let scene = Scene::from_path_with_parameters(Path::new(&args[1]), &parameters).unwrap_or_else(|e| {
+        eprintln!("error: can't load {}: {e}", args[1]);
+        process::exit(1);
+    });
+    let meshes = tessellate_world(&scene.world, &TessellationOptions::default());
+    if let Err(e) = export_meshes(&meshes, scene.cameras.first(), Path::new(&args[2])) {
+        eprintln!("error: can't export {}: {e}", args[2]);
+        process::exit(1);
+    }
+    let triangles: usize = meshes.iter().map(|m| m.triangles.len()).sum();
+    println!("Wrote {} meshes with {triangles} triangles to {}.", meshes.len(), args[2]);
+}
@@ -0,0 +1,348 @@
+use std::{collections::HashMap, env, fs, io::{self, Read, Write}, path::Path, process::{self, Command, Stdio}, sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, RecvTimeoutError}}, thread, time::{Duration, SystemTime}};
+
+use ray_tracer_challenge::*;
+
+// Shows a scene in the terminal while it's being edited: a coarse image first, refined until
+// it's as sharp as the terminal allows, and again whenever the file is saved. Terminals draw
+// the image with two pixels to each character, in 24 bit color. With `--output`, the full-size
+// image is saved as well, for image viewers that reload files when they change.
+
+const USAGE: &str = "usage: preview [--camera NAME] [--output image.ppm] [--set name=value]... <scene.yml>";
+const KEYS: &str = "arrows or wasd orbit, + and - zoom, r resets the camera, q quits";
+// How often to check whether the scene file changed.
+const POLL: Duration = Duration::from_millis(250);
+// How often to check for keys and changes while rendering, which is quicker so small renders
+// aren't held up.
+const RENDER_POLL: Duration = Duration::from_millis(10);
+
+struct Args {
+    scene: String,
+    camera: Option<String>,
+    output: Option<String>,
+    parameters: HashMap<String, f64>,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args { scene: String::new(), camera: None, output: None, parameters: HashMap::new() };
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            files.push(arg.clone());
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+        match arg.as_str() {
+            "--camera" => parsed.camera = Some(value.clone()),
+            "--output" => {
+                image_format(Path::new(value), None)?;
+                parsed.output = Some(value.clone());
+            }
+            "--set" => {
+                let (name, x) = parse_parameter(value).map_err(|e| format!("--set {value}: {e}"))?;
+                parsed.parameters.insert(name, x);
+            }
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+    let [scene] = <[String; 1]>::try_from(files).map_err(|_| "expected one scene file".to_owned())?;
+    Ok(Args { scene, ..parsed })
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Key {
+    Left,
+    Right,
+    Up,
+    Down,
+    ZoomIn,
+    ZoomOut,
+    Reset,
+    Quit,
+}
+
+// Reads keys until the terminal closes, including arrow keys, which come as ESC [ A to D.
+fn read_keys(keys: mpsc::Sender<Key>) {
+    let mut stdin = io::stdin().lock();
+    let mut byte = [0];
+    let mut escape = vec![];
+    while stdin.read_exact(&mut byte).is_ok() {
+        let b = byte[0];
+        let key = if !escape.is_empty() {
+            escape.push(b);
+            match escape.as_slice() {
+                [27, b'['] => continue,
+                [27, b'[', c] => {
+                    let key = match c {
+                        b'A' => Some(Key::Up),
+                        b'B' => Some(Key::Down),
+                        b'C' => Some(Key::Right),
+                        b'D' => Some(Key::Left),
+                        _ => None,
+                    };
+                    escape.clear();
+                    key
+                }
+                _ => {
+                    escape.clear();
+                    None
+                }
+            }
+        } else {
+            match b {
+                27 => {
+                    escape.push(b);
+                    None
+                }
+                b'a' | b'A' => Some(Key::Left),
+                b'd' | b'D' => Some(Key::Right),
+                b'w' | b'W' => Some(Key::Up),
+                b's' | b'S' => Some(Key::Down),
+                b'+' | b'=' => Some(Key::ZoomIn),
+                b'-' | b'_' => Some(Key::ZoomOut),
+                b'r' | b'R' => Some(Key::Reset),
+                // Ctrl-C, which we get as a key so the terminal is put back on the way out.
+                b'q' | b'Q' | 3 => Some(Key::Quit),
+                _ => None,
+            }
+        };
+        if let Some(key) = key {
+            if keys.send(key).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+// Keys without waiting for Enter, on a screen of our own, until it's dropped.
+struct Terminal {
+    saved: Option<String>,
+}
+
+impl Terminal {
+    fn start() -> Terminal {
+        let saved = stty(&["-g"]);
+        stty(&["-icanon", "-echo", "-isig", "min", "1"]);
+        print!("\x1b[?1049h\x1b[?25l");
+        Terminal { saved }
+    }
+
+    // The pixels we can show, leaving a line for the status.
+    fn pixels() -> (usize, usize) {
+        let size = stty(&["size"]).and_then(|s| {
+            let (rows, columns) = s.split_once(' ')?;
+            Some((rows.parse::<usize>().ok()?, columns.parse::<usize>().ok()?))
+        });
+        let (rows, columns) = size.unwrap_or((24, 80));
+        (columns.max(1), 2 * rows.saturating_sub(1).max(1))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().ok();
+        if let Some(saved) = &self.saved {
+            stty(&[saved]);
+        }
+    }
+}
+
+fn to_byte(x: f64) -> u8 {
+    (255. * x.clamp(0., 1.)).round() as u8
+}
+
+// Each character is a half block, with the upper pixel in front and the lower one behind it.
+fn draw(c: Option<&Canvas>, status: &str) {
+    let mut s = "\x1b[H".to_owned();
+    if let Some(c) = c {
+        for y in (0..c.height as i64).step_by(2) {
+            for x in 0..c.width as i64 {
+                let (top, bottom) = (pixel_at(c, x, y), pixel_at(c, x, y + 1));
+                s.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                    to_byte(top.red), to_byte(top.green), to_byte(top.blue),
+                    to_byte(bottom.red), to_byte(bottom.green), to_byte(bottom.blue),
+                ));
+            }
+            s.push_str("\x1b[0m\x1b[K\r\n");
+        }
+    }
+    s.push_str(&format!("\x1b[0m{status}\x1b[K\x1b[J"));
+    print!("{s}");
+    io::stdout().flush().ok();
+}
+
+// Blows up a coarse image to the size of the final one, so it stays the same size on screen.
+fn enlarged(c: &Canvas, width: usize, height: usize) -> Canvas {
+    let mut big = canvas(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let color = pixel_at(c, (x * c.width / width) as i64, (y * c.height / height) as i64);
+            write_pixel(&mut big, x as i64, y as i64, &color);
+        }
+    }
+    big
+}
+
+// The largest size with the camera's aspect ratio that fits.
+fn fit(camera: &Camera, width: usize, height: usize) -> (usize, usize) {
+    let aspect = camera.hsize as f64 / camera.vsize as f64;
+    if width as f64 / aspect <= height as f64 {
+        (width, ((width as f64 / aspect).round() as usize).max(1))
+    } else {
+        (((height as f64 * aspect).round() as usize).max(1), height)
+    }
+}
+
+// What the camera looks at, to orbit around: whatever's in the middle of the view, or if there's
+// nothing there, the point as far away as the origin.
+fn target(scene: &Scene, camera: &Camera) -> Tuple {
+    let r = ray_through(camera, camera.hsize as f64 / 2., camera.vsize as f64 / 2.);
+    let xs = scene.world.intersect(&r);
+    let t = xs.hit().map_or_else(|| (point(0., 0., 0.) - r.origin).magnitude(), |i| i.t);
+    position(&r, if t > EPSILON { t } else { 1. })
+}
+
+fn load(args: &Args) -> Result<Scene, String> {
+    let scene = Scene::from_path_with_parameters(Path::new(&args.scene), &args.parameters).map_err(|e| e.to_string())?;
+    match &args.camera {
+        Some(name) => scene.find_camera(name),
+        None => scene.camera(),
+    }.map_err(|e| e.to_string())?;
+    Ok(scene)
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+enum Event {
+    Key(Key),
+    SceneChanged,
+    Quit,
+}
+
+// Something that means starting again, if it's happened.
+fn poll(keys: &Receiver<Key>, timeout: Duration, args: &Args, last_modified: &mut Option<SystemTime>) -> Option<Event> {
+    match keys.recv_timeout(timeout) {
+        Ok(key) => return Some(Event::Key(key)),
+        Err(RecvTimeoutError::Disconnected) => return Some(Event::Quit),
+        Err(RecvTimeoutError::Timeout) => {}
+    }
+    let m = modified(&args.scene);
+    if m != *last_modified {
+        *last_modified = m;
+        return Some(Event::SceneChanged);
+    }
+    None
+}
+
+// Renders on other threads, watching for keys and changes meanwhile. Anything that means
+// starting again stops the render and is returned instead.
+fn render_or_event(camera: &Camera, world: &World, samples: usize, keys: &Receiver<Key>, args: &Args, last_modified: &mut Option<SystemTime>) -> Result<Canvas, Event> {
+    let cancel = AtomicBool::new(false);
+    thread::scope(|s| {
+        let render = s.spawn(|| render_cancellable(camera, world, &RenderOptions { samples, threads: 0 }, &cancel));
+        while !render.is_finished() {
+            if let Some(event) = poll(keys, RENDER_POLL, args, last_modified) {
+                cancel.store(true, Ordering::Relaxed);
+                return Err(event);
+            }
+        }
+        Ok(render.join().unwrap().expect("only cancelled after an event"))
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("error: {e}\n{USAGE}");
+        process::exit(2);
+    });
+    let (sender, keys) = mpsc::channel();
+    thread::spawn(move || read_keys(sender));
+    let terminal = Terminal::start();
+
+    let mut last_modified = modified(&args.scene);
+    let mut scene = load(&args);
+    let mut orbit: Option<Orbit> = None;
+    'scene: loop {
+        let event = match &scene {
+            Err(e) => {
+                draw(None, &format!("error: {e}\r\n{KEYS}"));
+                None
+            }
+            Ok(s) => {
+                let base = match &args.camera {
+                    Some(name) => s.find_camera(name),
+                    None => s.camera(),
+                }.expect("checked when loading");
+                let camera = orbit.map_or_else(|| base.with_size(base.hsize, base.vsize), |o| o.camera(base));
+                let (width, height) = Terminal::pixels();
+                let (width, height) = fit(&camera, width, height);
+                // Coarse to fine, then smoothed.
+                let passes = [(8, 1), (4, 1), (2, 1), (1, 1), (1, 4)];
+                let mut event = None;
+                for (i, (scale, samples)) in passes.iter().enumerate() {
+                    let coarse = camera.with_size((width / scale).max(1), (height / scale).max(1));
+                    match render_or_event(&coarse, &s.world, *samples, &keys, &args, &mut last_modified) {
+                        Ok(c) => draw(Some(&enlarged(&c, width, height)), &format!("{} pass {}/{}: {KEYS}", args.scene, i + 1, passes.len())),
+                        Err(e) => {
+                            event = Some(e);
+                            break;
+                        }
+                    }
+                }
+                // Stopped by a change, it isn't saved, since it would be out of date.
+                if let (None, Some(output)) = (&event, &args.output) {
+                    draw(None, &format!("rendering {output} at {}x{}", camera.hsize, camera.vsize));
+                    match render_or_event(&camera, &s.world, 4, &keys, &args, &mut last_modified) {
+                        Ok(c) => {
+                            let status = match save_canvas(&c, Path::new(output), None) {
+                                Ok(()) => format!("saved {output}: {KEYS}"),
+                                Err(e) => format!("error: can't save {output}: {e}"),
+                            };
+                            print!("\r\x1b[K{status}");
+                            io::stdout().flush().ok();
+                        }
+                        Err(e) => event = Some(e),
+                    }
+                }
+                event
+            }
+        };
+        let mut event = event;
+        while event.is_none() {
+            event = poll(&keys, POLL, &args, &mut last_modified);
+        }
+        match event.unwrap() {
+            Event::Quit | Event::Key(Key::Quit) => break 'scene,
+            Event::SceneChanged => scene = load(&args),
+            Event::Key(Key::Reset) => orbit = None,
+            Event::Key(key) => {
+                let Ok(s) = &scene else { continue 'scene };
+                let base = match &args.camera {
+                    Some(name) => s.find_camera(name),
+                    None => s.camera(),
+                }.expect("checked when loading");
+                let o = orbit.get_or_insert_with(|| Orbit::around(base, &target(s, base)));
+                match key {
+                    Key::Left => o.rotate(-0.2, 0.),
+                    Key::Right => o.rotate(0.2, 0.),
+                    Key::Up => o.rotate(0., 0.1),
+                    Key::Down => o.rotate(0., -0.1),
+                    Key::ZoomIn => o.zoom(0.8),
+                    Key::ZoomOut => o.zoom(1.25),
+                    Key::Reset | Key::Quit => {}
+                }
+            }
+        }
+    }
+    drop(terminal);
+}
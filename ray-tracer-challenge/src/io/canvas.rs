@@ -1,5 +1,5 @@
 use crate::{Color,BLACK};
-use std::cmp;
+use std::{cmp, error::Error, fs, path::Path};
 
 #[derive(Debug)]
 pub struct Canvas {
@@ -80,6 +80,57 @@ pub fn canvas_to_ppm(c: &Canvas) -> String {
     s
 }
 
+// The same rounding as `canvas_to_ppm`.
+fn to_byte(x: f64) -> u8 {
+    (255. * x.clamp(0., 1.)).ceil() as u8
+}
+
+/// Binary PPM (P6), which is much smaller than the plain text the book uses.
+pub fn canvas_to_raw_ppm(c: &Canvas) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", c.width, c.height).into_bytes();
+    for row in &c.data {
+        for color in row {
+            bytes.extend([to_byte(color.red), to_byte(color.green), to_byte(color.blue)]);
+        }
+    }
+    bytes
+}
+
+/// PFM, which keeps colors brighter than white. Rows go from the bottom up, and the negative
+/// scale says the floats are little endian.
+pub fn canvas_to_pfm(c: &Canvas) -> Vec<u8> {
+    let mut bytes = format!("PF\n{} {}\n-1.0\n", c.width, c.height).into_bytes();
+    for row in c.data.iter().rev() {
+        for color in row {
+            for x in [color.red, color.green, color.blue] {
+                bytes.extend((x as f32).to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+/// The format to save an image at `path` as: `format` if given, or whatever the extension says,
+/// with .ppm files as plain PPM.
+pub fn image_format(path: &Path, format: Option<&str>) -> Result<String, String> {
+    let format = format.or_else(|| path.extension().and_then(|e| e.to_str())).unwrap_or("");
+    match format {
+        "ppm" | "raw-ppm" | "pfm" => Ok(format.to_owned()),
+        _ => Err(format!("can't save images as {format:?}, expected ppm, raw-ppm or pfm")),
+    }
+}
+
+/// Saves a canvas as ppm, raw-ppm or pfm. See `image_format`.
+pub fn save_canvas(c: &Canvas, path: &Path, format: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let bytes = match image_format(path, format)?.as_str() {
+        "ppm" => canvas_to_ppm(c).into_bytes(),
+        "raw-ppm" => canvas_to_raw_ppm(c),
+        _ => canvas_to_pfm(c),
+    };
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
 pub fn lines(s: &String, start: usize, end: usize) -> String {
     let x: Vec<&str> = s.lines().collect();
     x[start..end].join("\n")
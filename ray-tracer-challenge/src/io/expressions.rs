@@ -0,0 +1,145 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    variables: &'a HashMap<String, f64>,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut x = self.product()?;
+        loop {
+            if self.eat('+') {
+                x += self.product()?;
+            } else if self.eat('-') {
+                x -= self.product()?;
+            } else {
+                return Ok(x);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut x = self.unary()?;
+        loop {
+            if self.eat('*') {
+                x *= self.unary()?;
+            } else if self.eat('/') {
+                x /= self.unary()?;
+            } else {
+                return Ok(x);
+            }
+        }
+    }
+
+    // Signs bind less tightly than powers, so -2^2 is -4.
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            Ok(-self.unary()?)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<f64, String> {
+        let x = self.atom()?;
+        if self.eat('^') {
+            Ok(x.powf(self.unary()?))
+        } else {
+            Ok(x)
+        }
+    }
+
+    fn atom(&mut self) -> Result<f64, String> {
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let x = self.sum()?;
+                if !self.eat(')') {
+                    return Err("missing `)`".to_owned());
+                }
+                Ok(x)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit() || *c == '.') {
+                    self.pos += 1;
+                }
+                // An exponent, like 1e-3.
+                if self.chars.get(self.pos).is_some_and(|c| *c == 'e' || *c == 'E') {
+                    let sign = self.chars.get(self.pos + 1).is_some_and(|c| *c == '-' || *c == '+');
+                    let digits = self.pos + 1 + sign as usize;
+                    if self.chars.get(digits).is_some_and(|c| c.is_ascii_digit()) {
+                        self.pos = digits;
+                        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+                            self.pos += 1;
+                        }
+                    }
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number.parse().map_err(|_| format!("invalid number `{number}`"))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                while self.chars.get(self.pos).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                if self.eat('(') {
+                    let f: fn(f64) -> f64 = match name.as_str() {
+                        "sqrt" => f64::sqrt,
+                        "sin" => f64::sin,
+                        "cos" => f64::cos,
+                        "tan" => f64::tan,
+                        _ => return Err(format!("unknown function `{name}`")),
+                    };
+                    let x = self.sum()?;
+                    if !self.eat(')') {
+                        return Err("missing `)`".to_owned());
+                    }
+                    return Ok(f(x));
+                }
+                match (self.variables.get(&name), name.as_str()) {
+                    (Some(x), _) => Ok(*x),
+                    (None, "pi") => Ok(PI),
+                    _ => Err(format!("unknown name `{name}`")),
+                }
+            }
+            Some(c) => Err(format!("unexpected `{c}`")),
+            None => Err("expected a number".to_owned()),
+        }
+    }
+}
+
+/// Evaluates arithmetic like `pi/4` or `2*radius + 1`, with `+`, `-`, `*`, `/`, `^`, parentheses,
+/// `pi`, `sqrt`, `sin`, `cos`, `tan` and the values of `variables`.
+pub fn evaluate(expression: &str, variables: &HashMap<String, f64>) -> Result<f64, String> {
+    let mut parser = Parser { chars: expression.chars().collect(), pos: 0, variables };
+    let x = parser.sum()?;
+    match parser.peek() {
+        None => Ok(x),
+        Some(c) => Err(format!("unexpected `{c}`")),
+    }
+}
+
+/// Whether `name` can be used as a variable in expressions.
+pub fn is_variable_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_') && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
@@ -0,0 +1,575 @@
+use std::{collections::HashMap, error::Error, fs, path::Path, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{Camera, Light, Material, Matrix, Shape, Tuple, camera, point_light, point, vector, color, group, add_child, instance, prototype, triangle, smooth_triangle, matrix4, inverse, scaling, identity_matrix, DEFAULT_MATERIAL};
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfAsset {
+    version: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfScene {
+    nodes: Vec<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LightReference {
+    light: usize,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct NodeExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    lights: Option<LightReference>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfNode {
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    camera: Option<usize>,
+    matrix: Option<[f64; 16]>,
+    translation: Option<[f64; 3]>,
+    rotation: Option<[f64; 4]>,
+    scale: Option<[f64; 3]>,
+    extensions: NodeExtensions,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfPrimitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    mode: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfMesh {
+    primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfAccessor {
+    buffer_view: Option<usize>,
+    byte_offset: usize,
+    component_type: u32,
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    type_: String,
+    sparse: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfBufferView {
+    buffer: usize,
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfBuffer {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct PbrMetallicRoughness {
+    base_color_factor: [f64; 4],
+    metallic_factor: f64,
+    roughness_factor: f64,
+}
+
+impl Default for PbrMetallicRoughness {
+    fn default() -> Self {
+        PbrMetallicRoughness { base_color_factor: [1., 1., 1., 1.], metallic_factor: 1., roughness_factor: 1. }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Transmission {
+    transmission_factor: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct Ior {
+    ior: f64,
+}
+
+impl Default for Ior {
+    fn default() -> Self {
+        Ior { ior: 1.5 }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_transmission")]
+    transmission: Option<Transmission>,
+    #[serde(rename = "KHR_materials_ior")]
+    ior: Option<Ior>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfMaterial {
+    pbr_metallic_roughness: PbrMetallicRoughness,
+    alpha_mode: Option<String>,
+    extensions: MaterialExtensions,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Perspective {
+    aspect_ratio: Option<f64>,
+    yfov: f64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfCamera {
+    name: Option<String>,
+    perspective: Option<Perspective>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct GltfLight {
+    #[serde(rename = "type")]
+    type_: String,
+    color: [f64; 3],
+}
+
+impl Default for GltfLight {
+    fn default() -> Self {
+        GltfLight { type_: String::new(), color: [1., 1., 1.] }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Lights {
+    lights: Vec<GltfLight>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct DocumentExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    lights: Lights,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Document {
+    asset: GltfAsset,
+    scene: Option<usize>,
+    scenes: Vec<GltfScene>,
+    nodes: Vec<GltfNode>,
+    meshes: Vec<GltfMesh>,
+    accessors: Vec<GltfAccessor>,
+    buffer_views: Vec<GltfBufferView>,
+    buffers: Vec<GltfBuffer>,
+    materials: Vec<GltfMaterial>,
+    cameras: Vec<GltfCamera>,
+    extensions: DocumentExtensions,
+}
+
+/// What a glTF file holds: its nodes as a group, and the cameras and lights placed in them.
+pub struct GltfImport {
+    pub root: Shape,
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<Light>,
+}
+
+/// Imported cameras are this many pixels wide, with their height set by their aspect ratio.
+pub const GLTF_CAMERA_WIDTH: usize = 640;
+
+// Directional lights become point lights this far away.
+const DIRECTIONAL_LIGHT_DISTANCE: f64 = 1e6;
+
+// glTF is right-handed and we're left-handed, so we convert by flipping z.
+fn handedness() -> Matrix {
+    scaling(1., 1., -1.)
+}
+
+// Our cameras have x pointing left in the image, and glTF's have it pointing right.
+fn camera_flip() -> Matrix {
+    scaling(-1., 1., 1.)
+}
+
+fn decode_base64(s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut out = vec![];
+    let (mut bits, mut nbits) = (0_u32, 0);
+    for c in s.bytes().filter(|&c| c != b'=' && !c.is_ascii_whitespace()) {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(format!("invalid base64 character {:?}", c as char).into()),
+        };
+        bits = (bits << 6) | v as u32;
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            out.push((bits >> nbits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+// A GLB file's JSON chunk and its binary chunk, if it has one.
+type GlbChunks<'a> = (&'a [u8], Option<&'a [u8]>);
+
+fn split_glb(bytes: &[u8]) -> Result<GlbChunks<'_>, Box<dyn Error>> {
+    let word = |at: usize| -> Result<usize, Box<dyn Error>> {
+        let b = bytes.get(at..at + 4).ok_or("GLB file is truncated")?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+    if word(4)? != 2 {
+        return Err(format!("unsupported GLB version {}", word(4)?).into());
+    }
+    let (mut json, mut bin) = (None, None);
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let (length, kind) = (word(pos)?, bytes.get(pos + 4..pos + 8).ok_or("GLB file is truncated")?);
+        let data = bytes.get(pos + 8..pos + 8 + length).ok_or("GLB chunk is truncated")?;
+        match kind {
+            b"JSON" => json = Some(data),
+            b"BIN\0" => bin = Some(data),
+            // Unknown chunks are to be ignored.
+            _ => {}
+        }
+        pos += 8 + length;
+    }
+    Ok((json.ok_or("GLB file has no JSON chunk")?, bin))
+}
+
+fn load_buffers(doc: &Document, glb_bin: Option<&[u8]>, dir: Option<&Path>) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    doc.buffers.iter().enumerate().map(|(i, b)| match &b.uri {
+        None => glb_bin.map(|b| b.to_vec()).ok_or_else(|| format!("buffer {i} has no data").into()),
+        Some(uri) if uri.starts_with("data:") => {
+            let (_, data) = uri.split_once(',').ok_or("invalid data URI")?;
+            decode_base64(data)
+        }
+        Some(uri) => {
+            let dir = dir.ok_or_else(|| format!("can't load {uri} without knowing where the file is"))?;
+            fs::read(dir.join(uri)).map_err(|e| format!("can't read {uri}: {e}").into())
+        }
+    }).collect()
+}
+
+// Reads an accessor as a list of elements, each a list of components. Normalized integers are
+// turned into [0, 1] or [-1, 1].
+fn read_accessor(doc: &Document, buffers: &[Vec<u8>], index: usize) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let a = doc.accessors.get(index).ok_or_else(|| format!("no accessor {index}"))?;
+    if a.sparse.is_some() {
+        return Err(format!("accessor {index} is sparse, which isn't supported").into());
+    }
+    let components = match a.type_.as_str() {
+        "SCALAR" => 1,
+        "VEC2" => 2,
+        "VEC3" => 3,
+        "VEC4" => 4,
+        "MAT4" => 16,
+        t => return Err(format!("unsupported accessor type {t}").into()),
+    };
+    let size = match a.component_type {
+        5120 | 5121 => 1,
+        5122 | 5123 => 2,
+        5125 | 5126 => 4,
+        t => return Err(format!("unknown component type {t}").into()),
+    };
+    let element_size = components * size;
+    // Accessors without a buffer view are all zeros. There's no data to check their count
+    // against, so a count too large to allocate is an error rather than an abort.
+    let Some(view_index) = a.buffer_view else {
+        let mut zeros = vec![];
+        zeros.try_reserve_exact(a.count).map_err(|_| format!("accessor {index} has too many elements: {}", a.count))?;
+        zeros.resize(a.count, vec![0.; components]);
+        return Ok(zeros);
+    };
+    let view = doc.buffer_views.get(view_index).ok_or_else(|| format!("no buffer view {view_index}"))?;
+    let buffer = buffers.get(view.buffer).ok_or_else(|| format!("no buffer {}", view.buffer))?;
+    let data = view.byte_offset.checked_add(view.byte_length)
+        .and_then(|end| buffer.get(view.byte_offset..end))
+        .ok_or("buffer view is outside its buffer")?;
+    let stride = view.byte_stride.unwrap_or(element_size);
+    // Where the last element ends, checked before allocating for all of them.
+    let end = match a.count {
+        0 => Some(0),
+        n => (n - 1).checked_mul(stride).and_then(|x| x.checked_add(a.byte_offset)).and_then(|x| x.checked_add(element_size)),
+    };
+    if end.is_none_or(|end| end > data.len()) {
+        return Err(format!("accessor {index} is outside its buffer view").into());
+    }
+    let mut elements = Vec::with_capacity(a.count);
+    for i in 0..a.count {
+        let start = a.byte_offset + i * stride;
+        let bytes = &data[start..start + element_size];
+        elements.push(bytes.chunks(size).map(|b| {
+            let (x, max) = match a.component_type {
+                5120 => (b[0] as i8 as f64, 127.),
+                5121 => (b[0] as f64, 255.),
+                5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.),
+                5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.),
+                5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.),
+                _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.),
+            };
+            if a.normalized { (x / max).max(-1.) } else { x }
+        }).collect());
+    }
+    Ok(elements)
+}
+
+// Metals reflect, rougher surfaces get broader and dimmer highlights, and the shininess undoes
+// what the exporter does with it.
+fn to_material(m: &GltfMaterial) -> Material {
+    let pbr = &m.pbr_metallic_roughness;
+    let [r, g, b, alpha] = pbr.base_color_factor;
+    let roughness = pbr.roughness_factor.clamp(0., 1.);
+    let mut material = DEFAULT_MATERIAL;
+    material.color = color(r, g, b);
+    material.reflective = pbr.metallic_factor.clamp(0., 1.) * (1. - roughness);
+    material.specular = DEFAULT_MATERIAL.specular * (1. - roughness);
+    material.shininess = (2. / (roughness * roughness).max(1e-6) - 2.).clamp(1., 1000.);
+    if m.alpha_mode.as_deref() == Some("BLEND") {
+        material.transparency = 1. - alpha;
+    }
+    if let Some(t) = &m.extensions.transmission {
+        material.transparency = t.transmission_factor;
+        material.refractive_index = m.extensions.ior.as_ref().map_or(1.5, |i| i.ior);
+    } else if let Some(i) = &m.extensions.ior {
+        material.refractive_index = i.ior;
+    }
+    material
+}
+
+fn node_matrix(n: &GltfNode) -> Matrix {
+    if let Some(a) = n.matrix {
+        // Column-major.
+        return matrix4([
+            [a[0], a[4], a[8], a[12]],
+            [a[1], a[5], a[9], a[13]],
+            [a[2], a[6], a[10], a[14]],
+            [a[3], a[7], a[11], a[15]],
+        ]);
+    }
+    let [tx, ty, tz] = n.translation.unwrap_or([0., 0., 0.]);
+    let [x, y, z, w] = n.rotation.unwrap_or([0., 0., 0., 1.]);
+    let [sx, sy, sz] = n.scale.unwrap_or([1., 1., 1.]);
+    let rotation = matrix4([
+        [1. - 2. * (y * y + z * z), 2. * (x * y - z * w), 2. * (x * z + y * w), 0.],
+        [2. * (x * y + z * w), 1. - 2. * (x * x + z * z), 2. * (y * z - x * w), 0.],
+        [2. * (x * z - y * w), 2. * (y * z + x * w), 1. - 2. * (x * x + y * y), 0.],
+        [0., 0., 0., 1.],
+    ]);
+    crate::translation(tx, ty, tz) * rotation * scaling(sx, sy, sz)
+}
+
+struct Importer<'a> {
+    doc: &'a Document,
+    buffers: Vec<Vec<u8>>,
+    materials: Vec<Material>,
+    meshes: HashMap<usize, Option<Arc<Shape>>>,
+    cameras: Vec<Camera>,
+    lights: Vec<Light>,
+}
+
+impl Importer<'_> {
+    // Builds a mesh once, as a prototype shared by every node using it, or None if it has no
+    // triangles.
+    fn mesh(&mut self, index: usize) -> Result<Option<Arc<Shape>>, Box<dyn Error>> {
+        if let Some(m) = self.meshes.get(&index) {
+            return Ok(m.clone());
+        }
+        let doc = self.doc;
+        let mesh = doc.meshes.get(index).ok_or_else(|| format!("no mesh {index}"))?;
+        let mut g = group();
+        for p in &mesh.primitives {
+            let mode = p.mode.unwrap_or(4);
+            // Points and lines have nothing to hit.
+            if !(4..=6).contains(&mode) {
+                continue;
+            }
+            // Attributes are read as the type glTF requires for them, with a value for each vertex.
+            let attribute = |name: &str, type_: &str, count: Option<usize>| -> Result<Option<Vec<Vec<f64>>>, Box<dyn Error>> {
+                let Some(&a) = p.attributes.get(name) else { return Ok(None) };
+                let values = read_accessor(doc, &self.buffers, a)?;
+                if doc.accessors[a].type_ != type_ {
+                    return Err(format!("{name} needs to be {type_}, not {}", doc.accessors[a].type_).into());
+                }
+                if let Some(count) = count.filter(|&n| n != values.len()) {
+                    return Err(format!("{name} has {} values for {count} positions", values.len()).into());
+                }
+                Ok(Some(values))
+            };
+            let positions: Vec<Tuple> = attribute("POSITION", "VEC3", None)?.ok_or("primitive has no positions")?
+                .iter().map(|p| point(p[0], p[1], p[2])).collect();
+            let normals: Option<Vec<Tuple>> = attribute("NORMAL", "VEC3", Some(positions.len()))?
+                .map(|ns| ns.iter().map(|n| vector(n[0], n[1], n[2])).collect());
+            let uvs: Option<Vec<(f64, f64)>> = attribute("TEXCOORD_0", "VEC2", Some(positions.len()))?
+                .map(|ts| ts.iter().map(|t| (t[0], t[1])).collect());
+            let indices: Vec<usize> = match p.indices {
+                Some(i) => read_accessor(doc, &self.buffers, i)?.iter().map(|x| x[0] as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            if let Some(&i) = indices.iter().find(|&&i| i >= positions.len()) {
+                return Err(format!("vertex index {i} is out of range, with {} defined", positions.len()).into());
+            }
+            let triangles: Vec<[usize; 3]> = match mode {
+                4 => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+                // Strips alternate their winding.
+                5 => (2..indices.len()).map(|i| if i % 2 == 0 {
+                    [indices[i - 2], indices[i - 1], indices[i]]
+                } else {
+                    [indices[i - 1], indices[i - 2], indices[i]]
+                }).collect(),
+                _ => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+            };
+            let material = match p.material {
+                Some(m) => *self.materials.get(m).ok_or_else(|| format!("no material {m}"))?,
+                None => to_material(&GltfMaterial::default()),
+            };
+            for [a, b, c] in triangles {
+                let mut t = match &normals {
+                    Some(n) => smooth_triangle(&positions[a], &positions[b], &positions[c], &n[a], &n[b], &n[c]),
+                    None => triangle(&positions[a], &positions[b], &positions[c]),
+                };
+                if let Some(uv) = &uvs {
+                    t.set_uvs(&[uv[a], uv[b], uv[c]]);
+                }
+                t.material = material;
+                add_child(&mut g, &t);
+            }
+        }
+        let mesh = if g.children().is_empty() { None } else { Some(prototype(&g)) };
+        self.meshes.insert(index, mesh.clone());
+        Ok(mesh)
+    }
+
+    // Builds the group for a node, or None if there's nothing in it to render. `to_world` is the
+    // node's parent's transform in our coordinates.
+    fn node(&mut self, index: usize, to_world: &Matrix, depth: usize) -> Result<Option<Shape>, Box<dyn Error>> {
+        // Nodes form a tree, so anything deeper than the number of nodes is a cycle.
+        if depth > self.doc.nodes.len() {
+            return Err("nodes form a cycle".into());
+        }
+        let doc = self.doc;
+        let n = doc.nodes.get(index).ok_or_else(|| format!("no node {index}"))?;
+        let local = node_matrix(n);
+        let to_world = *to_world * local;
+        // Exporters hide nodes by scaling them to nothing, which can't be inverted, and they
+        // can't be seen anyway, so they're left out with everything in them.
+        if !to_world.is_invertible() {
+            return Ok(None);
+        }
+
+        if let Some(c) = n.camera {
+            let gc = doc.cameras.get(c).ok_or_else(|| format!("no camera {c}"))?;
+            // Orthographic cameras have no equivalent, so they're skipped.
+            if let Some(p) = &gc.perspective {
+                let aspect = p.aspect_ratio.unwrap_or(1.);
+                // Our field of view is across the wider side.
+                let fov = if aspect > 1. { 2. * ((p.yfov / 2.).tan() * aspect).atan() } else { p.yfov };
+                let width = GLTF_CAMERA_WIDTH as f64;
+                let mut cam = camera(width, (width / aspect).round(), fov);
+                cam.set_transform(&(camera_flip() * inverse(&to_world)));
+                cam.name = gc.name.clone();
+                self.cameras.push(cam);
+            }
+        }
+        if let Some(l) = &n.extensions.lights {
+            let gl = doc.extensions.lights.lights.get(l.light).ok_or_else(|| format!("no light {}", l.light))?;
+            // Our lights don't fall off with distance, so only their color is used. Spot lights
+            // shine in every direction.
+            let intensity = color(gl.color[0], gl.color[1], gl.color[2]);
+            let position = if gl.type_ == "directional" {
+                let direction = (to_world * vector(0., 0., -1.)).normalized();
+                point(0., 0., 0.) - direction * DIRECTIONAL_LIGHT_DISTANCE
+            } else {
+                to_world * point(0., 0., 0.)
+            };
+            self.lights.push(point_light(&position, &intensity));
+        }
+
+        let mut g = group();
+        g.set_transform(&local);
+        if let Some(m) = n.mesh {
+            if let Some(mesh) = self.mesh(m)? {
+                add_child(&mut g, &instance(&mesh));
+            }
+        }
+        for &c in &n.children {
+            if let Some(child) = self.node(c, &to_world, depth + 1)? {
+                add_child(&mut g, &child);
+            }
+        }
+        Ok(if g.children().is_empty() { None } else { Some(g) })
+    }
+}
+
+fn import(json: &[u8], glb_bin: Option<&[u8]>, dir: Option<&Path>) -> Result<GltfImport, Box<dyn Error>> {
+    let doc: Document = serde_json::from_slice(json)?;
+    if !doc.asset.version.starts_with("2.") {
+        return Err(format!("unsupported glTF version {:?}", doc.asset.version).into());
+    }
+    let buffers = load_buffers(&doc, glb_bin, dir)?;
+    let materials = doc.materials.iter().map(to_material).collect();
+    let mut importer = Importer { doc: &doc, buffers, materials, meshes: HashMap::new(), cameras: vec![], lights: vec![] };
+
+    // Without scenes, every node that isn't a child is a root.
+    let roots = match doc.scenes.get(doc.scene.unwrap_or(0)) {
+        Some(s) => s.nodes.clone(),
+        None => (0..doc.nodes.len()).filter(|i| !doc.nodes.iter().any(|n| n.children.contains(i))).collect(),
+    };
+    let mut root = group();
+    root.set_transform(&handedness());
+    for i in roots {
+        if let Some(g) = importer.node(i, &(handedness() * identity_matrix), 0)? {
+            add_child(&mut root, &g);
+        }
+    }
+    Ok(GltfImport { root, cameras: importer.cameras, lights: importer.lights })
+}
+
+/// Parses a glTF 2.0 file, as JSON or GLB. Buffers in other files are read relative to `dir`,
+/// while embedded ones are always read.
+///
+/// Nodes become groups with their transforms, and meshes become triangles, or smooth triangles
+/// if they have normals, in a prototype that every node using the mesh instances. Materials are
+/// approximated from their metallic-roughness parameters. Perspective cameras and punctual lights
+/// are placed where their nodes are. Everything is mirrored in z to go from glTF's right-handed
+/// coordinates to ours, so it renders as it looks in other viewers.
+pub fn parse_gltf(bytes: &[u8], dir: Option<&Path>) -> Result<GltfImport, Box<dyn Error>> {
+    if bytes.starts_with(b"glTF") {
+        let (json, bin) = split_glb(bytes)?;
+        import(json, bin, dir)
+    } else {
+        import(bytes, None, dir)
+    }
+}
+
+/// Loads a .gltf or .glb file, along with the buffers it refers to.
+pub fn load_gltf(path: &Path) -> Result<GltfImport, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+    parse_gltf(&bytes, Some(path.parent().unwrap_or(Path::new("."))))
+}
@@ -0,0 +1,228 @@
+use std::{error::Error, fmt::Write, fs, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::{Camera, Color, Material, TriangleMesh, scaling};
+
+// Patterns can't be exported, so shapes with them get the average of the pattern's colors.
+fn base_color(m: &Material) -> Color {
+    match m.pattern {
+        Some(p) => (p.a + p.b) * 0.5,
+        None => m.color,
+    }
+}
+
+// The distinct materials of the meshes, and which one each mesh uses.
+fn distinct_materials(meshes: &[TriangleMesh]) -> (Vec<Material>, Vec<usize>) {
+    let mut materials: Vec<Material> = vec![];
+    let indices = meshes.iter().map(|mesh| {
+        materials.iter().position(|m| *m == mesh.material).unwrap_or_else(|| {
+            materials.push(mesh.material);
+            materials.len() - 1
+        })
+    }).collect();
+    (materials, indices)
+}
+
+/// Writes meshes as an OBJ file and the MTL file it uses, which should be saved as `mtl_name` next
+/// to it. Each mesh becomes an object.
+pub fn meshes_to_obj(meshes: &[TriangleMesh], mtl_name: &str) -> (String, String) {
+    let (materials, material_indices) = distinct_materials(meshes);
+    let mut mtl = String::new();
+    for (i, m) in materials.iter().enumerate() {
+        let c = base_color(m);
+        writeln!(mtl, "newmtl material{i}").unwrap();
+        writeln!(mtl, "Kd {} {} {}", c.red, c.green, c.blue).unwrap();
+        writeln!(mtl, "Ka {0} {0} {0}", m.ambient).unwrap();
+        writeln!(mtl, "Ks {0} {0} {0}", m.specular).unwrap();
+        writeln!(mtl, "Ns {}", m.shininess).unwrap();
+        writeln!(mtl, "d {}", 1. - m.transparency).unwrap();
+        writeln!(mtl, "Ni {}", m.refractive_index).unwrap();
+        writeln!(mtl).unwrap();
+    }
+
+    let mut obj = format!("mtllib {mtl_name}\n");
+    // OBJ indices are 1-based and count across the whole file.
+    let mut offset = 1;
+    for (k, mesh) in meshes.iter().enumerate() {
+        writeln!(obj, "o mesh{k}").unwrap();
+        writeln!(obj, "usemtl material{}", material_indices[k]).unwrap();
+        for p in &mesh.positions {
+            writeln!(obj, "v {} {} {}", p.x, p.y, p.z).unwrap();
+        }
+        for n in &mesh.normals {
+            writeln!(obj, "vn {} {} {}", n.x, n.y, n.z).unwrap();
+        }
+        for t in &mesh.triangles {
+            let [a, b, c] = t.map(|i| i + offset);
+            writeln!(obj, "f {a}//{a} {b}//{b} {c}//{c}").unwrap();
+        }
+        offset += mesh.positions.len();
+    }
+    (obj, mtl)
+}
+
+fn gltf_material(m: &Material) -> Value {
+    let c = base_color(m);
+    let alpha = (1. - m.transparency).clamp(0., 1.);
+    let mut value = json!({
+        "pbrMetallicRoughness": {
+            "baseColorFactor": [c.red.clamp(0., 1.), c.green.clamp(0., 1.), c.blue.clamp(0., 1.), alpha],
+            "metallicFactor": m.reflective.clamp(0., 1.),
+            // Roughly how wide a Phong highlight with this shininess is.
+            "roughnessFactor": (2. / (m.shininess + 2.)).sqrt(),
+        },
+        // Shapes in the ray tracer have no back faces.
+        "doubleSided": true,
+    });
+    if alpha < 1. {
+        value["alphaMode"] = json!("BLEND");
+    }
+    value
+}
+
+// Field of view in the direction of the camera's height, which is what glTF uses.
+fn gltf_camera(c: &Camera) -> Value {
+    let aspect = c.hsize as f64 / c.vsize as f64;
+    json!({
+        "type": "perspective",
+        "perspective": {
+            "aspectRatio": aspect,
+            "yfov": 2. * c.half_height.atan(),
+            "znear": 0.01,
+        },
+    })
+}
+
+/// Writes meshes as glTF 2.0 JSON and the binary buffer it refers to. With `bin_uri`, the buffer
+/// should be saved there, relative to the JSON, otherwise it's expected in the same GLB file. A
+/// camera becomes a node looking the same way. Everything is mirrored in z, since glTF is
+/// right-handed and we're left-handed, so the scene looks the same in other viewers.
+pub fn meshes_to_gltf(meshes: &[TriangleMesh], camera: Option<&Camera>, bin_uri: Option<&str>) -> (String, Vec<u8>) {
+    let (materials, material_indices) = distinct_materials(meshes);
+    let mut bin: Vec<u8> = vec![];
+    let (mut buffer_views, mut accessors, mut gltf_meshes, mut nodes) = (vec![], vec![], vec![], vec![]);
+    for (k, mesh) in meshes.iter().enumerate() {
+        let mut mesh = mesh.clone();
+        mesh.transform(&scaling(1., 1., -1.));
+        let mut attributes = vec![];
+        for (values, with_bounds) in [(&mesh.positions, true), (&mesh.normals, false)] {
+            let offset = bin.len();
+            for v in values {
+                for x in [v.x, v.y, v.z] {
+                    bin.extend((x as f32).to_le_bytes());
+                }
+            }
+            buffer_views.push(json!({ "buffer": 0, "byteOffset": offset, "byteLength": bin.len() - offset, "target": 34962 }));
+            let mut accessor = json!({ "bufferView": buffer_views.len() - 1, "componentType": 5126, "count": values.len(), "type": "VEC3" });
+            if with_bounds {
+                // Positions need their bounds.
+                let bound = |f: fn(f32, f32) -> f32, start: f32| (0..3).map(|i| values.iter().map(|v| v[i] as f32).fold(start, f)).collect::<Vec<f32>>();
+                accessor["min"] = json!(bound(f32::min, f32::INFINITY));
+                accessor["max"] = json!(bound(f32::max, f32::NEG_INFINITY));
+            }
+            accessors.push(accessor);
+            attributes.push(accessors.len() - 1);
+        }
+
+        let offset = bin.len();
+        for i in mesh.triangles.iter().flatten() {
+            bin.extend((*i as u32).to_le_bytes());
+        }
+        buffer_views.push(json!({ "buffer": 0, "byteOffset": offset, "byteLength": bin.len() - offset, "target": 34963 }));
+        accessors.push(json!({ "bufferView": buffer_views.len() - 1, "componentType": 5125, "count": mesh.triangles.len() * 3, "type": "SCALAR" }));
+
+        gltf_meshes.push(json!({
+            "primitives": [{
+                "attributes": { "POSITION": attributes[0], "NORMAL": attributes[1] },
+                "indices": accessors.len() - 1,
+                "material": material_indices[k],
+            }],
+        }));
+        nodes.push(json!({ "mesh": k }));
+    }
+
+    let mut doc = json!({
+        "asset": { "version": "2.0", "generator": "ray_tracer_challenge" },
+        "scene": 0,
+        "buffers": [{ "byteLength": bin.len() }],
+        "bufferViews": buffer_views,
+        "accessors": accessors,
+        "materials": materials.iter().map(gltf_material).collect::<Vec<Value>>(),
+        "meshes": gltf_meshes,
+    });
+    if let Some(uri) = bin_uri {
+        doc["buffers"][0]["uri"] = json!(uri);
+    }
+    if let Some(c) = camera {
+        // Our cameras look down -z with y up, like glTF's, but with x to the left of the image,
+        // so the node is placed by the inverse of the view transform with x mirrored, then
+        // mirrored in z with everything else. glTF matrices are column-major.
+        let m = scaling(1., 1., -1.) * c.inverse() * scaling(-1., 1., 1.);
+        let matrix: Vec<f64> = (0..16).map(|i| m[(i % 4, i / 4)]).collect();
+        doc["cameras"] = json!([gltf_camera(c)]);
+        nodes.push(json!({ "camera": 0, "matrix": matrix }));
+    }
+    doc["scenes"] = json!([{ "nodes": (0..nodes.len()).collect::<Vec<usize>>() }]);
+    doc["nodes"] = json!(nodes);
+    // glTF doesn't allow empty arrays, which we'd have with no meshes.
+    if bin.is_empty() {
+        for key in ["buffers", "bufferViews", "accessors", "materials", "meshes"] {
+            doc.as_object_mut().unwrap().remove(key);
+        }
+    }
+    (serde_json::to_string_pretty(&doc).unwrap(), bin)
+}
+
+/// Packs glTF JSON and its buffer into a single GLB file.
+pub fn gltf_to_glb(json: &str, bin: &[u8]) -> Vec<u8> {
+    // Chunks are padded to 4 bytes, JSON with spaces and binary data with zeros.
+    let mut json = json.as_bytes().to_vec();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let mut bin = bin.to_vec();
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = vec![];
+    glb.extend(b"glTF");
+    glb.extend(2_u32.to_le_bytes());
+    glb.extend((total as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json);
+    glb.extend((bin.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(bin);
+    glb
+}
+
+/// Saves meshes in the format given by the extension of `path`: `.obj`, with an `.mtl` file next to
+/// it, `.gltf`, with a `.bin` file next to it, or `.glb`.
+pub fn export_meshes(meshes: &[TriangleMesh], camera: Option<&Camera>, path: &Path) -> Result<(), Box<dyn Error>> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let sibling = |ext: &str| -> Result<(std::path::PathBuf, String), Box<dyn Error>> {
+        let p = path.with_extension(ext);
+        let name = p.file_name().and_then(|n| n.to_str()).ok_or("invalid file name")?.to_owned();
+        Ok((p, name))
+    };
+    match extension {
+        "obj" => {
+            let (mtl_path, mtl_name) = sibling("mtl")?;
+            let (obj, mtl) = meshes_to_obj(meshes, &mtl_name);
+            fs::write(path, obj)?;
+            fs::write(mtl_path, mtl)?;
+        }
+        "gltf" => {
+            let (bin_path, bin_name) = sibling("bin")?;
+            let (json, bin) = meshes_to_gltf(meshes, camera, Some(&bin_name));
+            fs::write(path, json)?;
+            fs::write(bin_path, bin)?;
+        }
+        "glb" => {
+            let (json, bin) = meshes_to_gltf(meshes, camera, None);
+            fs::write(path, gltf_to_glb(&json, &bin))?;
+        }
+        _ => return Err(format!("can't export to {}, expected .obj, .gltf or .glb", path.display()).into()),
+    }
+    Ok(())
+}
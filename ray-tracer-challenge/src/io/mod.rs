@@ -1,6 +1,27 @@
 pub mod obj_file;
 pub use obj_file::*;
 
+pub mod ply_file;
+pub use ply_file::*;
+
+pub mod stl_file;
+pub use stl_file::*;
+
+pub mod mesh_export;
+pub use mesh_export::*;
+
+pub mod gltf_file;
+pub use gltf_file::*;
+
+pub mod patch_file;
+pub use patch_file::*;
+
+pub mod pnm_file;
+pub use pnm_file::*;
+
+pub mod expressions;
+pub use expressions::*;
+
 pub mod scene;
 pub use scene::*;
 
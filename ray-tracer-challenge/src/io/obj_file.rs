@@ -1,27 +1,71 @@
-use std::{f64::NAN, collections::HashMap};
+use std::{collections::HashMap, error::Error, fmt, fs, path::Path};
 
-use crate::{Tuple, point, group, Shape, triangle, add_child, vector, smooth_triangle};
+use crate::{Tuple, point, group, Shape, triangle, add_child, vector, smooth_triangle, Material, material, color};
 
-pub struct Parser<'a> {
+/// A problem with an OBJ or MTL file, and the line of the OBJ file it's on. Line 0 means the
+/// problem isn't with any one line, like the file not existing.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ObjError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+fn error<T>(line: usize, message: String) -> Result<T, ObjError> {
+    Err(ObjError { line, message })
+}
+
+// Faces go in the default group, a named group, an object, or a named group within an object.
+type PartKey = (Option<String>, Option<String>);
+
+pub struct Parser {
     pub ignored: usize,
     pub vertices: Vec<Tuple>,
     pub normals: Vec<Tuple>,
+    pub uvs: Vec<(f64, f64)>,
     pub default_group: Shape,
-    pub current_group: Option<&'a str>,
-    pub groups: HashMap<&'a str, Shape>,
+    pub current_group: Option<String>,
+    pub current_object: Option<String>,
+    // Faces from every `g` with the same name, in or out of objects.
+    pub groups: HashMap<String, Shape>,
+    pub objects: HashMap<String, Shape>,
+    pub materials: HashMap<String, Material>,
+    // Libraries named by `mtllib`, whether or not we could load them.
+    pub material_libraries: Vec<String>,
+    current_material: Option<Material>,
+    // Triangles in order of first appearance of where they go.
+    parts: Vec<(PartKey, Vec<Shape>)>,
 }
 
-impl Parser<'_> {
-    fn new() -> Parser<'static> {
+impl Parser {
+    fn new() -> Parser {
         // Dummy point to permit 1-indexing
-        let dummy = point(NAN, NAN, NAN);
+        let dummy = point(f64::NAN, f64::NAN, f64::NAN);
         Parser {
             ignored: 0,
             vertices: vec![dummy],
             normals: vec![dummy],
+            uvs: vec![(f64::NAN, f64::NAN)],
             default_group: group(),
             groups: HashMap::new(),
+            objects: HashMap::new(),
             current_group: None,
+            current_object: None,
+            materials: HashMap::new(),
+            material_libraries: vec![],
+            current_material: None,
+            parts: vec![],
         }
     }
 
@@ -32,95 +76,314 @@ impl Parser<'_> {
     pub fn named_group(&self, name: &str) -> Option<&Shape> {
         self.groups.get(name)
     }
+
+    pub fn named_object(&self, name: &str) -> Option<&Shape> {
+        self.objects.get(name)
+    }
+
+    fn add_triangle(&mut self, t: Shape) {
+        let key = (self.current_object.clone(), self.current_group.clone());
+        match self.parts.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, shapes)) => shapes.push(t),
+            None => self.parts.push((key, vec![t])),
+        }
+    }
+
+    // Builds the groups and objects once all faces are in.
+    fn finish(&mut self) {
+        let faces = |f: &dyn Fn(&PartKey) -> bool| -> Vec<&Shape> {
+            self.parts.iter().filter(|(k, _)| f(k)).flat_map(|(_, shapes)| shapes).collect()
+        };
+        self.default_group = group_of(&faces(&|k| *k == (None, None)));
+        let mut groups = HashMap::new();
+        let mut objects = HashMap::new();
+        for ((o, g), _) in &self.parts {
+            if let Some(g) = g {
+                groups.entry(g.clone()).or_insert_with(|| group_of(&faces(&|k| k.1.as_ref() == Some(g))));
+            }
+            if let Some(o) = o {
+                objects.entry(o.clone()).or_insert_with(|| {
+                    let mut obj = group_of(&faces(&|k| *k == (Some(o.clone()), None)));
+                    for ((o2, g), shapes) in &self.parts {
+                        if o2.as_ref() == Some(o) && g.is_some() {
+                            add_child(&mut obj, &group_of(&shapes.iter().collect::<Vec<&Shape>>()));
+                        }
+                    }
+                    obj
+                });
+            }
+        }
+        self.groups = groups;
+        self.objects = objects;
+    }
 }
 
-pub fn parse_obj_file(bytes: &[u8]) -> Parser {
-    let s = std::str::from_utf8(bytes).unwrap();
+fn group_of(shapes: &[&Shape]) -> Shape {
+    let mut g = group();
+    for s in shapes {
+        add_child(&mut g, s);
+    }
+    g
+}
+
+fn parse_f64s(args: &[&str], line: usize) -> Result<Vec<f64>, ObjError> {
+    args.iter().map(|a| a.parse().or_else(|_| error(line, format!("invalid number {a:?}")))).collect()
+}
+
+// Turns a 1-based or negative (counting back from the end) index into an index into `count`
+// records after the dummy.
+fn resolve_index(s: &str, count: usize, what: &str, line: usize) -> Result<usize, ObjError> {
+    let i: i64 = s.parse().or_else(|_| error(line, format!("invalid {what} index {s:?}")))?;
+    let resolved = if i < 0 { count as i64 + 1 + i } else { i };
+    if resolved < 1 || resolved > count as i64 {
+        return error(line, format!("{what} index {i} is out of range, with {count} defined"));
+    }
+    Ok(resolved as usize)
+}
+
+/// Splits a polygon into triangles, as indices into `points`, keeping its winding. Convex polygons
+/// become a fan from the first vertex. Concave ones are split by ear clipping.
+pub fn triangulate(points: &[Tuple]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    // Newell's method, which works for concave polygons too.
+    let mut normal = vector(0., 0., 0.);
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        normal.x += (a.y - b.y) * (a.z + b.z);
+        normal.y += (a.z - b.z) * (a.x + b.x);
+        normal.z += (a.x - b.x) * (a.y + b.y);
+    }
+    let turn = |a: usize, b: usize, c: usize| (points[b] - points[a]).cross(&(points[c] - points[b])).dot(&normal);
+    if (0..n).all(|i| turn((i + n - 1) % n, i, (i + 1) % n) >= 0.) {
+        return (2..n).map(|i| [0, i - 1, i]).collect();
+    }
+
+    let inside = |p: usize, [a, b, c]: [usize; 3]| {
+        [(a, b), (b, c), (c, a)].iter().all(|&(x, y)| (points[y] - points[x]).cross(&(points[p] - points[x])).dot(&normal) >= 0.)
+    };
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut tris = vec![];
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corner = |i: usize| [remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]];
+        let ear = (0..m).find(|&i| {
+            let tri = corner(i);
+            turn(tri[0], tri[1], tri[2]) > 0. && remaining.iter().all(|&p| tri.contains(&p) || !inside(p, tri))
+        });
+        // Degenerate polygons may have no ears, so we make do with any corner.
+        let ear = ear.unwrap_or(0);
+        tris.push(corner(ear));
+        remaining.remove(ear);
+    }
+    tris.push([remaining[0], remaining[1], remaining[2]]);
+    tris
+}
+
+/// Parses an MTL file into materials by name. Colors go into `color`, the average of `Ka` and `Ks`
+/// into `ambient` and `specular`, `Ns` into `shininess`, `d` or `Tr` into `transparency` and `Ni`
+/// into `refractive_index`. Anything else is ignored.
+pub fn parse_mtl_file(bytes: &[u8]) -> Result<HashMap<String, Material>, ObjError> {
+    let s = std::str::from_utf8(bytes).or_else(|_| error(0, "not valid UTF-8".to_owned()))?;
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
+        let bits: Vec<&str> = line.split_whitespace().collect();
+        if bits.is_empty() {
+            continue;
+        }
+        let (cmd, args) = (bits[0], &bits[1..]);
+        if cmd == "newmtl" {
+            if let Some((name, m)) = current.take() {
+                materials.insert(name, m);
+            }
+            current = Some((args.join(" "), material()));
+            continue;
+        }
+        let Some((_, m)) = &mut current else {
+            continue;
+        };
+        let numbers = || parse_f64s(args, line_number);
+        let average = |xs: &[f64]| xs.iter().sum::<f64>() / xs.len() as f64;
+        match cmd {
+            "Kd" | "Ka" | "Ks" => {
+                let xs = numbers()?;
+                if xs.len() != 3 {
+                    return error(line_number, format!("{cmd} needs 3 values"));
+                }
+                match cmd {
+                    "Kd" => m.color = color(xs[0], xs[1], xs[2]),
+                    "Ka" => m.ambient = average(&xs),
+                    _ => m.specular = average(&xs),
+                }
+            }
+            "Ns" | "d" | "Tr" | "Ni" => {
+                let xs = numbers()?;
+                let Some(&x) = xs.first() else {
+                    return error(line_number, format!("{cmd} needs a value"));
+                };
+                match cmd {
+                    "Ns" => m.shininess = x,
+                    "d" => m.transparency = 1. - x,
+                    "Tr" => m.transparency = x,
+                    _ => m.refractive_index = x,
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, m)) = current {
+        materials.insert(name, m);
+    }
+    Ok(materials)
+}
+
+fn parse(bytes: &[u8], dir: Option<&Path>) -> Result<Parser, ObjError> {
+    let s = std::str::from_utf8(bytes).or_else(|_| error(0, "not valid UTF-8".to_owned()))?;
     let mut p = Parser::new();
-    for line in s.lines() {
+    for (i, line) in s.lines().enumerate() {
+        let line_number = i + 1;
         let bits: Vec<&str> = line.split_whitespace().collect();
-        if bits.len() == 0 {
+        if bits.is_empty() {
             continue
         }
         let cmd = bits[0];
         let args = &bits[1..];
+        // Names may contain spaces.
+        let name = || line.trim().split_once(char::is_whitespace).map(|(_, n)| n.trim().to_owned());
         match cmd {
-            "v" => {
-                assert!(args.len() == 3);
-                p.vertices.push(point(
-                    args[0].parse().unwrap(),
-                    args[1].parse().unwrap(),
-                    args[2].parse().unwrap(),
-                ));
+            "v" | "vn" => {
+                let xs = parse_f64s(args, line_number)?;
+                // Extra values, like w or vertex colors, are ignored.
+                if xs.len() < 3 {
+                    return error(line_number, format!("{cmd} needs 3 coordinates"));
+                }
+                if cmd == "v" {
+                    p.vertices.push(point(xs[0], xs[1], xs[2]));
+                } else {
+                    p.normals.push(vector(xs[0], xs[1], xs[2]));
+                }
             }
-            "vn" => {
-                assert!(args.len() == 3);
-                p.normals.push(vector(
-                    args[0].parse().unwrap(),
-                    args[1].parse().unwrap(),
-                    args[2].parse().unwrap(),
-                ));
+            "vt" => {
+                let xs = parse_f64s(args, line_number)?;
+                if xs.is_empty() {
+                    return error(line_number, "vt needs coordinates".to_owned());
+                }
+                p.uvs.push((xs[0], xs.get(1).copied().unwrap_or(0.)));
             }
             "f" => {
-                let has_normals = args[0].contains("/");
-                let parsed: Vec<(usize, Option<usize>, Option<usize>)> = args.into_iter().map(
-                    |x| {
-                        let els: Vec<&str> = x.split("/").collect();
-                        if has_normals {
-                            assert!(els.len() == 3);
-                            (els[0].parse().unwrap(), els[1].parse().ok(), els[2].parse().ok())
-                        } else {
-                            assert!(els.len() == 1);
-                            (els[0].parse().unwrap(), None, None)
-                        }
+                if args.len() < 3 {
+                    return error(line_number, format!("faces need at least 3 vertices, not {}", args.len()));
+                }
+                let mut corners = vec![];
+                for arg in args {
+                    let els: Vec<&str> = arg.split('/').collect();
+                    if els.len() > 3 {
+                        return error(line_number, format!("invalid face vertex {arg:?}"));
                     }
-                ).collect();
-                let g = if let Some(name) = p.current_group {
-                    p.groups.get_mut(name).unwrap()
-                } else {
-                    &mut p.default_group
-                };
-                for i in 2..args.len() {
-                    let idx = (0, i-1, i);
-                    let mut t = if has_normals {
-                        smooth_triangle(
-                            &p.vertices[parsed[idx.0].0],
-                            &p.vertices[parsed[idx.1].0],
-                            &p.vertices[parsed[idx.2].0],
-                            &p.normals[parsed[idx.0].2.unwrap()],
-                            &p.normals[parsed[idx.1].2.unwrap()],
-                            &p.normals[parsed[idx.2].2.unwrap()],
-                        )
-                    } else {
-                        triangle(
-                            &p.vertices[parsed[idx.0].0],
-                            &p.vertices[parsed[idx.1].0],
-                            &p.vertices[parsed[idx.2].0],
-                        )
+                    let v = resolve_index(els[0], p.vertices.len() - 1, "vertex", line_number)?;
+                    // Texture indices are only checked when there are texture coordinates, since
+                    // some files have placeholders without them.
+                    let vt = match els.get(1) {
+                        Some(x) if !x.is_empty() && p.uvs.len() > 1 => Some(resolve_index(x, p.uvs.len() - 1, "texture", line_number)?),
+                        _ => None,
                     };
-                    add_child(g, &mut t);
+                    let vn = match els.get(2) {
+                        Some(x) if !x.is_empty() => Some(resolve_index(x, p.normals.len() - 1, "normal", line_number)?),
+                        _ => None,
+                    };
+                    corners.push((v, vt, vn));
+                }
+                let points: Vec<Tuple> = corners.iter().map(|c| p.vertices[c.0]).collect();
+                for [a, b, c] in triangulate(&points) {
+                    let (a, b, c) = (corners[a], corners[b], corners[c]);
+                    let mut t = match (a.2, b.2, c.2) {
+                        (Some(na), Some(nb), Some(nc)) => smooth_triangle(
+                            &p.vertices[a.0], &p.vertices[b.0], &p.vertices[c.0],
+                            &p.normals[na], &p.normals[nb], &p.normals[nc],
+                        ),
+                        _ => triangle(&p.vertices[a.0], &p.vertices[b.0], &p.vertices[c.0]),
+                    };
+                    if let (Some(ta), Some(tb), Some(tc)) = (a.1, b.1, c.1) {
+                        t.set_uvs(&[p.uvs[ta], p.uvs[tb], p.uvs[tc]]);
+                    }
+                    if let Some(m) = p.current_material {
+                        t.material = m;
+                    }
+                    p.add_triangle(t);
                 }
             }
             "g" => {
-                let mut namesplit = line.splitn(2, char::is_whitespace);
-                assert!(namesplit.next().unwrap() == cmd);
-                let name = namesplit.next().unwrap();
-                p.groups.insert(name, group());
-                p.current_group = Some(name);
+                p.current_group = name();
+            }
+            "o" => {
+                p.current_object = name();
+                p.current_group = None;
+            }
+            "usemtl" => {
+                let Some(n) = name() else {
+                    return error(line_number, "usemtl needs a material name".to_owned());
+                };
+                // Without a file to go from, libraries aren't loaded, so their materials are left
+                // as the default.
+                p.current_material = match p.materials.get(&n) {
+                    Some(m) => Some(*m),
+                    None if dir.is_none() => None,
+                    None => return error(line_number, format!("no material {n:?}")),
+                };
+            }
+            "mtllib" => {
+                let Some(lib) = name() else {
+                    return error(line_number, "mtllib needs a file name".to_owned());
+                };
+                if let Some(dir) = dir {
+                    let bytes = fs::read(dir.join(&lib)).or_else(|e| error(line_number, format!("can't read {lib}: {e}")))?;
+                    let materials = parse_mtl_file(&bytes).or_else(|e| error(line_number, format!("in {lib}: {e}")))?;
+                    p.materials.extend(materials);
+                }
+                p.material_libraries.push(lib);
             }
             _ => {
                 p.ignored += 1;
             }
         }
     }
-    p
+    p.finish();
+    Ok(p)
+}
+
+/// Parses an OBJ file held in memory. Since we don't know where it came from, `mtllib` files aren't
+/// loaded, and faces using their materials get the default one.
+pub fn try_parse_obj_file(bytes: &[u8]) -> Result<Parser, ObjError> {
+    parse(bytes, None)
+}
+
+/// Parses an OBJ file held in memory, panicking if it's invalid.
+pub fn parse_obj_file(bytes: &[u8]) -> Parser {
+    try_parse_obj_file(bytes).unwrap_or_else(|e| panic!("Invalid OBJ file: {e}"))
+}
+
+/// Loads an OBJ file, along with the MTL files it names, which are relative to it. Using a
+/// material none of them define is an error.
+pub fn load_obj_file(path: &Path) -> Result<Parser, ObjError> {
+    let bytes = fs::read(path).or_else(|e| error(0, format!("can't read {}: {e}", path.display())))?;
+    parse(&bytes, Some(path.parent().unwrap_or(Path::new("."))))
 }
 
 pub fn obj_to_group(parser: &Parser) -> Shape {
     let mut g = group();
     add_child(&mut g, & parser.default_group);
-    for child in parser.groups.values() {
-        add_child(&mut g, child);
+    let mut objects_added = vec![];
+    for ((o, name), shapes) in &parser.parts {
+        match (o, name) {
+            // Just the faces outside objects, since objects may have groups with the same name.
+            (None, Some(_)) => add_child(&mut g, &group_of(&shapes.iter().collect::<Vec<&Shape>>())),
+            (Some(o), _) if !objects_added.contains(&o) => {
+                add_child(&mut g, &parser.objects[o]);
+                objects_added.push(o);
+            }
+            _ => {}
+        }
     }
     g
 }
@@ -0,0 +1,77 @@
+use std::error::Error;
+
+use crate::{Tuple, point, Shape, group, add_child, bezier_patch, tessellate_bezier_patch, rotation_x, PI};
+
+// The classic Newell teapot, converted from the symmetric patch data in GLUT's teapot.c.
+// Coordinates are z-up, as in the original data.
+const NEWELL_TEAPOT: &str = include_str!("newell_teapot.txt");
+
+/// Parses Bézier patches in the format of the Utah/Newell teapot data: a patch count, one line of 16
+/// (1-indexed) control point indices per patch, a vertex count, and one line of x, y, z per vertex.
+/// Values may be separated by commas and/or whitespace.
+pub fn parse_patch_file(bytes: &[u8]) -> Result<Vec<[Tuple; 16]>, Box<dyn Error>> {
+    let s = std::str::from_utf8(bytes)?;
+    let mut tokens = s.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| !t.is_empty());
+    let mut next = |what: &str| tokens.next().ok_or_else(|| format!("unexpected end of file, expected {what}"));
+
+    let npatches: usize = next("patch count")?.parse()?;
+    let mut indices = vec![];
+    for _ in 0..npatches {
+        let mut patch = [0; 16];
+        for idx in &mut patch {
+            *idx = next("control point index")?.parse::<usize>()?;
+        }
+        indices.push(patch);
+    }
+
+    let nvertices: usize = next("vertex count")?.parse()?;
+    let mut vertices = vec![];
+    for _ in 0..nvertices {
+        let x = next("x coordinate")?.parse()?;
+        let y = next("y coordinate")?.parse()?;
+        let z = next("z coordinate")?.parse()?;
+        vertices.push(point(x, y, z));
+    }
+
+    let mut patches = vec![];
+    for patch in indices {
+        let mut cps = [point(0., 0., 0.); 16];
+        for (cp, idx) in cps.iter_mut().zip(patch) {
+            if idx == 0 || idx > vertices.len() {
+                return Err(format!("control point index {idx} out of range 1..={}", vertices.len()).into());
+            }
+            *cp = vertices[idx - 1];
+        }
+        patches.push(cps);
+    }
+    Ok(patches)
+}
+
+/// Builds a group from patches. With a resolution, each patch is tessellated into smooth triangles,
+/// otherwise the patches are intersected directly.
+pub fn patches_to_group(patches: &[[Tuple; 16]], resolution: Option<usize>) -> Shape {
+    let mut g = group();
+    for cps in patches {
+        let s = bezier_patch(cps);
+        match resolution {
+            Some(n) => add_child(&mut g, &tessellate_bezier_patch(s.as_bezier_patch().unwrap(), n)),
+            None => add_child(&mut g, &s),
+        }
+    }
+    g
+}
+
+pub fn teapot_patches() -> Vec<[Tuple; 16]> {
+    parse_patch_file(NEWELL_TEAPOT.as_bytes()).unwrap()
+}
+
+/// The Newell teapot, rotated so that it sits on the xz plane with y up.
+/// It's about 6.4 units across from spout to handle, and 3.15 units tall.
+/// The returned group has an identity transform, so it can be placed freely.
+pub fn teapot(resolution: Option<usize>) -> Shape {
+    let mut patches = patches_to_group(&teapot_patches(), resolution);
+    patches.set_transform(&rotation_x(-PI / 2.));
+    let mut g = group();
+    add_child(&mut g, &patches);
+    g
+}
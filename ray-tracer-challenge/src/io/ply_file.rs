@@ -0,0 +1,275 @@
+use std::error::Error;
+
+use crate::{Tuple, Color, Shape, point, vector, color, group, add_child, triangle, smooth_triangle, triangulate};
+
+/// A polygon mesh read from a PLY file. Normals and colors are per vertex, when the file has them,
+/// and faces are lists of indices into `vertices`.
+pub struct PlyMesh {
+    pub vertices: Vec<Tuple>,
+    pub normals: Option<Vec<Tuple>>,
+    pub colors: Option<Vec<Color>>,
+    pub faces: Vec<Vec<usize>>,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Scalar {
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl Scalar {
+    fn parse(s: &str) -> Result<Scalar, Box<dyn Error>> {
+        Ok(match s {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("unknown property type {s}").into()),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // What to divide a color component by to get it into [0, 1].
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.,
+            Scalar::U16 => 65535.,
+            _ => 1.,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    // Name, type of the count and type of the items.
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    format: Format,
+}
+
+impl Reader<'_> {
+    fn value(&mut self, ty: Scalar) -> Result<f64, Box<dyn Error>> {
+        if self.format == Format::Ascii {
+            while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self.pos < self.bytes.len() && !self.bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if start == self.pos {
+                return Err("unexpected end of file".into());
+            }
+            let token = std::str::from_utf8(&self.bytes[start..self.pos])?;
+            return Ok(token.parse().map_err(|_| format!("invalid number {token:?}"))?);
+        }
+
+        let size = ty.size();
+        if self.pos + size > self.bytes.len() {
+            return Err("unexpected end of file".into());
+        }
+        let mut b = [0; 8];
+        b[..size].copy_from_slice(&self.bytes[self.pos..self.pos + size]);
+        if self.format == Format::BigEndian {
+            b[..size].reverse();
+        }
+        self.pos += size;
+        Ok(match ty {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+
+    fn index(&mut self, ty: Scalar) -> Result<usize, Box<dyn Error>> {
+        let x = self.value(ty)?;
+        if x < 0. || x.fract() != 0. {
+            return Err(format!("invalid index or count {x}").into());
+        }
+        Ok(x as usize)
+    }
+}
+
+// Parses the header, returning the format, the elements and where the data starts.
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize), Box<dyn Error>> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut pos = 0;
+    let mut first = true;
+    loop {
+        let Some(len) = bytes[pos..].iter().position(|&c| c == b'\n') else {
+            return Err("header has no end_header".into());
+        };
+        let line = std::str::from_utf8(&bytes[pos..pos + len])?.trim();
+        pos += len + 1;
+        if first {
+            if line != "ply" {
+                return Err("not a PLY file".into());
+            }
+            first = false;
+            continue;
+        }
+        let bits: Vec<&str> = line.split_whitespace().collect();
+        match bits.as_slice() {
+            ["end_header"] => break,
+            ["format", f, _version] => {
+                format = Some(match *f {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(format!("unknown format {f}").into()),
+                });
+            }
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| format!("invalid count for {name}: {count:?}"))?;
+                elements.push(Element { name: name.to_string(), count, properties: vec![] });
+            }
+            ["property", "list", count_ty, item_ty, name] => {
+                let e = elements.last_mut().ok_or("property before any element")?;
+                e.properties.push(Property::List(name.to_string(), Scalar::parse(count_ty)?, Scalar::parse(item_ty)?));
+            }
+            ["property", ty, name] => {
+                let e = elements.last_mut().ok_or("property before any element")?;
+                e.properties.push(Property::Scalar(name.to_string(), Scalar::parse(ty)?));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(format!("invalid header line {line:?}").into()),
+        }
+    }
+    Ok((format.ok_or("header has no format")?, elements, pos))
+}
+
+/// Parses an ASCII or binary PLY file. Vertices need `x`, `y` and `z`, and may have normals (`nx`,
+/// `ny`, `nz`) and colors (`red`, `green`, `blue`, as bytes or in [0, 1]). Faces are read from a
+/// `vertex_indices` list. Other elements and properties are skipped.
+pub fn parse_ply(bytes: &[u8]) -> Result<PlyMesh, Box<dyn Error>> {
+    let (format, elements, pos) = parse_header(bytes)?;
+    let mut r = Reader { bytes, pos, format };
+    let mut mesh = PlyMesh { vertices: vec![], normals: None, colors: None, faces: vec![] };
+    for e in &elements {
+        let names: Vec<&str> = e.properties.iter().map(|p| match p {
+            Property::Scalar(n, _) | Property::List(n, _, _) => n.as_str(),
+        }).collect();
+        let has = |wanted: &[&str]| wanted.iter().all(|w| names.contains(w));
+        let is_vertex = e.name == "vertex";
+        if is_vertex {
+            if !has(&["x", "y", "z"]) {
+                return Err("vertices need x, y and z".into());
+            }
+            if has(&["nx", "ny", "nz"]) {
+                mesh.normals = Some(vec![]);
+            }
+            if has(&["red", "green", "blue"]) {
+                mesh.colors = Some(vec![]);
+            }
+        }
+        for _ in 0..e.count {
+            let mut values = [0.; 9];
+            for p in &e.properties {
+                match p {
+                    Property::Scalar(name, ty) => {
+                        let x = r.value(*ty)?;
+                        let slot = ["x", "y", "z", "nx", "ny", "nz", "red", "green", "blue"].iter().position(|n| n == name);
+                        if let (true, Some(i)) = (is_vertex, slot) {
+                            values[i] = if i >= 6 { x / ty.color_scale() } else { x };
+                        }
+                    }
+                    Property::List(name, count_ty, item_ty) => {
+                        let n = r.index(*count_ty)?;
+                        // Every item takes at least a byte, so don't allocate for counts the file can't hold.
+                        let item_size = if format == Format::Ascii { 1 } else { item_ty.size() };
+                        if n.checked_mul(item_size).is_none_or(|size| size > bytes.len() - r.pos) {
+                            return Err(format!("list of {n} items is longer than the file").into());
+                        }
+                        let mut items = Vec::with_capacity(n);
+                        for _ in 0..n {
+                            items.push(r.index(*item_ty)?);
+                        }
+                        if e.name == "face" && (name == "vertex_indices" || name == "vertex_index") {
+                            if n < 3 {
+                                return Err(format!("faces need at least 3 vertices, not {n}").into());
+                            }
+                            mesh.faces.push(items);
+                        }
+                    }
+                }
+            }
+            if is_vertex {
+                mesh.vertices.push(point(values[0], values[1], values[2]));
+                if let Some(normals) = &mut mesh.normals {
+                    normals.push(vector(values[3], values[4], values[5]));
+                }
+                if let Some(colors) = &mut mesh.colors {
+                    colors.push(color(values[6], values[7], values[8]));
+                }
+            }
+        }
+    }
+    if let Some(&i) = mesh.faces.iter().flatten().find(|&&i| i >= mesh.vertices.len()) {
+        return Err(format!("vertex index {i} is out of range, with {} defined", mesh.vertices.len()).into());
+    }
+    Ok(mesh)
+}
+
+/// Builds a group of triangles from a mesh, or smooth triangles if it has normals.
+///
+/// Vertex colors aren't interpolated across triangles, since materials have a single color: each
+/// triangle gets the average of its corners' colors, so colors change in steps at the edges unless
+/// the mesh is fine.
+pub fn ply_to_group(mesh: &PlyMesh) -> Shape {
+    let mut g = group();
+    for face in &mesh.faces {
+        let points: Vec<Tuple> = face.iter().map(|&i| mesh.vertices[i]).collect();
+        for tri in triangulate(&points) {
+            let [a, b, c] = tri.map(|i| face[i]);
+            let (p1, p2, p3) = (&mesh.vertices[a], &mesh.vertices[b], &mesh.vertices[c]);
+            let mut t = match &mesh.normals {
+                Some(n) => smooth_triangle(p1, p2, p3, &n[a], &n[b], &n[c]),
+                None => triangle(p1, p2, p3),
+            };
+            if let Some(colors) = &mesh.colors {
+                t.material.color = (colors[a] + colors[b] + colors[c]) * (1. / 3.);
+            }
+            add_child(&mut g, &t);
+        }
+    }
+    g
+}
+
+/// Parses a PLY file into a group of triangles, ready for `freeze_and_optimize`.
+pub fn group_from_ply(bytes: &[u8]) -> Result<Shape, Box<dyn Error>> {
+    Ok(ply_to_group(&parse_ply(bytes)?))
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+
+use crate::{Shape, heightfield};
+
+/// A grayscale image, with values normalized to [0, 1] and stored row by row.
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<f64>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn skip_whitespace_and_comments(&mut self) {
+        while self.pos < self.bytes.len() {
+            let c = self.bytes[self.pos];
+            if c == b'#' {
+                while self.pos < self.bytes.len() && self.bytes[self.pos] != b'\n' {
+                    self.pos += 1;
+                }
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<&str, Box<dyn Error>> {
+        self.skip_whitespace_and_comments();
+        let start = self.pos;
+        while self.pos < self.bytes.len() && !self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err("unexpected end of file".into());
+        }
+        Ok(std::str::from_utf8(&self.bytes[start..self.pos])?)
+    }
+
+    fn number(&mut self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.token()?.parse()?)
+    }
+
+    fn binary_sample(&mut self, maxval: usize) -> Result<usize, Box<dyn Error>> {
+        let size = if maxval < 256 { 1 } else { 2 };
+        if self.pos + size > self.bytes.len() {
+            return Err("unexpected end of file".into());
+        }
+        let value = if size == 1 {
+            self.bytes[self.pos] as usize
+        } else {
+            // 16 bit samples are big endian.
+            ((self.bytes[self.pos] as usize) << 8) | self.bytes[self.pos + 1] as usize
+        };
+        self.pos += size;
+        Ok(value)
+    }
+}
+
+/// Parses a PGM (P2/P5) or PPM (P3/P6) image into grayscale. Color pixels are converted with
+/// Rec. 709 luma weights.
+pub fn parse_pnm(bytes: &[u8]) -> Result<GrayImage, Box<dyn Error>> {
+    let mut r = Reader { bytes, pos: 0 };
+    let magic = r.token()?.to_owned();
+    let (channels, binary) = match magic.as_str() {
+        "P2" => (1, false),
+        "P5" => (1, true),
+        "P3" => (3, false),
+        "P6" => (3, true),
+        _ => return Err(format!("unsupported image type {magic}, expected P2, P3, P5 or P6").into()),
+    };
+    let width = r.number()?;
+    let height = r.number()?;
+    let maxval = r.number()?;
+    if maxval == 0 || maxval > 65535 {
+        return Err(format!("invalid maximum value {maxval}").into());
+    }
+    if binary {
+        // Exactly one whitespace character separates the header from binary data.
+        r.pos += 1;
+    }
+
+    // Each sample takes at least a byte, so a header that asks for more than the file holds is
+    // wrong, and we don't try to allocate for it.
+    let pixels = width.checked_mul(height).ok_or("image is too large")?;
+    let sample_size = if binary && maxval >= 256 { 2 } else { 1 };
+    let needed = pixels.checked_mul(channels * sample_size).ok_or("image is too large")?;
+    if needed > bytes.len().saturating_sub(r.pos) {
+        return Err(format!("a {width}x{height} image needs more data than the file has").into());
+    }
+
+    let mut data = Vec::with_capacity(pixels);
+    for _ in 0..pixels {
+        let mut samples = [0.; 3];
+        for s in samples.iter_mut().take(channels) {
+            let v = if binary { r.binary_sample(maxval)? } else { r.number()? };
+            *s = v as f64 / maxval as f64;
+        }
+        data.push(if channels == 1 {
+            samples[0]
+        } else {
+            0.2126 * samples[0] + 0.7152 * samples[1] + 0.0722 * samples[2]
+        });
+    }
+    Ok(GrayImage { width, height, data })
+}
+
+/// Builds a heightfield from a grayscale image. Image columns map to x and rows map to z, and
+/// brightness maps to heights in [0, 1]. Scale the shape to get the desired size.
+pub fn heightfield_from_pnm(bytes: &[u8]) -> Result<Shape, Box<dyn Error>> {
+    let img = parse_pnm(bytes)?;
+    if img.width < 2 || img.height < 2 {
+        return Err(format!("heightfield needs at least 2x2 samples, got {}x{}", img.width, img.height).into());
+    }
+    Ok(heightfield(&img.data, img.width, img.height))
+}
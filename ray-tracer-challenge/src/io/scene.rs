@@ -1,34 +1,45 @@
-use core::panic;
-use std::{fs, error::Error, collections::HashMap};
+use std::{fs, error::Error, collections::HashMap, f64::consts::PI, fmt, path::{Path, PathBuf}};
 
 use serde::{Serialize, Deserialize};
+use serde_yaml::Value;
 
-use crate::{Tuple, Color, world, point_light, point, vector, color, camera, view_transform, render, Canvas, identity_matrix, Matrix, translation, rotation_x, plane, Shape, DEFAULT_MATERIAL, Material, scaling, cube, sphere, group, add_child, checkers_pattern, rotation_y, rotation_z, stripe_pattern, cylinder, cone};
+use crate::{Tuple, Color, World, Camera, world, point_light, point, vector, color, camera, view_transform, render, Canvas, identity_matrix, Matrix, translation, rotation_x, plane, Shape, DEFAULT_MATERIAL, Material, scaling, cube, sphere, group, add_child, checkers_pattern, rotation_y, rotation_z, stripe_pattern, cylinder, cone, annulus, quad, gradient_pattern, ring_pattern, nested_pattern, Pattern, shearing, csg, triangle, BLACK, smooth_triangle, load_obj_file, obj_to_group, evaluate, is_variable_name, matrix4, lathe, quadric, heightfield, bezier_patch, ShapeType, PatternType, BVHStats, RenderOptions, render_with};
 
-type Tup = [f64; 3];
+/// A number, or an expression using the scene's parameters, like `pi/4` or `2*radius`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Num {
+    Value(f64),
+    Expr(String),
+}
+
+type Tup = [Num; 3];
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Pat {
-    #[serde(alias = "type")]
+    #[serde(rename = "type", alias = "type_")]
     type_: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     colors: Vec<Tup>,
+    // Patterns to use instead of colors.
+    patterns: Option<Vec<Pat>>,
     transform: Option<Vec<TransformEntry>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Mat {
-    pattern: Option<Pat>,
+    pattern: Option<Box<Pat>>,
     color: Option<Tup>,
-    ambient: Option<f64>,
-    diffuse: Option<f64>,
-    specular: Option<f64>,
-    shininess: Option<f64>,
-    reflective: Option<f64>,
-    transparency: Option<f64>,
-    #[serde(alias = "refractive-index")]
-    refractive_index: Option<f64>,
+    ambient: Option<Num>,
+    diffuse: Option<Num>,
+    specular: Option<Num>,
+    shininess: Option<Num>,
+    reflective: Option<Num>,
+    transparency: Option<Num>,
+    #[serde(rename = "refractive-index", alias = "refractive_index")]
+    refractive_index: Option<Num>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -36,7 +47,7 @@ pub struct Mat {
 #[serde(untagged)]
 pub enum MatEntry {
     Name(String),
-    Mat(Mat),
+    Mat(Box<Mat>),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -51,9 +62,6 @@ impl TransformEntryItem {
     fn as_op(&self) -> Option<&str> {
         if let TransformEntryItem::Op(s) = self { Some(s) } else { None }
     }
-    fn as_arg(&self) -> Option<f64> {
-        if let TransformEntryItem::Arg(s) = self { Some(*s) } else { None }
-    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -64,34 +72,62 @@ pub enum TransformEntry {
     List(Vec<TransformEntryItem>),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(deny_unknown_fields)]
 pub struct ShapeEntry {
     add: String,
     material: Option<MatEntry>,
     transform: Option<Vec<TransformEntry>>,
-    min: Option<f64>,
-    max: Option<f64>,
+    min: Option<Num>,
+    max: Option<Num>,
     closed: Option<bool>,
+    #[serde(rename = "inner-radius", alias = "inner_radius")]
+    inner_radius: Option<Num>,
+    #[serde(rename = "outer-radius", alias = "outer_radius")]
+    outer_radius: Option<Num>,
     children: Option<Vec<ShapeEntry>>,
     shadow: Option<bool>,
+    operation: Option<String>,
+    left: Option<Box<ShapeEntry>>,
+    right: Option<Box<ShapeEntry>>,
+    p1: Option<Tup>,
+    p2: Option<Tup>,
+    p3: Option<Tup>,
+    n1: Option<Tup>,
+    n2: Option<Tup>,
+    n3: Option<Tup>,
+    uvs: Option<Vec<[Num; 2]>>,
+    file: Option<String>,
+    // Lathes, as (radius, y) points.
+    profile: Option<Vec<[Num; 2]>>,
+    coefficients: Option<Vec<Num>>,
+    #[serde(rename = "clip-min", alias = "clip_min")]
+    clip_min: Option<Tup>,
+    #[serde(rename = "clip-max", alias = "clip_max")]
+    clip_max: Option<Tup>,
+    width: Option<usize>,
+    depth: Option<usize>,
+    heights: Option<Vec<Num>>,
+    #[serde(rename = "control-points", alias = "control_points")]
+    control_points: Option<Vec<Tup>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 #[serde(tag = "add")]
 pub enum AddEntry {
-    #[serde(alias = "light")]
+    #[serde(rename = "light", alias = "Light")]
     Light {
         at: Tup,
         intensity: Tup,
     },
-    #[serde(alias = "camera")]
+    #[serde(rename = "camera", alias = "Camera")]
     Camera {
-        width: usize,
-        height: usize,
-        #[serde(alias = "field-of-view")]
-        field_of_view: f64,
+        name: Option<String>,
+        width: Num,
+        height: Num,
+        #[serde(rename = "field-of-view", alias = "field_of_view")]
+        field_of_view: Num,
         from: Tup,
         to: Tup,
         up: Tup,
@@ -102,9 +138,9 @@ pub enum AddEntry {
 #[serde(deny_unknown_fields)]
 #[serde(untagged)]
 pub enum DefineEntryValue {
-    Mat(Mat),
+    Mat(Box<Mat>),
     Transform(Vec<TransformEntry>),
-    Shape(ShapeEntry)
+    Shape(Box<ShapeEntry>)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -119,172 +155,838 @@ pub struct DefineEntry {
 #[serde(deny_unknown_fields)]
 #[serde(untagged)]
 pub enum Entry {
-    AddEntry(AddEntry),
+    AddEntry(Box<AddEntry>),
     DefineEntry(DefineEntry),
-    ShapeEntry(ShapeEntry),
+    ShapeEntry(Box<ShapeEntry>),
 }
 
-fn to_point(p: &Tup) -> Tuple { point(p[0], p[1], p[2]) }
-fn to_vector(p: &Tup) -> Tuple { vector(p[0], p[1], p[2]) }
-fn to_color(p: &Tup) -> Color { color(p[0], p[1], p[2]) }
-fn to_material(m: &MatEntry, base: &Material, mdef: &HashMap<&str, Material>, tdef: &HashMap<&str, Matrix>) -> Material {
+/// A problem with a scene file. `path` is where in the YAML it is, like `[3].material.pattern` for
+/// the pattern of the fourth entry, and `line` is the line that entry starts on. Line 0 means it's
+/// not known, and an empty path means the problem is with the file as a whole. `file` is the
+/// included file it's in, or empty for the scene itself.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SceneError {
+    pub file: String,
+    pub path: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}: ", self.file)?;
+        }
+        if self.line != 0 {
+            write!(f, "line {}, ", self.line)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for SceneError {}
+
+fn error<T>(path: &str, message: String) -> Result<T, SceneError> {
+    Err(SceneError { file: String::new(), path: path.to_owned(), line: 0, message })
+}
+
+fn to_material(m: &MatEntry, base: &Material, ctx: &Context, path: &str) -> Result<Material, SceneError> {
     match m {
-        MatEntry::Name(name) => *mdef.get(name.as_str()).unwrap(),
+        MatEntry::Name(name) => match ctx.materials.get(name) {
+            Some(m) => Ok(*m),
+            None => error(path, format!("undefined material {name:?}")),
+        },
         MatEntry::Mat(mat) => {
             let mut m = *base;
-            if let Some(x) = mat.color { m.color = to_color(&x); };
-            if let Some(x) = mat.ambient { m.ambient = x; };
-            if let Some(x) = mat.diffuse { m.diffuse = x; };
-            if let Some(x) = mat.specular { m.specular = x; };
-            if let Some(x) = mat.shininess { m.shininess = x; };
-            if let Some(x) = mat.reflective { m.reflective = x; };
-            if let Some(x) = mat.transparency { m.transparency = x; };
-            if let Some(x) = mat.refractive_index { m.refractive_index = x; };
+            let num = |x: &Option<Num>, field: &str| x.as_ref().map(|x| ctx.num(x, &format!("{path}.{field}"))).transpose();
+            if let Some(x) = &mat.color { m.color = ctx.color(x, &format!("{path}.color"))?; };
+            if let Some(x) = num(&mat.ambient, "ambient")? { m.ambient = x; };
+            if let Some(x) = num(&mat.diffuse, "diffuse")? { m.diffuse = x; };
+            if let Some(x) = num(&mat.specular, "specular")? { m.specular = x; };
+            if let Some(x) = num(&mat.shininess, "shininess")? { m.shininess = x; };
+            if let Some(x) = num(&mat.reflective, "reflective")? { m.reflective = x; };
+            if let Some(x) = num(&mat.transparency, "transparency")? { m.transparency = x; };
+            if let Some(x) = num(&mat.refractive_index, "refractive-index")? { m.refractive_index = x; };
             if let Some(p) = &mat.pattern {
-                let func = match p.type_.as_str() {
-                    "checkers" => checkers_pattern,
-                    "stripes" => stripe_pattern,
-                    _ => panic!(),
-                };
-                let mut pattern = func(&to_color(&p.colors[0]), &to_color(&p.colors[1]));
-                if let Some(ts) = &p.transform {
-                    pattern.set_transform(&to_transform(&ts, tdef));
-                }
-                m.pattern = Some(pattern);
+                m.pattern = Some(to_pattern(p, ctx, &format!("{path}.pattern"), true)?);
             }
-            m
+            Ok(m)
         }
     }
 }
 
-fn to_transform(ts: &Vec<TransformEntry>, tdef: &HashMap<&str, Matrix>) -> Matrix {
+fn to_pattern(p: &Pat, ctx: &Context, path: &str, can_nest: bool) -> Result<Pattern, SceneError> {
+    let func = match p.type_.as_str() {
+        "checkers" => checkers_pattern,
+        "stripes" => stripe_pattern,
+        "gradient" => gradient_pattern,
+        "rings" | "ring" => ring_pattern,
+        t => return error(path, format!("unknown pattern type {t:?}, expected checkers, stripes, gradient or rings")),
+    };
+    let mut pattern = match &p.patterns {
+        Some(_) if !p.colors.is_empty() => return error(path, "patterns have either colors or patterns, not both".to_owned()),
+        Some(_) if !can_nest => return error(path, "patterns can only be nested one level deep".to_owned()),
+        Some(ps) if ps.len() == 2 => {
+            let a = to_pattern(&ps[0], ctx, &format!("{path}.patterns[0]"), false)?;
+            let b = to_pattern(&ps[1], ctx, &format!("{path}.patterns[1]"), false)?;
+            nested_pattern(&func(&BLACK, &BLACK), &a, &b)
+        }
+        Some(ps) => return error(path, format!("patterns need 2 patterns, got {}", ps.len())),
+        None if p.colors.len() == 2 => func(&ctx.color(&p.colors[0], &format!("{path}.colors[0]"))?, &ctx.color(&p.colors[1], &format!("{path}.colors[1]"))?),
+        None => return error(path, format!("patterns need 2 colors, got {}", p.colors.len())),
+    };
+    if let Some(ts) = &p.transform {
+        pattern.set_transform(&to_transform(ts, ctx, &format!("{path}.transform"))?);
+    }
+    Ok(pattern)
+}
+
+fn to_transform(ts: &[TransformEntry], ctx: &Context, path: &str) -> Result<Matrix, SceneError> {
     let mut m = identity_matrix;
-    for t in ts {
+    for (i, t) in ts.iter().enumerate() {
+        let path = format!("{path}[{i}]");
         let tm = match t {
-            TransformEntry::Name(name) => *tdef.get(name.as_str()).unwrap(),
+            TransformEntry::Name(name) => match ctx.transforms.get(name) {
+                Some(m) => *m,
+                None => return error(&path, format!("undefined transform {name:?}")),
+            },
             TransformEntry::List(te) => {
-                let args: Vec<f64> = te[1..].into_iter().map(|a| a.as_arg().unwrap()).collect();
-                match te[0].as_op().unwrap() {
+                let Some(op) = te.first().and_then(|o| o.as_op()) else {
+                    return error(&path, "expected a list starting with an operation, like [translate, 1, 2, 3]".to_owned());
+                };
+                let args = te[1..].iter().map(|a| match a {
+                    TransformEntryItem::Arg(x) => Ok(*x),
+                    TransformEntryItem::Op(expr) => ctx.expression(expr, &path),
+                }).collect::<Result<Vec<f64>, SceneError>>()?;
+                let expected = match op {
+                    "translate" | "scale" => 3,
+                    "rotate-x" | "rotate-y" | "rotate-z" => 1,
+                    "shear" => 6,
+                    "matrix" => 16,
+                    _ => return error(&path, format!("unknown transform {op:?}, expected translate, scale, rotate-x, rotate-y, rotate-z, shear or matrix")),
+                };
+                if args.len() != expected {
+                    return error(&path, format!("{op} takes {expected} arguments, got {}", args.len()));
+                }
+                match op {
                     "translate" => translation(args[0], args[1], args[2]),
                     "scale" => scaling(args[0], args[1], args[2]),
                     "rotate-x" => rotation_x(args[0]),
                     "rotate-y" => rotation_y(args[0]),
                     "rotate-z" => rotation_z(args[0]),
-                    _ => panic!(),
+                    // Row by row.
+                    "matrix" => matrix4([0, 1, 2, 3].map(|r| [0, 1, 2, 3].map(|c| args[4 * r + c]))),
+                    _ => shearing(args[0], args[1], args[2], args[3], args[4], args[5]),
                 }
             },
         };
         m = tm * m;
     }
-    m
+    // Shapes and patterns invert their transforms, which asserts they can be.
+    if !m.is_invertible() {
+        return error(path, "transform can't be inverted, like a scale by 0".to_owned());
+    }
+    Ok(m)
+}
+
+fn required<T>(value: Option<T>, shape: &str, field: &str, path: &str) -> Result<T, SceneError> {
+    value.map_or_else(|| error(path, format!("{shape} is missing `{field}`")), Ok)
 }
 
-fn to_shape(se: &ShapeEntry, tdef: &HashMap<&str, Matrix>, mdef: &HashMap<&str, Material>, sdef: &HashMap<&str, Shape>) -> Shape {
+fn to_shape(se: &ShapeEntry, ctx: &Context, path: &str) -> Result<Shape, SceneError> {
+    let num = |x: &Option<Num>, shape: &str, field: &str| ctx.num(required(x.as_ref(), shape, field, path)?, &format!("{path}.{field}"));
+    let point = |x: &Option<Tup>, shape: &str, field: &str| ctx.point(required(x.as_ref(), shape, field, path)?, &format!("{path}.{field}"));
+    let vector = |x: &Option<Tup>, shape: &str, field: &str| ctx.vector(required(x.as_ref(), shape, field, path)?, &format!("{path}.{field}"));
+    let nums = |x: &Option<Vec<Num>>, shape: &str, field: &str| required(x.as_ref(), shape, field, path)?.iter().enumerate()
+        .map(|(i, n)| ctx.num(n, &format!("{path}.{field}[{i}]")))
+        .collect::<Result<Vec<f64>, SceneError>>();
+    let outer_radius = if se.outer_radius.is_some() { num(&se.outer_radius, &se.add, "outer-radius")? } else { 1. };
     let mut p = match se.add.as_str() {
         "plane" => plane(),
         "cube" => cube(),
         "sphere" => sphere(),
-        "cylinder" => {
-            let mut p = cylinder();
-            p.set_closed(&se.closed.unwrap());
-            p.set_minimum(&se.min.unwrap());
-            p.set_maximum(&se.max.unwrap());
-            p
+        "disk" | "annulus" => {
+            let name = se.add.as_str();
+            let inner_radius = if se.inner_radius.is_some() || name == "annulus" { num(&se.inner_radius, name, "inner-radius")? } else { 0. };
+            if !(0. <= inner_radius && inner_radius < outer_radius) {
+                return error(path, format!("{name} radii need 0 <= inner-radius < outer-radius, got {inner_radius} and {outer_radius}"));
+            }
+            annulus(inner_radius, outer_radius)
         }
-        "cone" => {
-            let mut p = cone();
-            p.set_closed(&se.closed.unwrap());
-            p.set_minimum(&se.min.unwrap());
-            p.set_maximum(&se.max.unwrap());
+        "quad" | "rectangle" => quad(),
+        "cylinder" | "cone" => {
+            let name = se.add.as_str();
+            let mut p = if name == "cylinder" { cylinder() } else { cone() };
+            p.set_closed(&required(se.closed, name, "closed", path)?);
+            p.set_minimum(&num(&se.min, name, "min")?);
+            p.set_maximum(&num(&se.max, name, "max")?);
             p
         }
+        "triangle" => {
+            let name = "triangle";
+            triangle(&point(&se.p1, name, "p1")?, &point(&se.p2, name, "p2")?, &point(&se.p3, name, "p3")?)
+        }
+        "smooth-triangle" => {
+            let name = "smooth-triangle";
+            smooth_triangle(
+                &point(&se.p1, name, "p1")?, &point(&se.p2, name, "p2")?, &point(&se.p3, name, "p3")?,
+                &vector(&se.n1, name, "n1")?, &vector(&se.n2, name, "n2")?, &vector(&se.n3, name, "n3")?,
+            )
+        }
+        "lathe" => {
+            let profile = required(se.profile.as_ref(), "lathe", "profile", path)?.iter().enumerate()
+                .map(|(i, [r, y])| Ok((ctx.num(r, &format!("{path}.profile[{i}]"))?, ctx.num(y, &format!("{path}.profile[{i}]"))?)))
+                .collect::<Result<Vec<(f64, f64)>, SceneError>>()?;
+            if profile.len() < 2 {
+                return error(path, format!("lathe profiles need at least 2 points, got {}", profile.len()));
+            }
+            lathe(&profile)
+        }
+        "quadric" => {
+            let Ok(coefficients) = <[f64; 10]>::try_from(nums(&se.coefficients, "quadric", "coefficients")?) else {
+                return error(path, "quadrics need 10 coefficients".to_owned());
+            };
+            let mut q = quadric(&coefficients);
+            let clip_min = if se.clip_min.is_some() { point(&se.clip_min, "quadric", "clip-min")? } else { q.as_quadric().unwrap().minimum };
+            let clip_max = if se.clip_max.is_some() { point(&se.clip_max, "quadric", "clip-max")? } else { q.as_quadric().unwrap().maximum };
+            q.set_clip(&clip_min, &clip_max);
+            q
+        }
+        "heightfield" => {
+            let (width, depth) = (required(se.width, "heightfield", "width", path)?, required(se.depth, "heightfield", "depth", path)?);
+            let heights = nums(&se.heights, "heightfield", "heights")?;
+            if width < 2 || depth < 2 {
+                return error(path, format!("heightfields need to be at least 2 by 2, got {width} by {depth}"));
+            }
+            if heights.len() != width * depth {
+                return error(path, format!("a {width} by {depth} heightfield needs {} heights, got {}", width * depth, heights.len()));
+            }
+            heightfield(&heights, width, depth)
+        }
+        "bezier-patch" => {
+            let points = required(se.control_points.as_ref(), "bezier-patch", "control-points", path)?.iter().enumerate()
+                .map(|(i, p)| ctx.point(p, &format!("{path}.control-points[{i}]")))
+                .collect::<Result<Vec<Tuple>, SceneError>>()?;
+            let Ok(points) = <[Tuple; 16]>::try_from(points) else {
+                return error(path, "bezier patches need 16 control points".to_owned());
+            };
+            bezier_patch(&points)
+        }
+        "csg" => {
+            let op = se.operation.as_deref().map_or_else(|| error(path, "csg is missing `operation`".to_owned()), Ok)?;
+            if !matches!(op, "union" | "intersection" | "difference") {
+                return error(path, format!("unknown csg operation {op:?}, expected union, intersection or difference"));
+            }
+            let (Some(left), Some(right)) = (&se.left, &se.right) else {
+                return error(path, "csg needs `left` and `right` shapes".to_owned());
+            };
+            csg(op, &to_shape(left, ctx, &format!("{path}.left"))?, &to_shape(right, ctx, &format!("{path}.right"))?)
+        }
+        "obj" => {
+            let file = se.file.as_deref().map_or_else(|| error(path, "obj is missing `file`".to_owned()), Ok)?;
+            let parser = load_obj_file(&ctx.dir.join(file)).or_else(|e| error(path, format!("can't load {file}: {e}")))?;
+            obj_to_group(&parser)
+        }
         "group" => {
             let mut g = group();
             if let Some(children) = &se.children {
-                for child_se in children {
-                    let mut s = to_shape(&child_se, tdef, mdef, sdef);
-                    add_child(&mut g, &mut s)
+                for (i, child_se) in children.iter().enumerate() {
+                    let s = to_shape(child_se, ctx, &format!("{path}.children[{i}]"))?;
+                    add_child(&mut g, &s)
                 }
             }
             g
         }
-        _ => sdef.get(se.add.as_str()).unwrap().clone(),
+        name => match ctx.shapes.get(name) {
+            Some(s) => s.clone(),
+            None => return error(path, format!("unknown shape {name:?}")),
+        },
     };
-    fill_shape(&mut p, se, &tdef, &mdef);
-    p
+    fill_shape(&mut p, se, ctx, path)?;
+    Ok(p)
 }
 
-fn fill_shape(shape: &mut Shape, se: &ShapeEntry, tdef: &HashMap<&str, Matrix>, mdef: &HashMap<&str, Material>) {
+fn fill_shape(shape: &mut Shape, se: &ShapeEntry, ctx: &Context, path: &str) -> Result<(), SceneError> {
     if let Some(t) = &se.transform {
-        shape.set_transform(&to_transform(t, tdef));
+        shape.set_transform(&to_transform(t, ctx, &format!("{path}.transform"))?);
     }
     if let Some(m) = &se.material {
-        shape.material = to_material(m, &DEFAULT_MATERIAL, mdef, tdef);
+        shape.material = to_material(m, &DEFAULT_MATERIAL, ctx, &format!("{path}.material"))?;
     }
     if let Some(s) = se.shadow {
         shape.shadow = s;
     }
+    if let Some(uvs) = &se.uvs {
+        if !matches!(shape.shape_type, ShapeType::Triangle(_) | ShapeType::SmoothTriangle(_)) {
+            return error(path, "only triangles have uvs".to_owned());
+        }
+        let uvs = uvs.iter().enumerate()
+            .map(|(i, [u, v])| Ok((ctx.num(u, &format!("{path}.uvs[{i}]"))?, ctx.num(v, &format!("{path}.uvs[{i}]"))?)))
+            .collect::<Result<Vec<(f64, f64)>, SceneError>>()?;
+        let Ok(uvs) = <[(f64, f64); 3]>::try_from(uvs) else {
+            return error(path, "triangles need 3 uvs".to_owned());
+        };
+        shape.set_uvs(&uvs);
+    }
+    Ok(())
+}
+
+// Untagged enums only say that nothing matched when they fail, so we try again as the kind of
+// entry it looks like to say what's wrong with it.
+fn parse_entry(v: &Value) -> Result<Entry, String> {
+    serde_yaml::from_value(v.clone()).map_err(|e| {
+        let specific = if let Some(value) = v.get("define").and(v.get("value")) {
+            match value {
+                Value::Sequence(_) => serde_yaml::from_value::<Vec<TransformEntry>>(value.clone()).err(),
+                _ if value.get("add").is_some() => serde_yaml::from_value::<ShapeEntry>(value.clone()).err(),
+                _ => serde_yaml::from_value::<Mat>(value.clone()).err(),
+            }
+        } else if v.get("define").is_some() {
+            serde_yaml::from_value::<DefineEntry>(v.clone()).err()
+        } else if matches!(v.get("add").and_then(|a| a.as_str()), Some("light" | "camera")) {
+            serde_yaml::from_value::<AddEntry>(v.clone()).err()
+        } else {
+            serde_yaml::from_value::<ShapeEntry>(v.clone()).err()
+        };
+        specific.unwrap_or(e).to_string()
+    })
+}
+
+// The lines top-level entries start on, if they're written as a block sequence.
+fn entry_lines(yaml: &str, count: usize) -> Vec<usize> {
+    let lines: Vec<usize> = yaml.lines().enumerate()
+        .filter(|(_, l)| l.starts_with('-') && !l.starts_with("---") && (l.len() == 1 || l[1..].starts_with(char::is_whitespace)))
+        .map(|(i, _)| i + 1)
+        .collect();
+    if lines.len() == count { lines } else { vec![0; count] }
+}
+
+// Parameters go in their own entries, and their values can use the ones before them.
+fn add_parameters(v: &Value, ctx: &mut Context, path: &str) -> Result<(), SceneError> {
+    let Some(parameters) = v.as_mapping() else {
+        return error(path, "parameters should be a map of names to values".to_owned());
+    };
+    for (name, value) in parameters {
+        let Some(name) = name.as_str() else {
+            return error(path, "parameter names should be strings".to_owned());
+        };
+        if !is_variable_name(name) {
+            return error(path, format!("invalid parameter name {name:?}"));
+        }
+        let path = format!("{path}.{name}");
+        let x = match (ctx.overrides.get(name), value) {
+            (Some(x), _) => *x,
+            (None, Value::Number(n)) => n.as_f64().unwrap_or(f64::NAN),
+            (None, Value::String(expr)) => ctx.expression(expr, &path)?,
+            _ => return error(&path, "parameters should be numbers or expressions".to_owned()),
+        };
+        ctx.parameters.insert(name.to_owned(), x);
+    }
+    Ok(())
 }
 
-pub fn load(path: &str) -> Result<Canvas, Box<dyn Error>> {
-    let bytes = fs::read(path)?;
-    let yaml = std::str::from_utf8(&bytes)?;
-    let res: Vec<Entry> = serde_yaml::from_str(&yaml)?;
+fn add_include(v: &Value, scene: &mut Scene, ctx: &mut Context, path: &str) -> Result<(), SceneError> {
+    let Some(name) = v.as_str() else {
+        return error(path, "include should be the name of a file".to_owned());
+    };
+    let file = ctx.dir.join(name);
+    let label = file.display().to_string();
+    let canonical = fs::canonicalize(&file).or_else(|e| error(path, format!("can't read {label}: {e}")))?;
+    if let Some(i) = ctx.files.iter().position(|(f, _)| *f == canonical) {
+        let cycle: Vec<&str> = ctx.files[i..].iter().map(|(_, l)| l.as_str()).chain([label.as_str()]).collect();
+        return error(path, format!("include cycle: {}", cycle.join(" -> ")));
+    }
+    let yaml = fs::read_to_string(&file).or_else(|e| error(path, format!("can't read {label}: {e}")))?;
+    let dir = file.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+    let outer_dir = std::mem::replace(&mut ctx.dir, dir);
+    ctx.files.push((canonical, label.clone()));
+    let added = add_entries(&yaml, &label, scene, ctx);
+    ctx.files.pop();
+    ctx.dir = outer_dir;
+    added
+}
+
+// Adds the entries of a scene file to the scene. `file` is what errors in it say they're in.
+fn add_entries(yaml: &str, file: &str, scene: &mut Scene, ctx: &mut Context) -> Result<(), SceneError> {
+    let entries: Vec<Value> = serde_yaml::from_str(yaml).map_err(|e| SceneError {
+        file: file.to_owned(),
+        path: String::new(),
+        line: e.location().map_or(0, |l| l.line()),
+        message: e.to_string(),
+    })?;
+    let lines = entry_lines(yaml, entries.len());
+    for (i, v) in entries.iter().enumerate() {
+        let path = format!("[{i}]");
+        let added = if let Some(include) = v.get("include") {
+            add_include(include, scene, ctx, &path)
+        } else if let Some(parameters) = v.get("parameters") {
+            add_parameters(parameters, ctx, &path)
+        } else {
+            parse_entry(v)
+                .or_else(|message| error(&path, message))
+                .and_then(|e| add_entry(&e, &path, scene, ctx))
+        };
+        // Errors in included files already say where they are.
+        added.map_err(|e| if e.file.is_empty() && e.line == 0 { SceneError { file: file.to_owned(), line: lines[i], ..e } } else { e })?;
+    }
+    Ok(())
+}
+
+// Builds the world described by a scene, and the cameras it can be viewed from. Files it refers
+// to are relative to `dir`, and `file` is the scene's own file, if it has one.
+fn parse_scene(yaml: &str, file: Option<&Path>, dir: &Path, overrides: &HashMap<String, f64>) -> Result<Scene, SceneError> {
+    let files = file.into_iter().filter_map(|f| Some((fs::canonicalize(f).ok()?, f.display().to_string()))).collect();
+    let mut scene = Scene { world: world(), cameras: vec![], bvh_stats: vec![] };
+    let mut ctx = Context {
+        transforms: HashMap::new(),
+        materials: HashMap::new(),
+        shapes: HashMap::new(),
+        parameters: HashMap::new(),
+        overrides: overrides.clone(),
+        files,
+        dir: dir.to_path_buf(),
+    };
+    add_entries(yaml, "", &mut scene, &mut ctx)?;
+    let mut unknown: Vec<&String> = overrides.keys().filter(|k| !ctx.parameters.contains_key(*k)).collect();
+    unknown.sort();
+    if let Some(name) = unknown.first() {
+        return error("", format!("the scene has no parameter called {name:?}"));
+    }
+    Ok(scene)
+}
+
+// What's been defined so far, and where files are.
+struct Context {
+    transforms: HashMap<String, Matrix>,
+    materials: HashMap<String, Material>,
+    shapes: HashMap<String, Shape>,
+    parameters: HashMap<String, f64>,
+    // Values for parameters from outside the scene, which win over the scene's own.
+    overrides: HashMap<String, f64>,
+    // The files being read, and how to refer to them, to find includes that go round in circles.
+    files: Vec<(PathBuf, String)>,
+    dir: PathBuf,
+}
+
+impl Context {
+    fn expression(&self, expr: &str, path: &str) -> Result<f64, SceneError> {
+        evaluate(expr, &self.parameters).or_else(|e| error(path, format!("can't evaluate {expr:?}: {e}")))
+    }
+
+    fn num(&self, n: &Num, path: &str) -> Result<f64, SceneError> {
+        match n {
+            Num::Value(x) => Ok(*x),
+            Num::Expr(expr) => self.expression(expr, path),
+        }
+    }
+
+    fn tup(&self, t: &Tup, path: &str) -> Result<[f64; 3], SceneError> {
+        Ok([self.num(&t[0], path)?, self.num(&t[1], path)?, self.num(&t[2], path)?])
+    }
+
+    fn point(&self, t: &Tup, path: &str) -> Result<Tuple, SceneError> {
+        let [x, y, z] = self.tup(t, path)?;
+        Ok(point(x, y, z))
+    }
 
-    let mut w = world();
-    let mut c = None;
-    let mut g = group();
+    fn vector(&self, t: &Tup, path: &str) -> Result<Tuple, SceneError> {
+        let [x, y, z] = self.tup(t, path)?;
+        Ok(vector(x, y, z))
+    }
 
-    let mut tdef = HashMap::new();
-    let mut mdef: HashMap<&str, Material> = HashMap::new();
-    let mut sdef: HashMap<&str, Shape> = HashMap::new();
+    fn color(&self, t: &Tup, path: &str) -> Result<Color, SceneError> {
+        let [r, g, b] = self.tup(t, path)?;
+        Ok(color(r, g, b))
+    }
 
-    for e in &res {
-        match &e {
-            Entry::AddEntry(ae) => {
-                match ae {
-                    AddEntry::Light { at, intensity } => {
-                        w.add_light(&point_light(&to_point(&at), &to_color(&intensity)))
+    // Image sizes are whole numbers of pixels.
+    fn size(&self, n: &Num, path: &str) -> Result<f64, SceneError> {
+        let x = self.num(n, path)?;
+        if x < 1. || x.fract() != 0. {
+            return error(path, format!("{x} isn't a whole number of pixels"));
+        }
+        Ok(x)
+    }
+}
+
+fn add_entry(e: &Entry, path: &str, scene: &mut Scene, ctx: &mut Context) -> Result<(), SceneError> {
+    match &e {
+        Entry::AddEntry(ae) => {
+            match &**ae {
+                AddEntry::Light { at, intensity } => {
+                    let light = point_light(&ctx.point(at, &format!("{path}.at"))?, &ctx.color(intensity, &format!("{path}.intensity"))?);
+                    scene.world.add_light(&light)
+                }
+                AddEntry::Camera { name, width, height, field_of_view, from, to, up } => {
+                    if name.is_some() && scene.cameras.iter().any(|c| c.name == *name) {
+                        return error(&format!("{path}.name"), format!("there's already a camera called {:?}", name.as_deref().unwrap_or("")));
+                    }
+                    let (width, height) = (ctx.size(width, &format!("{path}.width"))?, ctx.size(height, &format!("{path}.height"))?);
+                    let field_of_view = ctx.num(field_of_view, &format!("{path}.field-of-view"))?;
+                    if !(field_of_view > 0. && field_of_view < PI) {
+                        return error(&format!("{path}.field-of-view"), format!("field of view needs to be between 0 and pi, got {field_of_view}"));
+                    }
+                    let mut camera = camera(width, height, field_of_view);
+                    let (from, to, up) = (
+                        ctx.point(from, &format!("{path}.from"))?,
+                        ctx.point(to, &format!("{path}.to"))?,
+                        ctx.vector(up, &format!("{path}.up"))?,
+                    );
+                    // Otherwise the view can't be inverted, or is NaN, and rendering panics.
+                    if [from, to, up].iter().any(|t| ![t.x, t.y, t.z].iter().all(|x| x.is_finite())) {
+                        return error(path, "camera `from`, `to` and `up` need to be finite".to_owned());
                     }
-                    AddEntry::Camera { width, height, field_of_view, from, to, up } => {
-                        let mut camera = camera(*width as f64, *height as f64, *field_of_view);
-                        let t = view_transform(
-                            &to_point(from),
-                            &to_point(to),
-                            &to_vector(up),
-                        );
-                        camera.set_transform(&t);
-                        c = Some(camera);
+                    if (to - from).magnitude() == 0. {
+                        return error(&format!("{path}.to"), "the camera needs to look at a point other than `from`".to_owned());
                     }
+                    if (to - from).cross(&up).magnitude() == 0. {
+                        return error(&format!("{path}.up"), "camera `up` can't be zero or along the direction it looks".to_owned());
+                    }
+                    let t = view_transform(&from, &to, &up);
+                    camera.set_transform(&t);
+                    camera.name = name.clone();
+                    scene.cameras.push(camera);
                 }
             }
-            Entry::ShapeEntry(se) => {
-                let mut p = to_shape(se, &tdef, &mdef, &sdef);
-                add_child(&mut g, &mut p);
+        }
+        Entry::ShapeEntry(se) => {
+            let mut p = to_shape(se, ctx, path)?;
+            let stats = p.freeze_and_optimize();
+            if stats.nodes > 0 {
+                scene.bvh_stats.push(stats);
             }
-            Entry::DefineEntry(de) => {
-                match &de.value {
-                    DefineEntryValue::Mat(mat) => {
-                        let base = if let Some(e) = &de.extend { mdef.get(e.as_str()).unwrap() } else { &DEFAULT_MATERIAL };
-                        let m = to_material(&MatEntry::Mat(mat.clone()), base, &mdef, &tdef);
-                        mdef.insert(de.define.as_str(), m);
-                    },
-                    DefineEntryValue::Transform(ts) => {
-                        assert!(de.extend.is_none());
-                        tdef.insert(de.define.as_str(), to_transform(ts, &tdef));
-                    },
-                    DefineEntryValue::Shape(se) => {
-                        assert!(de.extend.is_none());
-                        sdef.insert(de.define.as_str(), to_shape(se, &tdef, &mdef, &sdef));
-                    },
-                }
+            scene.world.add(&p);
+        }
+        Entry::DefineEntry(de) => {
+            let value_path = format!("{path}.value");
+            match &de.value {
+                DefineEntryValue::Mat(mat) => {
+                    let base = match &de.extend {
+                        Some(e) => match ctx.materials.get(e) {
+                            Some(m) => *m,
+                            None => return error(&format!("{path}.extend"), format!("undefined material {e:?}")),
+                        },
+                        None => DEFAULT_MATERIAL,
+                    };
+                    let m = to_material(&MatEntry::Mat(mat.clone()), &base, ctx, &value_path)?;
+                    ctx.materials.insert(de.define.clone(), m);
+                },
+                _ if de.extend.is_some() => return error(&format!("{path}.extend"), "only materials can extend another definition".to_owned()),
+                DefineEntryValue::Transform(ts) => {
+                    let t = to_transform(ts, ctx, &value_path)?;
+                    ctx.transforms.insert(de.define.clone(), t);
+                },
+                DefineEntryValue::Shape(se) => {
+                    let s = to_shape(se, ctx, &value_path)?;
+                    ctx.shapes.insert(de.define.clone(), s);
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A scene read from YAML, with its world and the cameras it can be viewed from, in the order
+/// they're added.
+pub struct Scene {
+    pub world: World,
+    pub cameras: Vec<Camera>,
+    // From building the BVHs of the groups added, in order.
+    pub bvh_stats: Vec<BVHStats>,
+}
+
+impl Scene {
+    /// Parses a scene, where files it refers to are relative to the current directory.
+    pub fn from_yaml_str(yaml: &str) -> Result<Scene, SceneError> {
+        Scene::from_yaml_str_with_parameters(yaml, &HashMap::new())
+    }
+
+    /// Loads a scene, where files it refers to are relative to the scene file.
+    pub fn from_path(path: &Path) -> Result<Scene, SceneError> {
+        Scene::from_path_with_parameters(path, &HashMap::new())
+    }
+
+    /// Parses a scene, with `parameters` instead of the values the scene gives them. They must
+    /// all be parameters the scene has.
+    pub fn from_yaml_str_with_parameters(yaml: &str, parameters: &HashMap<String, f64>) -> Result<Scene, SceneError> {
+        parse_scene(yaml, None, Path::new("."), parameters)
+    }
+
+    pub fn from_path_with_parameters(path: &Path, parameters: &HashMap<String, f64>) -> Result<Scene, SceneError> {
+        let yaml = fs::read_to_string(path).or_else(|e| error("", format!("can't read {}: {e}", path.display())))?;
+        parse_scene(&yaml, Some(path), path.parent().unwrap_or(Path::new(".")), parameters)
+    }
+
+    /// The camera added first, which is the one the scene is rendered with.
+    pub fn camera(&self) -> Result<&Camera, SceneError> {
+        self.cameras.first().map_or_else(|| error("", "scene has no camera".to_owned()), Ok)
+    }
+
+    /// The camera called `name`, or failing that, the one at that index, counting from 0.
+    pub fn find_camera(&self, name: &str) -> Result<&Camera, SceneError> {
+        let named = self.cameras.iter().find(|c| c.name.as_deref() == Some(name));
+        match named.or_else(|| name.parse().ok().and_then(|i: usize| self.cameras.get(i))) {
+            Some(c) => Ok(c),
+            None if self.cameras.is_empty() => error("", "scene has no camera".to_owned()),
+            None => error("", format!("no camera called {name:?}, expected one of {}", self.camera_labels().join(", "))),
+        }
+    }
+
+    /// What to call each camera, which is its name, or its index if it hasn't got one.
+    pub fn camera_labels(&self) -> Vec<String> {
+        self.cameras.iter().enumerate().map(|(i, c)| c.name.clone().unwrap_or_else(|| i.to_string())).collect()
+    }
+
+    pub fn render(&self) -> Result<Canvas, SceneError> {
+        Ok(render(self.camera()?, &self.world))
+    }
+
+    pub fn render_camera(&self, name: &str, options: &RenderOptions) -> Result<Canvas, SceneError> {
+        Ok(render_with(self.find_camera(name)?, &self.world, options))
+    }
+
+    /// Renders the view from every camera, with their labels.
+    pub fn render_all(&self, options: &RenderOptions) -> Result<Vec<(String, Canvas)>, SceneError> {
+        self.camera()?;
+        Ok(self.camera_labels().into_iter().zip(&self.cameras).map(|(label, c)| (label, render_with(c, &self.world, options))).collect())
+    }
+
+    pub fn to_yaml(&self) -> Result<String, SceneError> {
+        scene_to_yaml(&self.world, &self.cameras)
+    }
+}
+
+fn from_tuple(x: f64, y: f64, z: f64) -> Tup {
+    [Num::Value(x), Num::Value(y), Num::Value(z)]
+}
+
+fn from_color(c: &Color) -> Tup {
+    from_tuple(c.red, c.green, c.blue)
+}
+
+fn from_point(p: &Tuple) -> Tup {
+    from_tuple(p.x, p.y, p.z)
+}
+
+// The simplest list of operations making the matrix, or nothing for the identity. Exact
+// comparisons, as anything else would change the scene.
+fn from_transform(m: &Matrix) -> Option<Vec<TransformEntry>> {
+    let op = |name: &str, args: &[f64]| TransformEntry::List(
+        [TransformEntryItem::Op(name.to_owned())].into_iter().chain(args.iter().map(|x| TransformEntryItem::Arg(*x))).collect()
+    );
+    let affine = m[(3, 0)] == 0. && m[(3, 1)] == 0. && m[(3, 2)] == 0. && m[(3, 3)] == 1.;
+    let diagonal = (0..3).all(|r| (0..3).all(|c| r == c || m[(r, c)] == 0.));
+    let (scale, translate) = ([m[(0, 0)], m[(1, 1)], m[(2, 2)]], [m[(0, 3)], m[(1, 3)], m[(2, 3)]]);
+    let mut ops = vec![];
+    if affine && diagonal {
+        if scale != [1.; 3] {
+            ops.push(op("scale", &scale));
+        }
+        if translate != [0.; 3] {
+            ops.push(op("translate", &translate));
+        }
+    } else {
+        ops.push(op("matrix", &(0..16).map(|i| m[(i / 4, i % 4)]).collect::<Vec<f64>>()));
+    }
+    (!ops.is_empty()).then_some(ops)
+}
+
+fn pattern_type_name(t: PatternType) -> Option<&'static str> {
+    match t {
+        PatternType::Stripe => Some("stripes"),
+        PatternType::Checkers => Some("checkers"),
+        PatternType::Gradient => Some("gradient"),
+        PatternType::Ring => Some("rings"),
+        PatternType::None | PatternType::TestPattern => None,
+    }
+}
+
+fn from_pattern(p: &Pattern) -> Option<Pat> {
+    let (colors, patterns) = match &p.nested {
+        Some(nested) => (vec![], Some(nested.iter().map(|n| Some(Pat {
+            type_: pattern_type_name(n.pattern_type)?.to_owned(),
+            colors: vec![from_color(&n.a), from_color(&n.b)],
+            patterns: None,
+            transform: from_transform(&n.transform()),
+        })).collect::<Option<Vec<Pat>>>()?)),
+        None => (vec![from_color(&p.a), from_color(&p.b)], None),
+    };
+    Some(Pat { type_: pattern_type_name(p.pattern_type)?.to_owned(), colors, patterns, transform: from_transform(&p.transform()) })
+}
+
+// Only what's different from the default material, which is what scenes start from.
+fn from_material(m: &Material) -> Option<MatEntry> {
+    let d = DEFAULT_MATERIAL;
+    let num = |x: f64, default: f64| (x != default).then_some(Num::Value(x));
+    let same_color = (m.color.red, m.color.green, m.color.blue) == (d.color.red, d.color.green, d.color.blue);
+    let mat = Mat {
+        pattern: m.pattern.as_ref().and_then(from_pattern).map(Box::new),
+        color: (!same_color).then(|| from_color(&m.color)),
+        ambient: num(m.ambient, d.ambient),
+        diffuse: num(m.diffuse, d.diffuse),
+        specular: num(m.specular, d.specular),
+        shininess: num(m.shininess, d.shininess),
+        reflective: num(m.reflective, d.reflective),
+        transparency: num(m.transparency, d.transparency),
+        refractive_index: num(m.refractive_index, d.refractive_index),
+    };
+    let numbers = [&mat.ambient, &mat.diffuse, &mat.specular, &mat.shininess, &mat.reflective, &mat.transparency, &mat.refractive_index];
+    let changed = mat.pattern.is_some() || mat.color.is_some() || numbers.iter().any(|x| x.is_some());
+    changed.then(|| MatEntry::Mat(Box::new(mat)))
+}
+
+fn from_uvs(uvs: Option<[(f64, f64); 3]>) -> Option<Vec<[Num; 2]>> {
+    uvs.map(|uvs| uvs.iter().map(|(u, v)| [Num::Value(*u), Num::Value(*v)]).collect())
+}
+
+// Test shapes have nothing to write.
+fn from_shape(s: &Shape, path: &str) -> Result<Option<ShapeEntry>, SceneError> {
+    let entry = |add: &str| ShapeEntry { add: add.to_owned(), ..ShapeEntry::default() };
+    let mut se = match &s.shape_type {
+        ShapeType::Sphere(_) => entry("sphere"),
+        ShapeType::Plane(_) => entry("plane"),
+        ShapeType::Cube(_) => entry("cube"),
+        ShapeType::Quad(_) => entry("quad"),
+        ShapeType::Cylinder(_) | ShapeType::Cone(_) => ShapeEntry {
+            min: Some(Num::Value(s.minimum())),
+            max: Some(Num::Value(s.maximum())),
+            closed: Some(s.closed()),
+            ..entry(if s.as_cone().is_some() { "cone" } else { "cylinder" })
+        },
+        ShapeType::Disk(d) => ShapeEntry {
+            inner_radius: (d.inner_radius != 0.).then_some(Num::Value(d.inner_radius)),
+            outer_radius: (d.outer_radius != 1.).then_some(Num::Value(d.outer_radius)),
+            ..entry(if d.inner_radius == 0. { "disk" } else { "annulus" })
+        },
+        ShapeType::Group(_) => {
+            let children = s.children().iter().enumerate()
+                .map(|(i, c)| from_shape(c, &format!("{path}.children[{i}]")))
+                .collect::<Result<Vec<Option<ShapeEntry>>, SceneError>>()?;
+            ShapeEntry { children: Some(children.into_iter().flatten().collect()), ..entry("group") }
+        }
+        ShapeType::Triangle(_) => ShapeEntry {
+            p1: Some(from_point(&s.p1())),
+            p2: Some(from_point(&s.p2())),
+            p3: Some(from_point(&s.p3())),
+            uvs: from_uvs(s.uvs()),
+            ..entry("triangle")
+        },
+        ShapeType::SmoothTriangle(t) => ShapeEntry {
+            p1: Some(from_point(&t.p1)),
+            p2: Some(from_point(&t.p2)),
+            p3: Some(from_point(&t.p3)),
+            n1: Some(from_point(&t.n1)),
+            n2: Some(from_point(&t.n2)),
+            n3: Some(from_point(&t.n3)),
+            uvs: from_uvs(t.uvs),
+            ..entry("smooth-triangle")
+        },
+        ShapeType::CSG(_) => {
+            let left = from_shape(&s.left(), &format!("{path}.left"))?;
+            let right = from_shape(&s.right(), &format!("{path}.right"))?;
+            let (Some(left), Some(right)) = (left, right) else { return Ok(None) };
+            ShapeEntry { operation: Some(s.operation()), left: Some(Box::new(left)), right: Some(Box::new(right)), ..entry("csg") }
+        }
+        ShapeType::Lathe(l) => ShapeEntry {
+            profile: Some(l.profile.iter().map(|(r, y)| [Num::Value(*r), Num::Value(*y)]).collect()),
+            ..entry("lathe")
+        },
+        ShapeType::Quadric(q) => {
+            let finite = |p: &Tuple| [p.x, p.y, p.z].iter().any(|x| x.is_finite());
+            ShapeEntry {
+                coefficients: Some(q.coefficients.iter().map(|x| Num::Value(*x)).collect()),
+                clip_min: finite(&q.minimum).then(|| from_point(&q.minimum)),
+                clip_max: finite(&q.maximum).then(|| from_point(&q.maximum)),
+                ..entry("quadric")
             }
         }
+        ShapeType::Heightfield(h) => ShapeEntry {
+            width: Some(h.width),
+            depth: Some(h.depth),
+            heights: Some(h.heights.iter().map(|x| Num::Value(*x)).collect()),
+            ..entry("heightfield")
+        },
+        ShapeType::BezierPatch(b) => ShapeEntry {
+            control_points: Some(b.control_points.iter().map(from_point).collect()),
+            ..entry("bezier-patch")
+        },
+        // Scenes have no way to share shapes or to write distance functions, so writing these
+        // would lose what they are.
+        ShapeType::Instance(_) => return error(path, "instances can't be written as scene YAML".to_owned()),
+        ShapeType::ImplicitSurface(_) => return error(path, "implicit surfaces can't be written as scene YAML".to_owned()),
+        ShapeType::TestShape(_) => return Ok(None),
+    };
+    se.transform = from_transform(&s.local_transform());
+    se.material = from_material(&s.material);
+    se.shadow = (!s.shadow).then_some(false);
+    Ok(Some(se))
+}
+
+// The rows of a view transform are left, up and backwards. When `up` wasn't at right angles to
+// where the camera looks, left and up are shorter than 1, and we put back some of the forward
+// direction to get an `up` making the same matrix.
+fn from_camera(c: &Camera) -> AddEntry {
+    let m = c.transform();
+    let row = |r: usize| vector(m[(r, 0)], m[(r, 1)], m[(r, 2)]);
+    let from = c.inverse() * point(0., 0., 0.);
+    let forward = -row(2);
+    let up = row(1) + forward * (1. - row(0).magnitude().powi(2)).max(0.).sqrt();
+    AddEntry::Camera {
+        name: c.name.clone(),
+        width: Num::Value(c.hsize as f64),
+        height: Num::Value(c.vsize as f64),
+        field_of_view: Num::Value(c.field_of_view),
+        from: from_point(&from),
+        to: from_point(&(from + forward)),
+        up: from_point(&up),
+    }
+}
+
+// Fields that aren't set are left out, rather than written as null.
+fn remove_nulls(v: &mut Value) {
+    match v {
+        Value::Mapping(m) => {
+            m.retain(|_, x| !x.is_null());
+            m.values_mut().for_each(remove_nulls);
+        }
+        Value::Sequence(s) => s.iter_mut().for_each(remove_nulls),
+        _ => {}
     }
+}
+
+/// Writes a world and the cameras viewing it as scene YAML, which reads back as the same scene.
+/// Cameras are written with `from`, `to` and `up`, which can be off in the last digits.
+///
+/// Instances and implicit surfaces have no equivalent in scene files, so worlds with them are an
+/// error, with the path to the shape in `world.objects`.
+pub fn scene_to_yaml(world: &World, cameras: &[Camera]) -> Result<String, SceneError> {
+    let objects = world.objects.iter().enumerate()
+        .map(|(i, o)| from_shape(o, &format!("objects[{i}]")))
+        .collect::<Result<Vec<Option<ShapeEntry>>, SceneError>>()?;
+    let entries: Vec<Entry> = cameras.iter().map(|c| Entry::AddEntry(Box::new(from_camera(c))))
+        .chain(world.lights.iter().map(|l| Entry::AddEntry(Box::new(AddEntry::Light { at: from_point(&l.position), intensity: from_color(&l.intensity) }))))
+        .chain(objects.into_iter().flatten().map(|se| Entry::ShapeEntry(Box::new(se))))
+        .collect();
+    let mut v = serde_yaml::to_value(&entries).expect("scenes can always be written");
+    remove_nulls(&mut v);
+    Ok(serde_yaml::to_string(&v).expect("scenes can always be written"))
+}
 
-    // Doesn't help too much here.
-    g.freeze_and_optimize();
-    w.add(&g);
+/// Parses a parameter given as `name=value`, where the value can be an expression.
+pub fn parse_parameter(s: &str) -> Result<(String, f64), String> {
+    let (name, value) = s.split_once('=').ok_or_else(|| format!("expected name=value, got {s:?}"))?;
+    let name = name.trim();
+    if !is_variable_name(name) {
+        return Err(format!("invalid parameter name {name:?}"));
+    }
+    let value = evaluate(value, &HashMap::new()).map_err(|e| format!("can't evaluate {value:?}: {e}"))?;
+    Ok((name.to_owned(), value))
+}
 
-    Ok(render(&c.unwrap(), &w))
+/// Reads a scene and renders it.
+pub fn load(path: &str) -> Result<Canvas, SceneError> {
+    Scene::from_path(Path::new(path))?.render()
 }
@@ -0,0 +1,86 @@
+use std::error::Error;
+
+use crate::{Tuple, Shape, point, group, add_child, triangle};
+
+// An 80 byte header and a 32 bit triangle count, then 50 bytes per triangle: a normal, three
+// vertices and a 16 bit attribute.
+const BINARY_HEADER_SIZE: usize = 84;
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+fn binary_triangle_count(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(80..BINARY_HEADER_SIZE)?;
+    Some(u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
+}
+
+fn parse_binary(bytes: &[u8], count: usize) -> Vec<[Tuple; 3]> {
+    let f = |at: usize| f32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as f64;
+    (0..count).map(|i| {
+        // Skip the normal.
+        let start = BINARY_HEADER_SIZE + i * BINARY_TRIANGLE_SIZE + 12;
+        [0, 1, 2].map(|v| {
+            let at = start + v * 12;
+            point(f(at), f(at + 4), f(at + 8))
+        })
+    }).collect()
+}
+
+fn parse_ascii(s: &str) -> Result<Vec<[Tuple; 3]>, Box<dyn Error>> {
+    let mut triangles = vec![];
+    let mut vertices = vec![];
+    let mut in_facet = false;
+    for (i, line) in s.lines().enumerate() {
+        let bits: Vec<&str> = line.split_whitespace().collect();
+        let in_line = |message: String| -> Box<dyn Error> { format!("line {}: {message}", i + 1).into() };
+        match bits.first().copied() {
+            Some("facet") => {
+                in_facet = true;
+                vertices.clear();
+            }
+            Some("vertex") => {
+                if !in_facet || bits.len() != 4 {
+                    return Err(in_line(format!("invalid vertex {:?}", line.trim())));
+                }
+                let xs = bits[1..].iter()
+                    .map(|x| x.parse::<f64>().map_err(|_| in_line(format!("invalid number {x:?}"))))
+                    .collect::<Result<Vec<f64>, _>>()?;
+                vertices.push(point(xs[0], xs[1], xs[2]));
+            }
+            Some("endfacet") => {
+                if vertices.len() != 3 {
+                    return Err(in_line(format!("facets need 3 vertices, not {}", vertices.len())));
+                }
+                triangles.push([vertices[0], vertices[1], vertices[2]]);
+                in_facet = false;
+            }
+            // Normals are recomputed from the vertices, since they're often missing or wrong.
+            _ => {}
+        }
+    }
+    if in_facet {
+        return Err("unexpected end of file in a facet".into());
+    }
+    Ok(triangles)
+}
+
+/// Parses an ASCII or binary STL file into triangles. Binary files can start with "solid" too, so
+/// a file is taken as binary when its size matches the triangle count in its header.
+pub fn parse_stl(bytes: &[u8]) -> Result<Vec<[Tuple; 3]>, Box<dyn Error>> {
+    if let Some(count) = binary_triangle_count(bytes) {
+        if bytes.len() == BINARY_HEADER_SIZE + count * BINARY_TRIANGLE_SIZE {
+            return Ok(parse_binary(bytes, count));
+        }
+    }
+    if !bytes.starts_with(b"solid") {
+        return Err("not an STL file".into());
+    }
+    parse_ascii(std::str::from_utf8(bytes)?)
+}
+
+/// Parses an STL file into a group of triangles, ready for `freeze_and_optimize`.
+pub fn group_from_stl(bytes: &[u8]) -> Result<Shape, Box<dyn Error>> {
+    let mut g = group();
+    for [p1, p2, p3] in parse_stl(bytes)? {
+        add_child(&mut g, &triangle(&p1, &p2, &p3));
+    }
+    Ok(g)
+}
@@ -1,241 +1,165 @@
-use std::f64::consts::PI;
-use std::fs;
-use std::time::Instant;
+use std::{collections::HashMap, env, path::{Path, PathBuf}, process, thread, time::Instant};
 
 use ray_tracer_challenge::*;
 
-fn ch4() {
-    let mut c = canvas(100, 100);
-    for i in 0..12 {
-        let rad = i as f64 * 2. * PI / 12.;
-        let pt = translation(50., 50., 0.) * rotation_z(rad) * scaling(40., 40., 30.) * point(1., 0., 0.);
-        write_pixel(&mut c, pt.x as i64, pt.y as i64, &color(1., 1., 1.))
-    }
-    fs::write("output/ch4.ppm", canvas_to_ppm(&c)).unwrap();
+const USAGE: &str = "usage: ray_tracer_challenge [options] <scene.yml> <output>
+
+options:
+  --width N          image width, keeping the camera's aspect ratio unless --height is given
+  --height N         image height, likewise
+  --samples N        rays per pixel, to smooth jagged edges (default 1)
+  --threads N        threads to render on, 0 for one per core (default 0)
+  --camera NAME      the camera to use, by name or by index counting from 0 (default 0)
+  --all-cameras      renders every camera, to files named like out-front.ppm for out.ppm
+  --format F         ppm, raw-ppm or pfm (default from the output's extension)
+  --set name=value   sets a scene parameter";
+
+struct Args {
+    scene: String,
+    output: String,
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: usize,
+    threads: usize,
+    camera: Option<String>,
+    all_cameras: bool,
+    format: Option<String>,
+    parameters: HashMap<String, f64>,
 }
 
-fn ch5() {
-    let mut c = canvas(100, 100);
-    let s = 0.5;
-    let mut sphere = sphere();
-    sphere.set_transform(&(translation(0.1, 0.1, 1.) * scaling(s, s, s)));
-    let o = point(0., 0., 0.);
-    for x in 0..c.width {
-        for y in 0..c.height {
-            let d = translation(-1., -1., 0.) * scaling(2./(c.width as f64), 2./(c.height as f64), 1.) * point(x as f64, y as f64, 1.);
-            let r = ray(&o, &normalize(&(d - o)));
-            let ii = intersect(&sphere, &r);
-            if ii.count > 0 {
-                write_pixel(&mut c, x as i64, y as i64, &color(1., 0., 0.));
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        scene: String::new(),
+        output: String::new(),
+        width: None,
+        height: None,
+        samples: 1,
+        threads: 0,
+        camera: None,
+        all_cameras: false,
+        format: None,
+        parameters: HashMap::new(),
+    };
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            files.push(arg.clone());
+            continue;
+        }
+        if arg == "--all-cameras" {
+            parsed.all_cameras = true;
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+        let number = |at_least: usize| match value.parse::<usize>() {
+            Ok(n) if n >= at_least => Ok(n),
+            _ if at_least == 0 => Err(format!("{arg}: expected a whole number, got {value:?}")),
+            _ => Err(format!("{arg}: expected a whole number of at least {at_least}, got {value:?}")),
+        };
+        match arg.as_str() {
+            "--width" => parsed.width = Some(number(1)?),
+            "--height" => parsed.height = Some(number(1)?),
+            "--samples" => parsed.samples = number(1)?,
+            "--threads" => parsed.threads = number(0)?,
+            "--camera" => parsed.camera = Some(value.clone()),
+            "--format" => parsed.format = Some(value.clone()),
+            "--set" => {
+                let (name, x) = parse_parameter(value).map_err(|e| format!("--set {value}: {e}"))?;
+                parsed.parameters.insert(name, x);
             }
+            _ => return Err(format!("unknown option {arg}")),
         }
     }
-    fs::write("output/ch5.ppm", canvas_to_ppm(&c)).unwrap();
-}
-
-fn ch7() {
-    let mut camera = camera(300., 300., PI/2.);
-    camera.set_transform(&view_transform(
-        &point(0., 1.5, -5.),
-        &point(0., 1., 0.),
-        &point(0., 1., 0.),
-    ));
-    let w = default_world();
-    let c = render(&camera, &w);
-    fs::write("output/ch7.ppm", canvas_to_ppm(&c)).unwrap();
-}
-
-fn ch9() {
-    let mut camera = camera(300., 300., PI/2.);
-    camera.set_transform(&view_transform(
-        &point(0., 1.5, -5.),
-        &point(0., 1., 0.),
-        &point(0., 1., 0.),
-    ));
-    let mut w = default_world();
-    let mut p = plane();
-    set_transform(&mut p, &translation(0., 0., -10.));
-    w.add(&p);
-    let c = render(&camera, &w);
-    fs::write("output/ch9.ppm", canvas_to_ppm(&c)).unwrap();
-}
-
-fn ch10() {
-    let mut camera = camera(300., 300., PI/2.);
-    camera.set_transform(&view_transform(
-        &point(0., 0., 0.),
-        &point(0., 0., 1.),
-        &point(0., 1., 0.),
-    ));
-    let mut w = world();
-	w.add_light(&point_light(&point(-10_f64, 10_f64, -10_f64), &color(1_f64, 1_f64, 1_f64)));
-
-    let mut p = plane();
-    p.set_transform(&translation(0., -1., 0.));
-    p.material.pattern = Some(stripe_pattern(&color(1., 0., 0.), &color(0., 0., 1.)));
-    w.add(&p);
-
-    let mut p = plane();
-    p.set_transform(&(translation(0., 0., 5.) * rotation_x(PI/2.)));
-    let mut pattern = gradient_pattern(&color(1., 0., 0.), &color(0., 0., 1.));
-    pattern.set_transform(&scaling(10., 1., 1.));
-    p.material.pattern = Some(pattern);
-    w.add(&p);
-
-    let mut s = sphere();
-    s.material.color = color(1., 0., 0.);
-    s.set_transform(&translation(0., 0., 3.));
-    w.add(&s);
-
-    let c = render(&camera, &w);
-    fs::write("output/ch10.ppm", canvas_to_ppm(&c)).unwrap();
-}
-
-fn ch11() {
-    let mut camera = camera(300., 300., PI/2.);
-    camera.set_transform(&view_transform(
-        &point(0., 0., 0.),
-        &point(0., 0., 1.),
-        &point(0., 1., 0.),
-    ));
-    let mut w = world();
-	w.add_light(&point_light(&point(0., 10., -1.), &color(1_f64, 1_f64, 1_f64)));
-    let mut pattern = checkers_pattern(&WHITE , &BLACK);
-    // To avoid acne
-    pattern.set_transform(&translation(0., 2.*EPSILON, 0.));
-
-    let mut p = plane();
-    p.set_transform(&translation(0., -1., 0.));
-    p.material.pattern = Some(pattern);
-    p.material.reflective = 0.2;
-    w.add(&p);
-
-    let mut p = plane();
-    p.set_transform(&(translation(0., 0., 5.5) * rotation_x(PI/2.)));
-    p.material.pattern = Some(pattern);
-    w.add(&p);
-
-    let t = translation(0., 0., 2.);
-    let mut s = sphere();
-    // let mut s = cylinder();
-    // s.set_maximum(&3.);
-    // s.set_minimum(&0.);
-    // Copying pg159
-    s.material.color = WHITE;
-    s.material.ambient = 0.;
-    s.material.diffuse = 0.;
-    s.material.specular = 0.9;
-    s.material.shininess = 300.;
-    s.material.reflective = 0.9;
-    s.material.transparency = 0.9;
-    s.material.refractive_index = 1.05;
-    s.set_transform(&t);
-    w.add(&s);
-
-    let mut s = sphere();
-    s.material.color = color(1., 0., 0.);
-    s.set_transform(&translation(0., 0., 5.));
-    w.add(&s);
-
-    let c = render(&camera, &w);
-    fs::write("output/ch11.ppm", canvas_to_ppm(&c)).unwrap();
+    if parsed.all_cameras && parsed.camera.is_some() {
+        return Err("--camera and --all-cameras can't be used together".to_owned());
+    }
+    let [scene, output] = <[String; 2]>::try_from(files).map_err(|files| format!("expected a scene and an output file, got {}", plural(files.len(), "file")))?;
+    Ok(Args { scene, output, ..parsed })
 }
 
-fn teapot_low() -> World {
-    // Downloaded from https://graphics.cs.utah.edu/courses/cs6620/fall2019/prj05/teapot-low.obj
-    let bytes = fs::read("objs/teapot-low.obj").unwrap();
-    let mut p = parse_obj_file(&bytes);
-
-    let mut s = obj_to_group(&mut p);
-    let scale = 0.3;
-    let t = rotation_x(-1.2*PI/2.).scale(scale, scale, scale).translate(0., -2., 7.5);
-    s.set_transform(&t);
-    s.freeze_and_optimize();
-
-    let mut w = world();
-	w.add_light(&point_light(&point(0., 7., 0.), &color(1_f64, 1_f64, 1_f64)));
-    w.add(&s);
-
-    let mut p = plane();
-    p.set_transform(&translation(0., -2., 0.));
-    p.material.pattern = Some(checkers_pattern(&color(0.7, 0.7, 0.7), &color(0.8, 0.8, 0.8)));
-    w.add(&p);
-
-    let mut p = plane();
-    p.set_transform(&rotation_x(PI/2.).translate(10., -10., 10.));
-    p.material.pattern = Some(checkers_pattern(&color(0.7, 0.7, 0.7), &color(0.8, 0.8, 0.8)));
-    w.add(&p);
-
-    w
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 { format!("{n} {word}") } else { format!("{n} {word}s") }
 }
 
-fn teapot_high() -> World {
-    // Downloaded from https://users.cs.utah.edu/~natevm/newell_teaset/newell_teaset.zip
-    let bytes = fs::read("objs/teapot.obj").unwrap();
-    let mut p = parse_obj_file(&bytes);
-    let mut s = obj_to_group(&mut p);
-    let scale = 0.9;
-    let t = rotation_y(-PI/2.).rotate_x(-0.0 * PI / 2.).scale(scale, scale, scale).translate(0., -2., 4.5);
-    s.set_transform(&t);
-    s.freeze_and_optimize();
-    let mut w = world();
-	w.add_light(&point_light(&point(5., 7., -5.), &color(1_f64, 1_f64, 1_f64)));
-    w.add(&s);
-
-    let mut p = plane();
-    p.set_transform(&translation(0., -2., 0.));
-    p.material.pattern = Some(checkers_pattern(&color(0.7, 0.7, 0.7), &color(0.8, 0.8, 0.8)));
-    w.add(&p);
-
-    let mut p = plane();
-    p.set_transform(&rotation_x(PI/2.).translate(10., -10., 10.));
-    p.material.pattern = Some(checkers_pattern(&color(0.7, 0.7, 0.7), &color(0.8, 0.8, 0.8)));
-    w.add(&p);
-
-    w
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
 }
 
-fn render_world(w: &World, output: &str) {
-    let mut camera = camera(600., 400., PI/2.);
-    camera.set_transform(&view_transform(
-        &point(0., 0., 0.),
-        &point(0., 0., 1.),
-        &point(0., 1., 0.),
-    ));
-    let c = render(&camera, &w);
-    fs::write(output, canvas_to_ppm(&c)).unwrap();
+// Where to save the view from one of several cameras, so `out.ppm` becomes `out-front.ppm` for
+// the camera called front.
+fn output_for(output: &Path, label: &str) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let label = label.replace(['/', '\\'], "_");
+    match output.extension().and_then(|e| e.to_str()) {
+        Some(extension) => output.with_file_name(format!("{stem}-{label}.{extension}")),
+        None => output.with_file_name(format!("{stem}-{label}")),
+    }
 }
 
-fn render_scene(path: &str, output: &str) {
-    let c = load(path).unwrap();
-    fs::write(output, canvas_to_ppm(&c)).unwrap();
+// The camera at the size asked for, where giving only one side keeps the camera's aspect ratio.
+fn resized(camera: &Camera, width: Option<usize>, height: Option<usize>) -> Camera {
+    let aspect = camera.hsize as f64 / camera.vsize as f64;
+    let (w, h) = match (width, height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, ((w as f64 / aspect).round() as usize).max(1)),
+        (None, Some(h)) => (((h as f64 * aspect).round() as usize).max(1), h),
+        (None, None) => (camera.hsize, camera.vsize),
+    };
+    camera.with_size(w, h)
 }
 
 fn main() {
-    let now = Instant::now();
-    ch4();
-    ch5();
-    ch7();
-    ch9();
-    ch10();
-    ch11();
-
-    render_scene("book-code/cover.yml", "output/cover.ppm");
-
-    for name in [
-        "pg159",
-        "table",
-        "cylinders",
-        "puppets",
-        "reflect-refract",
-        "groups",
-    ] {
-        render_scene(&format!("book-code/forum-scenes/{name}.yml"), &format!("output/{name}.ppm"));
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{USAGE}");
+        return;
     }
+    let args = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("error: {e}\n\n{USAGE}");
+        process::exit(2);
+    });
 
-    render_world(&teapot_low(), "output/ch15.ppm");
-    render_world(&teapot_high(), "output/ch15-high.ppm");
-
-    let elapsed_time = now.elapsed();
-    println!("Rendering done. {} seconds.", (elapsed_time.as_millis() as f64)/1000.);
+    let format = image_format(Path::new(&args.output), args.format.as_deref()).unwrap_or_else(|e| fail(&e));
+    let now = Instant::now();
+    let scene = Scene::from_path_with_parameters(Path::new(&args.scene), &args.parameters)
+        .unwrap_or_else(|e| fail(&format!("{}: {e}", args.scene)));
+    println!("Loaded {} in {:.3} seconds.", args.scene, now.elapsed().as_secs_f64());
+    for stats in &scene.bvh_stats {
+        println!("BVH: {stats}");
+    }
+    println!("Top-level BVH: {}", scene.world.bvh_stats());
+
+    let scene_error = |e: SceneError| fail(&format!("{}: {e}", args.scene));
+    let views: Vec<(PathBuf, &Camera)> = if args.all_cameras {
+        scene.camera().unwrap_or_else(scene_error);
+        let outputs = scene.camera_labels().into_iter().map(|label| output_for(Path::new(&args.output), &label));
+        outputs.zip(&scene.cameras).collect()
+    } else {
+        let camera = match &args.camera {
+            Some(name) => scene.find_camera(name),
+            None => scene.camera(),
+        };
+        vec![(PathBuf::from(&args.output), camera.unwrap_or_else(scene_error))]
+    };
+    let threads = match args.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let options = RenderOptions { samples: args.samples, threads };
+
+    for (output, camera) in views {
+        let camera = resized(camera, args.width, args.height);
+        let now = Instant::now();
+        let canvas = render_with(&camera, &scene.world, &options);
+        println!(
+            "Rendered {}x{} pixels with {} each on {} in {:.3} seconds.",
+            camera.hsize, camera.vsize, plural(args.samples, "sample"), plural(threads, "thread"), now.elapsed().as_secs_f64(),
+        );
+        if let Err(e) = save_canvas(&canvas, &output, Some(&format)) {
+            fail(&format!("can't save {}: {e}", output.display()));
+        }
+        println!("Wrote {}.", output.display());
+    }
 }
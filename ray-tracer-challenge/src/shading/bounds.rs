@@ -1,14 +1,12 @@
-use std::f64::{INFINITY, NEG_INFINITY};
-
-use crate::{Shape, Ray, Tuple, transform, Intersection, point, check_axis};
+use crate::{Shape, Ray, Tuple, Intersection, point, check_axis, BVHBuildOptions, split_items, shape_items};
 
 // https://forum.raytracerchallenge.com/post/401/thread
 pub const INFINITY_FOR_BOUNDS: f64 = f64::MAX / 2.;
 
 pub fn f64_for_bound(a: f64) -> f64 {
-    if a == INFINITY {
+    if a == f64::INFINITY {
         INFINITY_FOR_BOUNDS
-    } else if a == NEG_INFINITY {
+    } else if a == f64::NEG_INFINITY {
         -INFINITY_FOR_BOUNDS
     } else {
         a
@@ -17,14 +15,17 @@ pub fn f64_for_bound(a: f64) -> f64 {
 
 pub enum BVHTraversalPolicy<'i> {
     ClosestHit { x: Option<Intersection<'i>> },
-    // TODO to make this useful for shadowing, we need to take a maximum value here.
-    AnyHit { x: Option<Intersection<'i>> },
+    // Looks for any shadow casting hit with 0 < t < t_max, and stops at the first one.
+    AnyHit { x: Option<Intersection<'i>>, t_max: f64 },
     AllHits { xs: Vec<Intersection<'i>> },
 }
 
 impl<'i> BVHTraversalPolicy<'i> {
     pub fn new_any_hit() -> BVHTraversalPolicy<'i> {
-        BVHTraversalPolicy::AnyHit { x: None }
+        BVHTraversalPolicy::new_any_hit_before(f64::INFINITY)
+    }
+    pub fn new_any_hit_before(t_max: f64) -> BVHTraversalPolicy<'i> {
+        BVHTraversalPolicy::AnyHit { x: None, t_max }
     }
     pub fn new_all_hits() -> BVHTraversalPolicy<'i> {
         BVHTraversalPolicy::AllHits { xs: vec![] }
@@ -37,13 +38,15 @@ impl<'i> BVHTraversalPolicy<'i> {
         match self {
             BVHTraversalPolicy::ClosestHit { x } => {
                 let (bbhit, tmin, tmax) = bb.local_intersect_tmin_tmax(ray);
-                let current_t = x.map_or(INFINITY, |x| x.t);
+                let current_t = x.map_or(f64::INFINITY, |x| x.t);
                 return (bbhit && tmin <= current_t && 0. <= tmax, tmin);
             },
-            BVHTraversalPolicy::AnyHit { x } => {
+            BVHTraversalPolicy::AnyHit { x, t_max } => {
                 if x.is_some() {
-                    return (false, NEG_INFINITY);
+                    return (false, f64::NEG_INFINITY);
                 }
+                let (bbhit, tmin, tmax) = bb.local_intersect_tmin_tmax(ray);
+                return (bbhit && tmin < *t_max && 0. <= tmax, tmin);
             },
             BVHTraversalPolicy::AllHits { xs: _ } => {},
         }
@@ -54,7 +57,7 @@ impl<'i> BVHTraversalPolicy<'i> {
     pub fn add_intersections<'a>(&'a mut self, new_xs: &mut Vec<Intersection<'i>>) {
         match self {
             BVHTraversalPolicy::ClosestHit { x } => {
-                let mut current_t = x.map_or(INFINITY, |x| x.t);
+                let mut current_t = x.map_or(f64::INFINITY, |x| x.t);
                 for new_x in new_xs {
                     if 0. <= new_x.t && new_x.t < current_t {
                         *x = Some(*new_x);
@@ -62,9 +65,9 @@ impl<'i> BVHTraversalPolicy<'i> {
                     }
                 }
             },
-            BVHTraversalPolicy::AnyHit { x } => {
+            BVHTraversalPolicy::AnyHit { x, t_max } => {
                 for new_x in new_xs {
-                    if 0. <= new_x.t {
+                    if 0. < new_x.t && new_x.t < *t_max && new_x.casts_shadow() {
                         *x = Some(*new_x);
                     }
                 }
@@ -75,12 +78,40 @@ impl<'i> BVHTraversalPolicy<'i> {
         }
     }
 
+    // The closest hit so far, for the ClosestHit policy.
+    pub fn closest_t(&self) -> f64 {
+        match self {
+            BVHTraversalPolicy::ClosestHit { x } => x.map_or(f64::INFINITY, |x| x.t),
+            _ => f64::INFINITY,
+        }
+    }
+
+    // True once nothing else could change the result, so traversal can stop.
+    pub fn is_done(&self) -> bool {
+        matches!(self, BVHTraversalPolicy::AnyHit { x: Some(_), t_max: _ })
+    }
+
+    // Intersects a child shape, given a ray in its parent's space, the way this policy needs.
+    pub fn intersect_child(&mut self, child: &'i Shape, ray: &Ray) {
+        let mut ts = match self {
+            BVHTraversalPolicy::ClosestHit { x: _ } => child.intersect_closest_hit(ray),
+            BVHTraversalPolicy::AnyHit { x, t_max } => {
+                if x.is_none() {
+                    *x = child.intersect_occluder(ray, *t_max);
+                }
+                return;
+            }
+            BVHTraversalPolicy::AllHits { xs: _ } => child.intersect(ray),
+        };
+        self.add_intersections(&mut ts);
+    }
+
     pub fn intersections<'a>(&'a self) -> Vec<Intersection<'i>> {
         match self {
             BVHTraversalPolicy::ClosestHit { x } => {
                 if let Some(i) = x { vec![*i] } else { vec![] }
             }
-            BVHTraversalPolicy::AnyHit { x } => {
+            BVHTraversalPolicy::AnyHit { x, t_max: _ } => {
                 if let Some(i) = x { vec![*i] } else { vec![] }
             }
             BVHTraversalPolicy::AllHits { xs } => {
@@ -98,6 +129,7 @@ pub enum BVHNodeType {
 
 type BVHLink = Box<BVHNode>;
 
+// A pointer based BVH holding copies of its shapes. Groups and the world use LinearBVH instead.
 #[derive(PartialEq, Debug, Clone)]
 pub struct BVHNode {
     pub bb: BoundingBox,
@@ -155,16 +187,10 @@ impl BVHNode {
         match &self.ntype {
             BVHNodeType::Leaf(shapes) => {
                 for child in shapes {
-                    // HACK: this loop was originally copy/pasted from group's intersect.
-                    let t = child.local_inverse();
-                    let object_ray = transform(ray, &t);
-                    // TODO Can optimize this further for AnyHit by bailing out if there's a match here. Potentially via should_traverse
-                    let mut ts = match p {
-                        BVHTraversalPolicy::ClosestHit { x: _ } => child.local_intersect_closest_hit(&object_ray),
-                        BVHTraversalPolicy::AnyHit { x: _ } => child.local_intersect_any_hit(&object_ray),
-                        BVHTraversalPolicy::AllHits { xs: _ } => child.local_intersect(&object_ray),
-                    };
-                    p.add_intersections(&mut ts);
+                    if p.is_done() {
+                        return;
+                    }
+                    p.intersect_child(child, ray);
                 }
             }
             BVHNodeType::Internal(left, right) => {
@@ -202,103 +228,227 @@ impl BVHNode {
         }
     }
 
-    pub fn build(bb: BoundingBox, shapes: &Vec<Shape>) -> BVHNode {
-        // Implementation closely follows PBR 3rd Ed
-        // https://pbr-book.org/3ed-2018/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies#TheSurfaceAreaHeuristic
-        const DIVISIONS: usize = 12;
-        const RELATIVE_COST_TRAVERSAL: f64 = 0.125;
+    pub fn build(bb: BoundingBox, shapes: &[Shape]) -> BVHNode {
+        if let Some((left, right, lbb, rbb)) = sah_split(&bb, &shape_items(shapes)) {
+            let pick = |idxs: Vec<usize>| idxs.into_iter().map(|i| shapes[i].clone()).collect::<Vec<Shape>>();
+            // Recursively build tree.
+            let left = BVHNode::build(lbb, &pick(left));
+            let right = BVHNode::build(rbb, &pick(right));
 
-        if shapes.len() < 4 {
-            return BVHNode {
+            BVHNode {
                 bb,
-                ntype: BVHNodeType::Leaf(shapes.clone())
+                ntype: BVHNodeType::Internal(
+                    Box::new(left),
+                    Box::new(right),
+                ),
+            }
+        } else {
+            BVHNode {
+                bb,
+                ntype: BVHNodeType::Leaf(shapes.to_vec()),
             }
         }
+    }
+}
+
+/// A node of a `LinearBVH`. Leaves cover `count` primitive indices starting at `offset`. Interior
+/// nodes have `count` zero, their first child right after them, and their second child at `offset`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct LinearBVHNode {
+    pub bb: BoundingBox,
+    pub offset: usize,
+    pub count: usize,
+}
+
+impl LinearBVHNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// A BVH flattened into a depth-first array of nodes, following PBR 3rd Ed's LinearBVHNode.
+/// Leaves refer to primitives by index into whatever list the BVH was built from, so the
+/// primitives themselves stay where they are.
+#[derive(PartialEq, Debug, Clone)]
+pub struct LinearBVH {
+    pub nodes: Vec<LinearBVHNode>,
+    pub indices: Vec<usize>,
+    // The parent of every node, and the leaf holding every primitive, so refitting after one
+    // primitive moves only touches the nodes above it. See `link`.
+    pub(crate) parents: Vec<usize>,
+    pub(crate) leaves: Vec<usize>,
+}
+
+impl LinearBVH {
+    /// Builds a BVH over primitives given as (bounding box, centroid) pairs, with default options.
+    pub fn build(items: &[(BoundingBox, Tuple)]) -> LinearBVH {
+        LinearBVH::build_with(items, &BVHBuildOptions::default())
+    }
+
+    pub fn build_for_shapes(shapes: &[Shape]) -> LinearBVH {
+        LinearBVH::build_with(&shape_items(shapes), &BVHBuildOptions::default())
+    }
 
-        // The case where we don't split
-        // This cost is a simplified version of below equation for cost assuming no traversal; surface area of bb with itself cancels out.
-        let mut mincost = shapes.len() as f64;
-        let mut minlists = (0, 0, bb, BoundingBox::new_empty());
-
-        // Init buckets
-        let mut allbuckets: [[(BoundingBox, Vec<&Shape>); DIVISIONS]; 3] = core::array::from_fn(|_dim|
-            core::array::from_fn(|_bucket| (BoundingBox::new_empty(), vec![])));
-
-        for (dim, buckets) in allbuckets.iter_mut().enumerate() {
-            // Fill buckets
-            let (min, max) = (bb.min[dim], bb.max[dim]);
-            let extent = max - min;
-            for s in shapes {
-                let centroid = s.as_local_shape().local_bounding_box().centroid();
-                // This is ok b/c of linearity?
-                let c = s.local_to_parent_transform * centroid;
-                let idx = ((c[dim] - min) / extent * DIVISIONS as f64).floor() as usize;
-                let (bb, v) = &mut buckets[idx];
-                v.push(s);
-                bb.include_transformed_shape(s);
+    pub fn max_depth(&self) -> usize {
+        let mut max_depth = 0;
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![(0, 1)] };
+        while let Some((idx, depth)) = stack.pop() {
+            max_depth = max_depth.max(depth);
+            let node = &self.nodes[idx];
+            if !node.is_leaf() {
+                stack.push((idx + 1, depth + 1));
+                stack.push((node.offset, depth + 1));
             }
+        }
+        max_depth
+    }
 
-            // Try all partitions of buckets.
-            // We start at index 1 to avoid an empty one.
-            for split_idx in 1..DIVISIONS {
-                let mut lbb = BoundingBox::new_empty();
-                let mut rbb = BoundingBox::new_empty();
-                let mut lcount = 0;
-                let mut rcount = 0;
-                for (b, ct, rng) in [
-                    (&mut lbb, &mut lcount, 0..split_idx),
-                    (&mut rbb, &mut rcount, split_idx..DIVISIONS),
-                ] {
-                    for idx in rng {
-                        let (bb, v) = &buckets[idx];
-                        b.include_bb(bb);
-                        *ct += v.len();
+    /// Walks the BVH with an explicit stack, calling `visit` with the index of every primitive in
+    /// the leaves the policy wants to look at.
+    pub fn traverse<'a, F>(&self, ray: &Ray, p: &mut BVHTraversalPolicy<'a>, mut visit: F)
+    where F: FnMut(usize, &mut BVHTraversalPolicy<'a>) {
+        if self.nodes.is_empty() || !p.should_traverse(&self.nodes[0].bb, ray).0 {
+            return;
+        }
+        // Nodes on the stack were checked when pushed. The flag marks ones we should check again,
+        // since hits found in the meantime may rule them out.
+        let mut stack: Vec<(usize, bool)> = vec![(0, false)];
+        while let Some((idx, recheck)) = stack.pop() {
+            if p.is_done() {
+                return;
+            }
+            let node = &self.nodes[idx];
+            if recheck && !p.should_traverse(&node.bb, ray).0 {
+                continue;
+            }
+            if node.is_leaf() {
+                for &i in &self.indices[node.offset..node.offset + node.count] {
+                    if p.is_done() {
+                        return;
                     }
+                    visit(i, p);
                 }
-                let cost = RELATIVE_COST_TRAVERSAL + (
-                    lcount as f64 * lbb.surface_area() +
-                    rcount as f64 * rbb.surface_area()) / bb.surface_area();
-                if cost < mincost {
-                    mincost = cost;
-                    minlists = (dim, split_idx, lbb, rbb);
-                }
+                continue;
+            }
+            let (left, right) = (idx + 1, node.offset);
+            let (l_bbhit, l_tmin) = p.should_traverse(&self.nodes[left].bb, ray);
+            let (r_bbhit, r_tmin) = p.should_traverse(&self.nodes[right].bb, ray);
+            if let BVHTraversalPolicy::AllHits { xs: _ } = p {
+                // Order doesn't matter when we want all hits, but we match the recursive version.
+                if r_bbhit { stack.push((right, false)) }
+                if l_bbhit { stack.push((left, false)) }
+                continue;
+            }
+            if l_bbhit && r_bbhit {
+                // Traverse the closer box first, by pushing it last.
+                let (near, far) = if l_tmin > r_tmin { (right, left) } else { (left, right) };
+                stack.push((far, true));
+                stack.push((near, false));
+            } else if l_bbhit {
+                stack.push((left, false));
+            } else if r_bbhit {
+                stack.push((right, false));
             }
         }
+    }
 
-        let (dim, split_idx, lbb, rbb) = minlists;
+    /// Intersects shapes that this BVH was built over, with a ray in their parent's space.
+    pub fn intersect_shapes<'a>(&self, shapes: &'a [Shape], ray: &Ray, p: &mut BVHTraversalPolicy<'a>) {
+        self.traverse(ray, p, |i, p| p.intersect_child(&shapes[i], ray));
+    }
 
-        if split_idx == 0 {
-            // Not worth recursing.
-            return BVHNode {
-                bb,
-                ntype: BVHNodeType::Leaf(shapes.clone()),
+    /// Recomputes node bounds after primitives moved, keeping the tree as it is. `bbs` is indexed
+    /// by primitive. This is much cheaper than a rebuild, though the tree gets worse as things move.
+    pub fn refit(&mut self, bbs: &[BoundingBox]) {
+        // Children come after their parents, so going backwards sees them first.
+        for idx in (0..self.nodes.len()).rev() {
+            let node = self.nodes[idx];
+            let mut bb = BoundingBox::new_empty();
+            if node.is_leaf() {
+                for &i in &self.indices[node.offset..node.offset + node.count] {
+                    bb.include_bb(&bbs[i]);
+                }
+            } else {
+                bb.include_bb(&self.nodes[idx + 1].bb);
+                bb.include_bb(&self.nodes[node.offset].bb);
             }
-        } else {
-            let mut left: Vec<Shape> = vec![];
-            let mut right = vec![];
-            for (v, rng) in [
-                (&mut left, 0..split_idx),
-                (&mut right, split_idx..DIVISIONS),
-            ] {
-                for idx in rng {
-                    v.extend(allbuckets[dim][idx].1.iter().map(|s| (*s).clone()));
+            self.nodes[idx].bb = bb;
+        }
+    }
+
+    /// Like `refit` after only primitive `i` moved: recomputes the bounds of its leaf and of the
+    /// nodes above it. `bb` gives the bounds of a primitive, and is only asked about the ones in
+    /// that leaf.
+    pub fn refit_primitive<F: Fn(usize) -> BoundingBox>(&mut self, i: usize, bb: F) {
+        let Some(&leaf) = self.leaves.get(i) else { return };
+        let node = self.nodes[leaf];
+        let mut leaf_bb = BoundingBox::new_empty();
+        for &j in &self.indices[node.offset..node.offset + node.count] {
+            leaf_bb.include_bb(&bb(j));
+        }
+        self.nodes[leaf].bb = leaf_bb;
+        let mut idx = leaf;
+        while idx != 0 {
+            idx = self.parents[idx];
+            let mut node_bb = BoundingBox::new_empty();
+            node_bb.include_bb(&self.nodes[idx + 1].bb);
+            node_bb.include_bb(&self.nodes[self.nodes[idx].offset].bb);
+            self.nodes[idx].bb = node_bb;
+        }
+    }
+
+    // Fills in `parents` and `leaves` from the nodes and indices.
+    pub(crate) fn link(&mut self) {
+        self.parents = vec![0; self.nodes.len()];
+        self.leaves = vec![0; self.indices.len()];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if node.is_leaf() {
+                for &i in &self.indices[node.offset..node.offset + node.count] {
+                    self.leaves[i] = idx;
                 }
+            } else {
+                self.parents[idx + 1] = idx;
+                self.parents[node.offset] = idx;
             }
-            // Recursively build tree.
-            let left = BVHNode::build(lbb, &left);
-            let right = BVHNode::build(rbb, &right);
+        }
+    }
 
-            BVHNode {
-                bb,
-                ntype: BVHNodeType::Internal(
-                    Box::new(left),
-                    Box::new(right),
-                ),
+    /// Takes primitive `i` out, and renumbers the ones after it to match removing it from the
+    /// primitive list. Bounds need a refit afterwards. Returns false when that would leave an empty
+    /// leaf, which we can't represent, so the BVH needs a rebuild instead.
+    pub fn remove_primitive(&mut self, i: usize) -> bool {
+        let Some(pos) = self.indices.iter().position(|&x| x == i) else { return true };
+        if self.nodes.iter().any(|n| n.is_leaf() && n.offset <= pos && pos < n.offset + n.count && n.count == 1) {
+            return false;
+        }
+        self.indices.remove(pos);
+        for n in &mut self.nodes {
+            if !n.is_leaf() {
+                continue;
+            }
+            if n.offset <= pos && pos < n.offset + n.count {
+                n.count -= 1;
+            } else if n.offset > pos {
+                n.offset -= 1;
+            }
+        }
+        for x in &mut self.indices {
+            if *x > i {
+                *x -= 1;
             }
         }
+        self.link();
+        true
     }
 }
 
+/// Chooses how to split items, given as (bounding box, centroid) pairs within `bb`, into two
+/// subtrees with the default options. Returns the item indices and bounding boxes of both sides, or
+/// None when it's not worth splitting.
+pub fn sah_split(bb: &BoundingBox, items: &[(BoundingBox, Tuple)]) -> Option<(Vec<usize>, Vec<usize>, BoundingBox, BoundingBox)> {
+    split_items(bb, items, &BVHBuildOptions::default())
+}
+
 
 pub trait BaseBoundingBox {
     fn bounds(&self) -> (Tuple, Tuple);
@@ -339,8 +489,8 @@ impl BoundingBox {
     }
 
     pub fn new_empty() -> BoundingBox {
-        let min = point(INFINITY, INFINITY, INFINITY);
-        let max = point(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY);
+        let min = point(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let max = point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
         BoundingBox::new(min, max)
     }
 
@@ -413,6 +563,12 @@ impl BoundingBox {
         )
     }
 
+    // Infinite shapes like planes have bounds of +/- INFINITY_FOR_BOUNDS (see f64_for_bound), which
+    // overflow when we compute their area.
+    pub fn is_unbounded(&self) -> bool {
+        self.surface_area() == f64::INFINITY
+    }
+
     pub fn surface_area(&self) -> f64 {
         let dx = self.max.x - self.min.x;
         let dy = self.max.y - self.min.y;
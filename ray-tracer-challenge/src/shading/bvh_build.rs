@@ -0,0 +1,291 @@
+use std::{fmt, thread, time::Duration};
+
+use crate::{BoundingBox, Tuple, Shape, LinearBVH, LinearBVHNode};
+
+// Relative to the cost of intersecting a primitive.
+const RELATIVE_COST_TRAVERSAL: f64 = 0.125;
+// Smaller subtrees are built on the current thread, since spawning would cost more than it saves.
+const PARALLEL_THRESHOLD: usize = 4096;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SplitMethod {
+    /// Surface area heuristic, evaluated at bucket boundaries along each axis.
+    Sah,
+    /// Split at the middle of the centroids' extent, along its longest axis.
+    Median,
+    /// Split into two halves with the same number of primitives, along the centroids' longest axis.
+    EqualCounts,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BVHBuildOptions {
+    pub split: SplitMethod,
+    // Number of buckets per axis for SAH.
+    pub buckets: usize,
+    // Nodes with fewer primitives than this become leaves without trying to split.
+    pub min_split_size: usize,
+    // Nodes with more primitives than this are split even when SAH says a leaf is cheaper.
+    pub max_leaf_size: usize,
+    // Build large subtrees on separate threads.
+    pub parallel: bool,
+}
+
+impl Default for BVHBuildOptions {
+    fn default() -> Self {
+        BVHBuildOptions {
+            split: SplitMethod::Sah,
+            buckets: 12,
+            min_split_size: 4,
+            max_leaf_size: usize::MAX,
+            parallel: false,
+        }
+    }
+}
+
+/// What a BVH looks like, for judging build options.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct BVHStats {
+    pub primitives: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    // Number of levels, so a lone leaf has depth 1.
+    pub max_depth: usize,
+    // leaf_sizes[n] is the number of leaves with n primitives.
+    pub leaf_sizes: Vec<usize>,
+    // depth_histogram[d] is the number of leaves d levels below the root.
+    pub depth_histogram: Vec<usize>,
+    // Expected cost of a ray hitting the root, in primitive intersections. Zero for unbounded roots.
+    pub sah_cost: f64,
+    pub build_time: Duration,
+    // Stats for the BVHs of nested groups, which are built first.
+    pub nested: Vec<BVHStats>,
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} primitives, {} nodes, {} leaves, max depth {}, SAH cost {:.2}, built in {:.3} seconds",
+            self.primitives, self.nodes, self.leaves, self.max_depth, self.sah_cost, self.build_time.as_secs_f64(),
+        )?;
+        if !self.nested.is_empty() {
+            let total: usize = self.nested.iter().map(|s| s.primitives).sum();
+            write!(f, " ({} nested BVHs with {} primitives)", self.nested.len(), total)?;
+        }
+        Ok(())
+    }
+}
+
+/// The (bounding box, centroid) pairs we build BVHs from, for shapes in their parent's space.
+pub fn shape_items(shapes: &[Shape]) -> Vec<(BoundingBox, Tuple)> {
+    shapes.iter().map(|s| {
+        // This is ok b/c of linearity?
+        let centroid = s.local_to_parent_transform * s.as_local_shape().local_bounding_box().centroid();
+        (BoundingBox::from_transformed_shapes(&[s]), centroid)
+    }).collect()
+}
+
+fn bounds_of(items: &[(BoundingBox, Tuple)], idxs: &[usize]) -> BoundingBox {
+    let mut bb = BoundingBox::new_empty();
+    for &i in idxs {
+        bb.include_bb(&items[i].0);
+    }
+    bb
+}
+
+fn longest_centroid_axis(items: &[(BoundingBox, Tuple)]) -> (usize, f64, f64) {
+    let cb = BoundingBox::from_points(&items.iter().map(|(_, c)| *c).collect::<Vec<Tuple>>());
+    let axis = (0..3).max_by(|&a, &b| (cb.max[a] - cb.min[a]).total_cmp(&(cb.max[b] - cb.min[b]))).unwrap();
+    (axis, cb.min[axis], cb.max[axis])
+}
+
+fn equal_counts_split(items: &[(BoundingBox, Tuple)]) -> Option<(Vec<usize>, Vec<usize>)> {
+    if items.len() < 2 {
+        return None;
+    }
+    let (axis, _, _) = longest_centroid_axis(items);
+    let mut idxs: Vec<usize> = (0..items.len()).collect();
+    idxs.sort_by(|&a, &b| items[a].1[axis].total_cmp(&items[b].1[axis]));
+    let right = idxs.split_off(items.len() / 2);
+    Some((idxs, right))
+}
+
+fn median_split(items: &[(BoundingBox, Tuple)]) -> Option<(Vec<usize>, Vec<usize>)> {
+    let (axis, min, max) = longest_centroid_axis(items);
+    let mid = (min + max) / 2.;
+    let (left, right): (Vec<usize>, Vec<usize>) = (0..items.len()).partition(|&i| items[i].1[axis] < mid);
+    if left.is_empty() || right.is_empty() { None } else { Some((left, right)) }
+}
+
+fn sah_split_with(bb: &BoundingBox, items: &[(BoundingBox, Tuple)], buckets: usize, force: bool) -> Option<(Vec<usize>, Vec<usize>, BoundingBox, BoundingBox)> {
+    // Implementation closely follows PBR 3rd Ed
+    // https://pbr-book.org/3ed-2018/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies#TheSurfaceAreaHeuristic
+
+    // The case where we don't split
+    // This cost is a simplified version of below equation for cost assuming no traversal; surface area of bb with itself cancels out.
+    let leafcost = items.len() as f64;
+    let mut mincost = f64::INFINITY;
+    let mut minlists = None;
+
+    let mut allbuckets: Vec<Vec<(BoundingBox, Vec<usize>)>> = vec![vec![(BoundingBox::new_empty(), vec![]); buckets]; 3];
+
+    for (dim, buckets_for_dim) in allbuckets.iter_mut().enumerate() {
+        // Fill buckets
+        let (min, max) = (bb.min[dim], bb.max[dim]);
+        let extent = max - min;
+        for (i, (item_bb, c)) in items.iter().enumerate() {
+            // Centroids on the max edge would land one past the last bucket.
+            let idx = (((c[dim] - min) / extent * buckets as f64).floor() as usize).min(buckets - 1);
+            let (bb, v) = &mut buckets_for_dim[idx];
+            v.push(i);
+            bb.include_bb(item_bb);
+        }
+
+        // Try all partitions of buckets.
+        // We start at index 1 to avoid an empty one.
+        for split_idx in 1..buckets {
+            let mut lbb = BoundingBox::new_empty();
+            let mut rbb = BoundingBox::new_empty();
+            let mut lcount = 0;
+            let mut rcount = 0;
+            for (b, ct, rng) in [
+                (&mut lbb, &mut lcount, 0..split_idx),
+                (&mut rbb, &mut rcount, split_idx..buckets),
+            ] {
+                for idx in rng {
+                    let (bb, v) = &buckets_for_dim[idx];
+                    b.include_bb(bb);
+                    *ct += v.len();
+                }
+            }
+            // Forced splits need both sides non-empty, or we'd recurse forever.
+            if force && (lcount == 0 || rcount == 0) {
+                continue;
+            }
+            let cost = RELATIVE_COST_TRAVERSAL + (
+                lcount as f64 * lbb.surface_area() +
+                rcount as f64 * rbb.surface_area()) / bb.surface_area();
+            if cost < mincost {
+                mincost = cost;
+                minlists = Some((dim, split_idx, lbb, rbb));
+            }
+        }
+    }
+
+    let (dim, split_idx, lbb, rbb) = minlists?;
+    if mincost >= leafcost && !force {
+        // Not worth recursing.
+        return None;
+    }
+    let collect = |rng: std::ops::Range<usize>| rng.flat_map(|idx| allbuckets[dim][idx].1.clone()).collect::<Vec<usize>>();
+    Some((collect(0..split_idx), collect(split_idx..buckets), lbb, rbb))
+}
+
+/// Chooses how to split items, given as (bounding box, centroid) pairs within `bb`, into two
+/// subtrees. Returns the item indices and bounding boxes of both sides, or None for a leaf.
+pub fn split_items(bb: &BoundingBox, items: &[(BoundingBox, Tuple)], options: &BVHBuildOptions) -> Option<(Vec<usize>, Vec<usize>, BoundingBox, BoundingBox)> {
+    if items.len() < options.min_split_size.max(2) {
+        return None;
+    }
+    let with_bounds = |(left, right): (Vec<usize>, Vec<usize>)| {
+        let (lbb, rbb) = (bounds_of(items, &left), bounds_of(items, &right));
+        (left, right, lbb, rbb)
+    };
+    match options.split {
+        SplitMethod::Sah => {
+            let force = items.len() > options.max_leaf_size;
+            sah_split_with(bb, items, options.buckets.max(2), force)
+                // When all centroids share a bucket, we can only force a split by counting.
+                .or_else(|| if force { equal_counts_split(items).map(with_bounds) } else { None })
+        }
+        SplitMethod::Median => median_split(items).or_else(|| equal_counts_split(items)).map(with_bounds),
+        SplitMethod::EqualCounts => equal_counts_split(items).map(with_bounds),
+    }
+}
+
+// The tree we build before flattening it, since subtrees may be built on different threads.
+enum BuildNode {
+    Leaf(BoundingBox, Vec<usize>),
+    Interior(BoundingBox, Box<BuildNode>, Box<BuildNode>),
+}
+
+fn build_recursive(bb: BoundingBox, items: &[(BoundingBox, Tuple)], indices: Vec<usize>, options: &BVHBuildOptions) -> BuildNode {
+    let subset: Vec<(BoundingBox, Tuple)> = indices.iter().map(|&i| items[i]).collect();
+    match split_items(&bb, &subset, options) {
+        None => BuildNode::Leaf(bb, indices),
+        Some((left, right, lbb, rbb)) => {
+            let pick = |idxs: Vec<usize>| idxs.into_iter().map(|i| indices[i]).collect::<Vec<usize>>();
+            let (left, right) = (pick(left), pick(right));
+            let (left, right) = if options.parallel && indices.len() >= PARALLEL_THRESHOLD {
+                thread::scope(|s| {
+                    let handle = s.spawn(|| build_recursive(lbb, items, left, options));
+                    let right = build_recursive(rbb, items, right, options);
+                    (handle.join().unwrap(), right)
+                })
+            } else {
+                (build_recursive(lbb, items, left, options), build_recursive(rbb, items, right, options))
+            };
+            BuildNode::Interior(bb, Box::new(left), Box::new(right))
+        }
+    }
+}
+
+impl LinearBVH {
+    pub fn build_with(items: &[(BoundingBox, Tuple)], options: &BVHBuildOptions) -> LinearBVH {
+        let mut bvh = LinearBVH { nodes: vec![], indices: vec![], parents: vec![], leaves: vec![] };
+        if !items.is_empty() {
+            let bb = bounds_of(items, &(0..items.len()).collect::<Vec<usize>>());
+            let root = build_recursive(bb, items, (0..items.len()).collect(), options);
+            bvh.flatten(root);
+            bvh.link();
+        }
+        bvh
+    }
+
+    fn flatten(&mut self, node: BuildNode) {
+        match node {
+            BuildNode::Leaf(bb, indices) => {
+                self.nodes.push(LinearBVHNode { bb, offset: self.indices.len(), count: indices.len() });
+                self.indices.extend(indices);
+            }
+            BuildNode::Interior(bb, left, right) => {
+                let idx = self.nodes.len();
+                self.nodes.push(LinearBVHNode { bb, offset: 0, count: 0 });
+                self.flatten(*left);
+                self.nodes[idx].offset = self.nodes.len();
+                self.flatten(*right);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        let mut stats = BVHStats { primitives: self.indices.len(), nodes: self.nodes.len(), ..Default::default() };
+        let Some(root) = self.nodes.first() else { return stats };
+        let root_area = root.bb.surface_area();
+        let mut cost = 0.;
+        let mut stack = vec![(0, 0)];
+        while let Some((idx, depth)) = stack.pop() {
+            let node = &self.nodes[idx];
+            let area = node.bb.surface_area() / root_area;
+            if node.is_leaf() {
+                stats.leaves += 1;
+                if stats.leaf_sizes.len() <= node.count {
+                    stats.leaf_sizes.resize(node.count + 1, 0);
+                }
+                stats.leaf_sizes[node.count] += 1;
+                if stats.depth_histogram.len() <= depth {
+                    stats.depth_histogram.resize(depth + 1, 0);
+                }
+                stats.depth_histogram[depth] += 1;
+                cost += node.count as f64 * area;
+            } else {
+                cost += RELATIVE_COST_TRAVERSAL * area;
+                stack.push((idx + 1, depth + 1));
+                stack.push((node.offset, depth + 1));
+            }
+        }
+        stats.max_depth = stats.depth_histogram.len();
+        stats.sah_cost = if cost.is_finite() { cost } else { 0. };
+        stats
+    }
+}
@@ -1,6 +1,8 @@
 use std::cmp;
 use std::ops;
 use crate::EPSILON;
+use crate::Instance;
+use crate::Material;
 use crate::Ray;
 use crate::Shape;
 use crate::Tuple;
@@ -14,9 +16,31 @@ use crate::reflect;
 pub struct Intersection<'a> {
     pub t: f64,
     pub object: &'a Shape,
-    // Only used by triangles.
+    // Used by triangles (barycentric coordinates), lathes (profile segment index), and for
+    // surface coordinates on patches, disks and quads.
     pub u: f64,
     pub v: f64,
+    // Set when the object belongs to an instance's shared prototype.
+    pub instance: Option<&'a Shape>,
+}
+
+impl<'a> Intersection<'a> {
+    pub fn material(&self) -> &'a Material {
+        match self.instance.and_then(|s| s.as_instance()) {
+            Some(Instance { material: Some(m), .. }) => m,
+            _ => &self.object.material,
+        }
+    }
+
+    pub fn casts_shadow(&self) -> bool {
+        self.object.shadow && self.instance.is_none_or(|s| s.shadow)
+    }
+
+    // Two intersections are with the same surface if they hit the same shape in the same instance.
+    pub fn same_surface(&self, other: &Intersection) -> bool {
+        std::ptr::eq(self.object, other.object) &&
+        self.instance.map(|s| s as *const Shape) == other.instance.map(|s| s as *const Shape)
+    }
 }
 
 impl cmp::PartialEq<f64> for Intersection<'_> {
@@ -30,7 +54,7 @@ pub fn intersection(t: f64, object: &Shape) -> Intersection {
 }
 
 pub fn intersection_with_uv(t: f64, object: &Shape, u: f64, v: f64) -> Intersection {
-    Intersection { t, object, u, v }
+    Intersection { t, object, u, v, instance: None }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -44,7 +68,7 @@ impl Intersections<'_> {
         self.data.iter().find(|i| i.t > 0.)
     }
     pub fn hit_for_shadow(&self) -> Option<&Intersection> {
-        self.data.iter().find(|i| i.t > 0. && i.object.shadow)
+        self.data.iter().find(|i| i.t > 0. && i.casts_shadow())
     }
     pub fn is_empty(&self) -> bool {
         self.count == 0
@@ -93,6 +117,8 @@ pub fn hit<'a>(is: &'a Intersections) -> Option<Intersection<'a>> {
 pub struct C<'a> {
     pub t: f64,
     pub object: &'a Shape,
+    pub instance: Option<&'a Shape>,
+    pub material: &'a Material,
     pub point: Tuple,
     pub eyev: Tuple,
     pub normalv: Tuple,
@@ -130,23 +156,22 @@ pub fn prepare_computations3<'a>(intersection: &'a Intersection, ray: &Ray, inte
 
     let mut n1 = 1.;
     let mut n2 = 1.;
-    let mut containers: Vec<&Shape> = Vec::new();
+    let mut containers: Vec<&Intersection> = Vec::new();
     for i in &intersections.data {
         let matching = std::ptr::eq(intersection, i);
         if matching {
             if let Some(last) = containers.last() {
-                n1 = last.material.refractive_index;
+                n1 = last.material().refractive_index;
             }
         }
-        let o = i.object;
-        if let Some(index) = containers.iter().position(|value| std::ptr::eq(o, *value)) {
+        if let Some(index) = containers.iter().position(|value| i.same_surface(value)) {
             containers.swap_remove(index);
         } else {
-            containers.push(o);
+            containers.push(i);
         }
         if matching {
             if let Some(last) = containers.last() {
-                n2 = last.material.refractive_index;
+                n2 = last.material().refractive_index;
             }
             break;
         }
@@ -155,6 +180,8 @@ pub fn prepare_computations3<'a>(intersection: &'a Intersection, ray: &Ray, inte
     C {
         t: intersection.t,
         object: &intersection.object,
+        instance: intersection.instance,
+        material: intersection.material(),
         point,
         eyev,
         normalv,
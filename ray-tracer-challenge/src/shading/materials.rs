@@ -1,4 +1,4 @@
-use crate::{Color, Light, Tuple, dot, normalize, BLACK, reflect, Pattern, pattern_at, pattern_at_shape, Shape};
+use crate::{Color, Light, Tuple, dot, normalize, BLACK, reflect, Pattern, pattern_at, pattern_at_shape, Shape, world_to_object};
 
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct Material {
@@ -45,9 +45,16 @@ pub fn lighting6(material: &Material, light: &Light, position: &Tuple, eyev: &Tu
 }
 
 pub fn lighting7(material: &Material, object: Option<&Shape>, light: &Light, position: &Tuple, eyev: &Tuple, normalv: &Tuple, in_shadow: &bool) -> Color {
+    lighting8(material, object, None, light, position, eyev, normalv, in_shadow)
+}
+
+// Patterns on instanced shapes are evaluated in the shape's space within the prototype.
+#[allow(clippy::too_many_arguments)]
+pub fn lighting8(material: &Material, object: Option<&Shape>, instance: Option<&Shape>, light: &Light, position: &Tuple, eyev: &Tuple, normalv: &Tuple, in_shadow: &bool) -> Color {
     let color = if let Some(p) = material.pattern {
         if let Some(o) = object {
-            pattern_at_shape(&p, o, position)
+            let prototype_position = instance.map_or(*position, |s| world_to_object(s, position));
+            pattern_at_shape(&p, o, &prototype_position)
         } else {
             pattern_at(&p, position)
         }
@@ -4,6 +4,9 @@ pub use materials::*;
 pub mod world;
 pub use world::*;
 
+pub mod scene_graph;
+pub use scene_graph::*;
+
 pub mod intersections;
 pub use intersections::*;
 
@@ -12,3 +15,9 @@ pub use patterns::*;
 
 pub mod bounds;
 pub use bounds::*;
+
+pub mod bvh_build;
+pub use bvh_build::*;
+
+pub mod packets;
+pub use packets::*;
@@ -0,0 +1,272 @@
+use crate::{Ray, Matrix, BoundingBox, LinearBVH, Shape, ShapeType, Intersection, World, Triangle, SmoothTriangle, ray, point, vector, intersection, intersection_with_uv, Tuple, EPSILON};
+
+pub const PACKET_SIZE: usize = 4;
+
+type Lanes<T> = [T; PACKET_SIZE];
+
+/// Up to PACKET_SIZE rays stored as a struct of arrays, so the same operation on every lane
+/// compiles to vector instructions. That only happens for loops over lanes without branches, so
+/// those compute every lane and combine masks with `&` rather than returning early. Unused lanes
+/// are inactive.
+#[derive(Debug, Clone, Copy)]
+pub struct RayPacket {
+    // Indexed by axis, then lane.
+    pub origin: [Lanes<f64>; 3],
+    pub direction: [Lanes<f64>; 3],
+    pub active: Lanes<bool>,
+}
+
+impl RayPacket {
+    pub fn new(rays: &[Ray]) -> RayPacket {
+        assert!(rays.len() <= PACKET_SIZE);
+        let mut p = RayPacket {
+            origin: [[0.; PACKET_SIZE]; 3],
+            // Inactive lanes get a harmless direction, to keep their math finite.
+            direction: [[1.; PACKET_SIZE]; 3],
+            active: [false; PACKET_SIZE],
+        };
+        for (lane, r) in rays.iter().enumerate() {
+            for axis in 0..3 {
+                p.origin[axis][lane] = r.origin[axis];
+                p.direction[axis][lane] = r.direction[axis];
+            }
+            p.active[lane] = true;
+        }
+        p
+    }
+
+    pub fn ray(&self, lane: usize) -> Ray {
+        ray(
+            &point(self.origin[0][lane], self.origin[1][lane], self.origin[2][lane]),
+            &vector(self.direction[0][lane], self.direction[1][lane], self.direction[2][lane]),
+        )
+    }
+
+    pub fn transform(&self, m: &Matrix) -> RayPacket {
+        let mut p = *self;
+        for row in 0..3 {
+            for lane in 0..PACKET_SIZE {
+                let (o, d) = (&self.origin, &self.direction);
+                p.origin[row][lane] = m[(row, 0)] * o[0][lane] + m[(row, 1)] * o[1][lane] + m[(row, 2)] * o[2][lane] + m[(row, 3)];
+                p.direction[row][lane] = m[(row, 0)] * d[0][lane] + m[(row, 1)] * d[1][lane] + m[(row, 2)] * d[2][lane];
+            }
+        }
+        p
+    }
+}
+
+/// The closest hit (with t >= 0) found so far for each lane of a packet.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketHits<'a> {
+    pub hits: Lanes<Option<Intersection<'a>>>,
+}
+
+impl<'a> PacketHits<'a> {
+    pub fn new() -> PacketHits<'a> {
+        PacketHits { hits: [None; PACKET_SIZE] }
+    }
+
+    pub fn t(&self, lane: usize) -> f64 {
+        self.hits[lane].map_or(f64::INFINITY, |x| x.t)
+    }
+
+    pub fn add(&mut self, lane: usize, xs: &[Intersection<'a>]) {
+        for x in xs {
+            if 0. <= x.t && x.t < self.t(lane) {
+                self.hits[lane] = Some(*x);
+            }
+        }
+    }
+}
+
+impl Default for PacketHits<'_> {
+    fn default() -> Self {
+        PacketHits::new()
+    }
+}
+
+impl BoundingBox {
+    /// Slab test for every lane at once. Matches `local_intersect_tmin_tmax` lane by lane.
+    pub fn intersect_packet(&self, p: &RayPacket) -> (Lanes<bool>, Lanes<f64>, Lanes<f64>) {
+        let mut tmin = [f64::NEG_INFINITY; PACKET_SIZE];
+        let mut tmax = [f64::INFINITY; PACKET_SIZE];
+        for axis in 0..3 {
+            let (low, high) = (self.min[axis], self.max[axis]);
+            for lane in 0..PACKET_SIZE {
+                let (o, d) = (p.origin[axis][lane], p.direction[axis][lane]);
+                // Same as check_axis, which treats tiny directions as parallel and multiplies by
+                // infinity. Dividing by +0 gives the same infinities and NaNs.
+                let d = if d.abs() >= EPSILON { d } else { 0. };
+                let (t0, t1) = ((low - o) / d, (high - o) / d);
+                let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+                tmin[lane] = tmin[lane].max(t0);
+                tmax[lane] = tmax[lane].min(t1);
+            }
+        }
+        let mut hit = [false; PACKET_SIZE];
+        for lane in 0..PACKET_SIZE {
+            // Written like this so NaNs count as hits, as in the scalar version.
+            let (t0, t1) = (tmin[lane], tmax[lane]);
+            hit[lane] = p.active[lane] & ((t0 <= t1) | t0.is_nan() | t1.is_nan());
+        }
+        (hit, tmin, tmax)
+    }
+}
+
+// Möller–Trumbore for every lane, returning (t, u, v) where the lane hits.
+fn intersect_triangle_packet(p1: &Tuple, e1: &Tuple, e2: &Tuple, p: &RayPacket, mask: &Lanes<bool>) -> Lanes<Option<(f64, f64, f64)>> {
+    let (mut t, mut u, mut v) = ([0.; PACKET_SIZE], [0.; PACKET_SIZE], [0.; PACKET_SIZE]);
+    let mut hit = [false; PACKET_SIZE];
+    for lane in 0..PACKET_SIZE {
+        let (dx, dy, dz) = (p.direction[0][lane], p.direction[1][lane], p.direction[2][lane]);
+        let dir_cross_e2 = (dy * e2.z - dz * e2.y, dz * e2.x - dx * e2.z, dx * e2.y - dy * e2.x);
+        let det = e1.x * dir_cross_e2.0 + e1.y * dir_cross_e2.1 + e1.z * dir_cross_e2.2;
+        let f = 1. / det;
+        let po = (p.origin[0][lane] - p1.x, p.origin[1][lane] - p1.y, p.origin[2][lane] - p1.z);
+        u[lane] = f * (po.0 * dir_cross_e2.0 + po.1 * dir_cross_e2.1 + po.2 * dir_cross_e2.2);
+        let origin_cross_e1 = (po.1 * e1.z - po.2 * e1.y, po.2 * e1.x - po.0 * e1.z, po.0 * e1.y - po.1 * e1.x);
+        v[lane] = f * (dx * origin_cross_e1.0 + dy * origin_cross_e1.1 + dz * origin_cross_e1.2);
+        t[lane] = f * (e2.x * origin_cross_e1.0 + e2.y * origin_cross_e1.1 + e2.z * origin_cross_e1.2);
+        // Same tests as the scalar version, which rejects nearly parallel rays via `equal(det, 0.)`.
+        hit[lane] = mask[lane] & (det.abs() >= EPSILON) & (0. <= u[lane]) & (u[lane] <= 1.) & (0. <= v[lane]) & (u[lane] + v[lane] <= 1.);
+    }
+    std::array::from_fn(|lane| if hit[lane] { Some((t[lane], u[lane], v[lane])) } else { None })
+}
+
+impl Triangle {
+    pub fn intersect_packet(&self, p: &RayPacket, mask: &Lanes<bool>) -> Lanes<Option<f64>> {
+        intersect_triangle_packet(&self.p1, &self.e1, &self.e2, p, mask).map(|x| x.map(|(t, _, _)| t))
+    }
+}
+
+impl SmoothTriangle {
+    pub fn intersect_packet(&self, p: &RayPacket, mask: &Lanes<bool>) -> Lanes<Option<(f64, f64, f64)>> {
+        intersect_triangle_packet(&self.p1, &self.e1, &self.e2, p, mask)
+    }
+}
+
+impl LinearBVH {
+    /// Walks the BVH once for a whole packet, visiting a node if any lane might still find a
+    /// closer hit in it. `visit` gets each primitive index along with the lanes that reached it.
+    pub fn traverse_packet<'a, F>(&self, p: &RayPacket, hits: &mut PacketHits<'a>, mut visit: F)
+    where F: FnMut(usize, &Lanes<bool>, &mut PacketHits<'a>) {
+        let lanes = |bb: &BoundingBox, hits: &PacketHits| {
+            let (hit, tmin, tmax) = bb.intersect_packet(p);
+            let mut mask = [false; PACKET_SIZE];
+            let mut nearest = f64::INFINITY;
+            for lane in 0..PACKET_SIZE {
+                mask[lane] = hit[lane] & (tmin[lane] <= hits.t(lane)) & (0. <= tmax[lane]);
+                nearest = nearest.min(if mask[lane] { tmin[lane] } else { f64::INFINITY });
+            }
+            (mask, nearest)
+        };
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            let (mask, _) = lanes(&node.bb, hits);
+            if !mask.contains(&true) {
+                continue;
+            }
+            if node.is_leaf() {
+                for &i in &self.indices[node.offset..node.offset + node.count] {
+                    visit(i, &mask, hits);
+                }
+                continue;
+            }
+            let (left, right) = (idx + 1, node.offset);
+            let (_, l_tmin) = lanes(&self.nodes[left].bb, hits);
+            let (_, r_tmin) = lanes(&self.nodes[right].bb, hits);
+            // Visit the closer child first, by pushing it last. Nodes are checked again when popped.
+            if l_tmin > r_tmin {
+                stack.extend([left, right]);
+            } else {
+                stack.extend([right, left]);
+            }
+        }
+    }
+}
+
+impl Shape {
+    /// Closest hits for a packet of rays in this shape's parent space. Triangles, smooth triangles,
+    /// groups and instances are handled a packet at a time, other shapes fall back to one ray at a time.
+    pub fn intersect_closest_hit_packet<'a>(&'a self, p: &RayPacket, mask: &Lanes<bool>, hits: &mut PacketHits<'a>) {
+        let mut p = p.transform(&self.local_inverse());
+        // Lanes that missed our parent's bounds don't need to look any further.
+        p.active = *mask;
+        self.local_intersect_closest_hit_packet(&p, mask, hits);
+    }
+
+    fn local_intersect_closest_hit_packet<'a>(&'a self, p: &RayPacket, mask: &Lanes<bool>, hits: &mut PacketHits<'a>) {
+        match &self.shape_type {
+            ShapeType::Triangle(t) => {
+                for (lane, x) in t.intersect_packet(p, mask).into_iter().enumerate() {
+                    if let Some(t) = x {
+                        hits.add(lane, &[intersection(t, self)]);
+                    }
+                }
+            }
+            ShapeType::SmoothTriangle(t) => {
+                for (lane, x) in t.intersect_packet(p, mask).into_iter().enumerate() {
+                    if let Some((t, u, v)) = x {
+                        hits.add(lane, &[intersection_with_uv(t, self, u, v)]);
+                    }
+                }
+            }
+            ShapeType::Group(g) => {
+                if let Some(bvh) = &g.bvh {
+                    bvh.traverse_packet(p, hits, |i, mask, hits| g.children[i].intersect_closest_hit_packet(p, mask, hits));
+                } else {
+                    let (hit, _, _) = g.bb.intersect_packet(p);
+                    let mask = std::array::from_fn(|lane| mask[lane] && hit[lane]);
+                    for c in &g.children {
+                        c.intersect_closest_hit_packet(p, &mask, hits);
+                    }
+                }
+            }
+            ShapeType::Instance(inst) => {
+                let mut inner = PacketHits::new();
+                inst.prototype.intersect_closest_hit_packet(p, mask, &mut inner);
+                for (lane, x) in inner.hits.into_iter().enumerate() {
+                    if let Some(x) = x {
+                        hits.add(lane, &[Intersection { instance: Some(self), ..x }]);
+                    }
+                }
+            }
+            _ => {
+                for (lane, active) in mask.iter().enumerate() {
+                    if *active {
+                        hits.add(lane, &self.local_intersect_closest_hit(&p.ray(lane)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl World {
+    /// Closest hits for up to PACKET_SIZE rays, like calling `intersect_closest_hit` on each.
+    pub fn intersect_closest_hit_packet(&self, rays: &[Ray]) -> Vec<Option<Intersection<'_>>> {
+        self.intersect_closest_hit_packet_in_csg(rays).into_iter().map(|(x, _)| x).collect()
+    }
+
+    /// Like `intersect_closest_hit_packet`, but also says for each ray whether its hit is on an
+    /// object containing a CSG, as `intersect_closest_hit_in_csg` does.
+    pub fn intersect_closest_hit_packet_in_csg(&self, rays: &[Ray]) -> Vec<(Option<Intersection<'_>>, bool)> {
+        let p = RayPacket::new(rays);
+        let mut hits = PacketHits::new();
+        let mut in_csg = [false; PACKET_SIZE];
+        self.visit_objects_packet(&p, &mut hits, |i, mask, hits| {
+            let before: Lanes<f64> = std::array::from_fn(|lane| hits.t(lane));
+            self.objects[i].intersect_closest_hit_packet(&p, mask, hits);
+            for lane in 0..PACKET_SIZE {
+                if hits.t(lane) < before[lane] {
+                    in_csg[lane] = self.object_in_csg(i);
+                }
+            }
+        });
+        (0..rays.len()).map(|lane| (hits.hits[lane], in_csg[lane])).collect()
+    }
+}
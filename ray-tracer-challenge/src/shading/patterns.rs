@@ -17,14 +17,32 @@ pub struct Pattern {
     pub a: Color,
     pub b: Color,
     pub pattern_type: PatternType,
+    // Patterns used instead of `a` and `b`.
+    pub nested: Option<[NestedPattern; 2]>,
     transform: Matrix,
     inverse: Matrix,
 }
 
+/// A pattern used instead of one of the colors of another. These can't be nested any further,
+/// which keeps patterns, and materials, `Copy`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct NestedPattern {
+    pub a: Color,
+    pub b: Color,
+    pub pattern_type: PatternType,
+    transform: Matrix,
+    inverse: Matrix,
+}
+
+impl NestedPattern {
+    pub fn transform(&self) -> Matrix { self.transform }
+    pub fn inverse(&self) -> Matrix { self.inverse }
+}
+
 
 impl Pattern {
     pub fn new(pattern_type: PatternType, a: &Color, b: &Color) -> Pattern {
-        Pattern { transform: identity_matrix, inverse: identity_matrix, a: *a, b: *b, pattern_type }
+        Pattern { transform: identity_matrix, inverse: identity_matrix, a: *a, b: *b, pattern_type, nested: None }
     }
     pub fn transform(&self) -> Matrix { self.transform }
     pub fn inverse(&self) -> Matrix { self.inverse }
@@ -43,9 +61,19 @@ pub fn floor_i64(x: f64) -> i64 {
 }
 
 pub fn pattern_at(pattern: &Pattern, point: &Tuple) -> Color {
-    let a = pattern.a;
-    let b = pattern.b;
-    match pattern.pattern_type {
+    let (a, b) = match &pattern.nested {
+        // Nested patterns are in the space of the pattern they're in.
+        Some([a, b]) => (
+            colors_at(a.pattern_type, a.a, a.b, &(a.inverse() * *point)),
+            colors_at(b.pattern_type, b.a, b.b, &(b.inverse() * *point)),
+        ),
+        None => (pattern.a, pattern.b),
+    };
+    colors_at(pattern.pattern_type, a, b, point)
+}
+
+fn colors_at(pattern_type: PatternType, a: Color, b: Color, point: &Tuple) -> Color {
+    match pattern_type {
         PatternType::TestPattern => color(point.x, point.y, point.z),
         PatternType::Stripe => if floor_i64(point.x).rem(2) == 0 { a } else { b }
         PatternType::Gradient => a + (b - a) * (point.x - point.x.floor()),
@@ -88,3 +116,17 @@ pub fn gradient_pattern(color1: &Color, color2: &Color) -> Pattern {
 pub fn ring_pattern(color1: &Color, color2: &Color) -> Pattern {
     Pattern::new(PatternType::Ring, color1, color2)
 }
+
+/// A pattern like `pattern`, with `a` and `b` instead of its colors. Its colors become the
+/// average of each nested pattern's, for anything that needs one color. Nested patterns can't have
+/// patterns in them themselves.
+pub fn nested_pattern(pattern: &Pattern, a: &Pattern, b: &Pattern) -> Pattern {
+    assert!(a.nested.is_none() && b.nested.is_none(), "patterns can only be nested one level deep");
+    let nest = |p: &Pattern| NestedPattern { a: p.a, b: p.b, pattern_type: p.pattern_type, transform: p.transform, inverse: p.inverse };
+    Pattern {
+        a: (a.a + a.b) * 0.5,
+        b: (b.a + b.b) * 0.5,
+        nested: Some([nest(a), nest(b)]),
+        ..*pattern
+    }
+}
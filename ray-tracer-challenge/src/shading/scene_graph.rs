@@ -0,0 +1,180 @@
+use crate::{World, Shape, ShapeType, Matrix, Material, Group, CSG, add_child, LinearBVH};
+
+/// Refers to a shape in a `SceneGraph`. Handles stay valid as other shapes are added and
+/// removed, until the shape itself is removed.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct NodeHandle(usize);
+
+/// A world we can keep changing after it's built, for previews and animation. Groups that were
+/// frozen keep their BVHs, which get refit as children move, rather than rebuilt.
+///
+/// Handles track where shapes are, so changing `world.objects` or groups directly (other than
+/// materials) invalidates them.
+pub struct SceneGraph {
+    pub world: World,
+    // For each handle, the child indices leading to its shape from `world.objects`, or None once
+    // it's been removed.
+    paths: Vec<Option<Vec<usize>>>,
+}
+
+fn node<'a>(objects: &'a [Shape], path: &[usize]) -> &'a Shape {
+    let mut s = &objects[path[0]];
+    for &i in &path[1..] {
+        s = &s.children()[i];
+    }
+    s
+}
+
+fn node_mut<'a>(objects: &'a mut [Shape], path: &[usize]) -> &'a mut Shape {
+    let mut s = &mut objects[path[0]];
+    for &i in &path[1..] {
+        s = &mut s.as_group_mut().unwrap().children[i];
+    }
+    s
+}
+
+fn set_material_recursive(s: &mut Shape, m: &Material) {
+    s.material = *m;
+    match &mut s.shape_type {
+        ShapeType::Group(Group { children, .. }) | ShapeType::CSG(CSG { children, .. }) => {
+            for c in children {
+                set_material_recursive(c, m);
+            }
+        }
+        // Other instances share the prototype, so we override its materials instead.
+        ShapeType::Instance(inst) => inst.material = Some(*m),
+        _ => {}
+    }
+}
+
+impl SceneGraph {
+    /// Takes over a world, handing out handles for its objects and everything in their groups.
+    pub fn new(world: World) -> SceneGraph {
+        let mut graph = SceneGraph { world, paths: vec![] };
+        for i in 0..graph.world.objects.len() {
+            graph.register(vec![i]);
+        }
+        graph
+    }
+
+    // Adds handles for the shape at `path` and everything under it, returning the shape's handle.
+    fn register(&mut self, path: Vec<usize>) -> NodeHandle {
+        let h = NodeHandle(self.paths.len());
+        let count = node(&self.world.objects, &path).as_group().map_or(0, |g| g.children.len());
+        self.paths.push(Some(path.clone()));
+        for i in 0..count {
+            let mut child = path.clone();
+            child.push(i);
+            self.register(child);
+        }
+        h
+    }
+
+    fn path(&self, h: NodeHandle) -> Vec<usize> {
+        self.paths[h.0].clone().expect("shape was removed")
+    }
+
+    fn handles_where<F: Fn(&[usize]) -> bool>(&self, f: F) -> Vec<NodeHandle> {
+        self.paths.iter().enumerate()
+            .filter(|(_, p)| p.as_deref().is_some_and(&f))
+            .map(|(i, _)| NodeHandle(i))
+            .collect()
+    }
+
+    pub fn contains(&self, h: NodeHandle) -> bool {
+        self.paths.get(h.0).is_some_and(|p| p.is_some())
+    }
+
+    pub fn get(&self, h: NodeHandle) -> Option<&Shape> {
+        let path = self.paths.get(h.0)?.as_ref()?;
+        Some(node(&self.world.objects, path))
+    }
+
+    /// Handles for the world's objects, in order.
+    pub fn roots(&self) -> Vec<NodeHandle> {
+        let mut roots = self.handles_where(|p| p.len() == 1);
+        roots.sort_by_key(|h| self.paths[h.0].as_ref().unwrap()[0]);
+        roots
+    }
+
+    /// Handles for a group's children, in order.
+    pub fn children(&self, h: NodeHandle) -> Vec<NodeHandle> {
+        let path = self.path(h);
+        let mut children = self.handles_where(|p| p.len() == path.len() + 1 && p.starts_with(&path));
+        children.sort_by_key(|c| *self.paths[c.0].as_ref().unwrap().last().unwrap());
+        children
+    }
+
+    pub fn add(&mut self, shape: &Shape) -> NodeHandle {
+        self.world.add(shape);
+        self.register(vec![self.world.objects.len() - 1])
+    }
+
+    /// Adds a copy of `shape` to a group. A frozen group gets its BVH rebuilt, since there's no
+    /// good place to put a new child in the old one.
+    pub fn add_child(&mut self, parent: NodeHandle, shape: &Shape) -> NodeHandle {
+        let path = self.path(parent);
+        let group = node_mut(self.world.objects.shapes_mut(), &path);
+        let bvh = group.as_group_mut().expect("can only add children to groups").bvh.take();
+        add_child(group, shape);
+        let g = group.as_group_mut().unwrap();
+        if bvh.is_some() {
+            g.bvh = Some(LinearBVH::build_for_shapes(&g.children));
+        }
+        let mut child = path.clone();
+        child.push(g.children.len() - 1);
+        self.refit_ancestors(&child);
+        // The new shape may be a CSG, which the world needs to know about.
+        self.world.refit_bvh();
+        self.register(child)
+    }
+
+    /// Moves a shape, refitting the bounds of the groups it's in.
+    pub fn set_transform(&mut self, h: NodeHandle, m: &Matrix) {
+        let path = self.path(h);
+        node_mut(self.world.objects.shapes_mut(), &path).set_transform(m);
+        self.refit_ancestors(&path);
+    }
+
+    /// Changes the material of a shape, and everything in it if it's a group or CSG. Instances get
+    /// it as their material override. No bounds change.
+    pub fn set_material(&mut self, h: NodeHandle, m: &Material) {
+        let path = self.path(h);
+        set_material_recursive(node_mut(self.world.objects.shapes_mut(), &path), m);
+    }
+
+    /// Takes a shape out of the world, along with everything in it, returning it.
+    pub fn remove(&mut self, h: NodeHandle) -> Shape {
+        let path = self.path(h);
+        let (&last, parent) = path.split_last().unwrap();
+        let shape = if parent.is_empty() {
+            self.world.objects.remove(last)
+        } else {
+            let s = node_mut(self.world.objects.shapes_mut(), parent).as_group_mut().unwrap().remove_child(last);
+            self.refit_ancestors(parent);
+            // The removed shape may have been the only CSG in its object.
+            self.world.refit_bvh();
+            s
+        };
+        // Forget the shape and what's under it, and shift its later siblings down.
+        for p in &mut self.paths {
+            if p.as_ref().is_some_and(|p| p.starts_with(&path)) {
+                *p = None;
+            } else if let Some(p) = p {
+                if p.len() > parent.len() && p.starts_with(parent) && p[parent.len()] > last {
+                    p[parent.len()] -= 1;
+                }
+            }
+        }
+        shape
+    }
+
+    // Refits every group above the shape at `path`, and then the world's BVH, only along the way
+    // from the shape to the root.
+    fn refit_ancestors(&mut self, path: &[usize]) {
+        for depth in (1..path.len()).rev() {
+            node_mut(self.world.objects.shapes_mut(), &path[..depth]).as_group_mut().unwrap().refit_child(path[depth]);
+        }
+        self.world.refit_object(path[0]);
+    }
+}
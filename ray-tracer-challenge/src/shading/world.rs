@@ -1,9 +1,56 @@
-use crate::{Light, Intersections, Ray, Shape, Color, C, Tuple, point_light, point, sphere, color, scaling, intersections, BLACK, magnitude, normalize, ray, lighting7, dot, schlick, prepare_computations3};
+use std::{ops::{Deref, DerefMut}, sync::OnceLock, time::Instant};
+
+use crate::{BoundingBox, BVHStats, BVHTraversalPolicy, Intersection, LinearBVH, RayPacket, PacketHits, PACKET_SIZE, Light, Intersections, Ray, Shape, Color, C, Tuple, point_light, point, sphere, color, scaling, intersections, BLACK, magnitude, normalize, ray, lighting8, dot, schlick, prepare_computations3};
 
 pub struct World {
     pub count: usize,
     pub lights: Vec<Light>,
-    pub objects: Vec<Shape>,
+    pub objects: Objects,
+}
+
+/// The objects in a world, used like a `Vec<Shape>`. The world's top-level BVH over them lives
+/// here too, so changing them in any way drops it, and it's rebuilt on the next intersection.
+pub struct Objects {
+    shapes: Vec<Shape>,
+    // Built on first use after objects change.
+    bvh: OnceLock<TopLevelBVH>,
+}
+
+impl Objects {
+    fn new(shapes: Vec<Shape>) -> Objects {
+        Objects { shapes, bvh: OnceLock::new() }
+    }
+
+    fn bvh(&self) -> &TopLevelBVH {
+        self.bvh.get_or_init(|| TopLevelBVH::build(&self.shapes))
+    }
+
+    // Lets the scene graph move shapes while keeping the BVH, which it then refits.
+    pub(crate) fn shapes_mut(&mut self) -> &mut [Shape] {
+        &mut self.shapes
+    }
+}
+
+impl Deref for Objects {
+    type Target = Vec<Shape>;
+    fn deref(&self) -> &Vec<Shape> {
+        &self.shapes
+    }
+}
+
+impl DerefMut for Objects {
+    fn deref_mut(&mut self) -> &mut Vec<Shape> {
+        self.bvh = OnceLock::new();
+        &mut self.shapes
+    }
+}
+
+impl<'a> IntoIterator for &'a Objects {
+    type Item = &'a Shape;
+    type IntoIter = std::slice::Iter<'a, Shape>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.shapes.iter()
+    }
 }
 
 impl World {
@@ -14,6 +61,45 @@ impl World {
         self.lights.push(*light);
     }
 
+    /// Drops the top-level BVH, so it's rebuilt on the next intersection. Changing `objects`
+    /// does this for us.
+    pub fn rebuild_bvh(&mut self) {
+        self.objects.bvh = OnceLock::new();
+    }
+
+    // Updates the top-level BVH after shapes moved through `Objects::shapes_mut`, refitting it
+    // rather than rebuilding it when we can.
+    pub(crate) fn refit_bvh(&mut self) {
+        let objects = &mut self.objects;
+        if let Some(bvh) = objects.bvh.get_mut() {
+            if !bvh.refit(&objects.shapes) {
+                self.rebuild_bvh();
+            }
+        }
+    }
+
+    // Like `refit_bvh`, after only `objects[i]` moved, so only the boxes above it change.
+    pub(crate) fn refit_object(&mut self, i: usize) {
+        let objects = &mut self.objects;
+        if let Some(bvh) = objects.bvh.get_mut() {
+            if !bvh.refit_object(&objects.shapes, i) {
+                self.rebuild_bvh();
+            }
+        }
+    }
+
+    /// Stats for the top-level BVH over the bounded objects, building it if it hasn't been.
+    /// `build_time` is only set when it's built here.
+    pub fn bvh_stats(&self) -> BVHStats {
+        let now = Instant::now();
+        let built_here = self.objects.bvh.get().is_none();
+        let mut stats = self.objects.bvh().bvh.stats();
+        if built_here {
+            stats.build_time = now.elapsed();
+        }
+        stats
+    }
+
     // for testing
     pub fn light(&self) -> Light {
         *self.lights.first().unwrap()
@@ -22,20 +108,132 @@ impl World {
         self.lights = vec![*light];
     }
 
-    pub fn intersect(&self, ray: &Ray) -> Intersections {
-        let mut v = vec![];
-        for o in &self.objects {
-            v.append(&mut o.intersect(ray));
+    // Calls `visit` with the index of every object the ray might hit, as far as the policy cares.
+    fn visit_objects<'a, F>(&'a self, ray: &Ray, p: &mut BVHTraversalPolicy<'a>, mut visit: F)
+    where F: FnMut(usize, &mut BVHTraversalPolicy<'a>) {
+        let bvh = self.objects.bvh();
+        for &i in &bvh.unbounded {
+            visit(i, p);
         }
-        intersections(v)
+        bvh.bvh.traverse(ray, p, |i, p| visit(bvh.bounded[i], p));
+    }
+
+    fn intersect_with_policy<'a>(&'a self, ray: &Ray, p: &mut BVHTraversalPolicy<'a>) {
+        self.visit_objects(ray, p, |i, p| p.intersect_child(&self.objects[i], ray));
+    }
+
+    pub(crate) fn visit_objects_packet<'a, F>(&'a self, p: &RayPacket, hits: &mut PacketHits<'a>, mut visit: F)
+    where F: FnMut(usize, &[bool; PACKET_SIZE], &mut PacketHits<'a>) {
+        let bvh = self.objects.bvh();
+        for &i in &bvh.unbounded {
+            visit(i, &p.active, hits);
+        }
+        bvh.bvh.traverse_packet(p, hits, |i, mask, hits| visit(bvh.bounded[i], mask, hits));
+    }
+
+    // Whether `objects[i]` contains a CSG.
+    pub(crate) fn object_in_csg(&self, i: usize) -> bool {
+        self.objects.bvh().csg[i]
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Intersections {
+        let mut p = BVHTraversalPolicy::new_all_hits();
+        self.intersect_with_policy(ray, &mut p);
+        intersections(p.intersections())
     }
 
     pub fn intersect_closest_hit(&self, ray: &Ray) -> Intersections {
-        let mut v = vec![];
-        for o in &self.objects {
-            v.append(&mut o.intersect_closest_hit(ray));
+        let mut p = BVHTraversalPolicy::new_closest_hit();
+        self.intersect_with_policy(ray, &mut p);
+        intersections(p.intersections())
+    }
+
+    /// Like `intersect_closest_hit`, but also says whether the hit is on an object containing a CSG.
+    pub fn intersect_closest_hit_in_csg(&self, ray: &Ray) -> (Intersections<'_>, bool) {
+        let mut p = BVHTraversalPolicy::new_closest_hit();
+        let mut in_csg = false;
+        self.visit_objects(ray, &mut p, |i, p| {
+            let t = p.closest_t();
+            p.intersect_child(&self.objects[i], ray);
+            if p.closest_t() < t {
+                in_csg = self.object_in_csg(i);
+            }
+        });
+        (intersections(p.intersections()), in_csg)
+    }
+
+    /// Any shadow casting hit with 0 < t < t_max, stopping at the first one found.
+    pub fn intersect_occluder(&self, ray: &Ray, t_max: f64) -> Option<Intersection<'_>> {
+        let mut p = BVHTraversalPolicy::new_any_hit_before(t_max);
+        self.intersect_with_policy(ray, &mut p);
+        p.intersections().first().copied()
+    }
+
+    pub fn is_occluded(&self, ray: &Ray, t_max: f64) -> bool {
+        self.intersect_occluder(ray, t_max).is_some()
+    }
+}
+
+// The top level of a two-level BVH: a tree over world objects, each of which may have its own BVH
+// if it's a frozen group. We keep object indices rather than clones of the objects. Infinite
+// shapes like planes would make every box infinite, so we always test those separately.
+struct TopLevelBVH {
+    unbounded: Vec<usize>,
+    // Maps primitive indices in the BVH back to indices in `objects`.
+    bounded: Vec<usize>,
+    bvh: LinearBVH,
+    // Whether each object has a CSG somewhere inside it.
+    csg: Vec<bool>,
+}
+
+// Empty boxes come from test shapes, which we can't place either.
+fn is_placeable(bb: &BoundingBox) -> bool {
+    !bb.is_unbounded() && bb.min.x <= bb.max.x
+}
+
+impl TopLevelBVH {
+    fn build(objects: &[Shape]) -> TopLevelBVH {
+        let mut unbounded = vec![];
+        let mut bounded = vec![];
+        let mut items = vec![];
+        for (i, o) in objects.iter().enumerate() {
+            let bb = BoundingBox::from_transformed_shapes(&[o]);
+            if !is_placeable(&bb) {
+                unbounded.push(i);
+            } else {
+                items.push((bb, bb.centroid()));
+                bounded.push(i);
+            }
+        }
+        let csg = objects.iter().map(|o| o.contains_csg()).collect();
+        TopLevelBVH { unbounded, bounded, bvh: LinearBVH::build(&items), csg }
+    }
+
+    // Returns false if objects became unbounded, so we need a rebuild.
+    fn refit(&mut self, objects: &[Shape]) -> bool {
+        let mut bbs = vec![];
+        for &i in &self.bounded {
+            let bb = BoundingBox::from_transformed_shapes(&[&objects[i]]);
+            if !is_placeable(&bb) {
+                return false;
+            }
+            bbs.push(bb);
+        }
+        self.bvh.refit(&bbs);
+        self.csg = objects.iter().map(|o| o.contains_csg()).collect();
+        true
+    }
+
+    // Like `refit`, for when only objects[i] moved. Moving doesn't add or remove CSGs.
+    fn refit_object(&mut self, objects: &[Shape], i: usize) -> bool {
+        // Unbounded objects are always tested, wherever they are.
+        let Ok(k) = self.bounded.binary_search(&i) else { return true };
+        if !is_placeable(&BoundingBox::from_transformed_shapes(&[&objects[i]])) {
+            return false;
         }
-        intersections(v)
+        let bounded = &self.bounded;
+        self.bvh.refit_primitive(k, |j| BoundingBox::from_transformed_shapes(&[&objects[bounded[j]]]));
+        true
     }
 }
 
@@ -43,7 +241,7 @@ pub fn world() -> World {
     World {
         count: 0,
         lights: vec![],
-        objects: vec![],
+        objects: Objects::new(vec![]),
     }
 }
 
@@ -58,7 +256,7 @@ pub fn default_world() -> World {
     World {
         count: 2,
         lights: vec![light],
-        objects: vec![s1, s2],
+        objects: Objects::new(vec![s1, s2]),
     }
 }
 
@@ -73,11 +271,11 @@ pub fn shade_hit2(world: &World, comps: &C) -> Color {
 }
 
 pub fn shade_hit3(world: &World, comps: &C, remaining: usize) -> Color {
-    let material = &comps.object.material;
+    let material = comps.material;
     let mut surface = BLACK;
     for light in &world.lights {
         let shadowed = is_shadowed3(world, &comps.over_point, light);
-        surface = surface + lighting7(material, Some(&comps.object), light, &comps.over_point, &comps.eyev, &comps.normalv, &shadowed);
+        surface = surface + lighting8(material, Some(&comps.object), comps.instance, light, &comps.over_point, &comps.eyev, &comps.normalv, &shadowed);
     }
     let reflective = reflected_color3(world, comps, remaining);
     let refractive = refracted_color(world, comps, remaining);
@@ -94,18 +292,40 @@ pub fn color_at(world: &World, ray: &Ray) -> Color {
 }
 
 pub fn color_at3(world: &World, ray: &Ray, remaining: usize) -> Color {
-    // TODO Optimize this by first looking for the closest hit, and
-    // only getting all hits if the closest hit requires them.
-    // i.e., the closest hit is transparent or a CSG
+    // Most hits only need the closest intersection. Transparent hits need all of them to work out
+    // n1 and n2, and CSG hits come from filtering full lists, so those take the slow path.
+    let (closest, in_csg) = world.intersect_closest_hit_in_csg(ray);
+    match closest.hit() {
+        Some(i) if !in_csg && i.material().transparency == 0. => {
+            let comps = prepare_computations3(i, ray, &closest);
+            return shade_hit3(world, &comps, remaining);
+        }
+        // The closest hit may be at t = 0, which doesn't count, so we still look further.
+        None if closest.is_empty() => return BLACK,
+        _ => {}
+    }
     let is = intersect_world(world, ray);
     if let Some(i) = is.hit() {
-        let comps = prepare_computations3(&i, ray, &is);
+        let comps = prepare_computations3(i, ray, &is);
         shade_hit3(world, &comps, remaining)
     } else {
         BLACK
     }
 }
 
+/// Colors for up to PACKET_SIZE rays, like `color_at` on each, with the closest hits found a
+/// packet at a time. Rays whose hits need every intersection go the slow way, one at a time.
+pub fn color_at_packet(world: &World, rays: &[Ray]) -> Vec<Color> {
+    world.intersect_closest_hit_packet_in_csg(rays).into_iter().zip(rays).map(|((closest, in_csg), ray)| match closest {
+        Some(i) if i.t > 0. && !in_csg && i.material().transparency == 0. => {
+            let comps = prepare_computations3(&i, ray, &intersections(vec![i]));
+            shade_hit3(world, &comps, DEFAULT_REMAINING)
+        }
+        None => BLACK,
+        _ => color_at(world, ray),
+    }).collect()
+}
+
 pub fn is_shadowed(world: &World, point: &Tuple) -> bool {
     // For testing
     is_shadowed3(world, point, &world.light())
@@ -115,14 +335,7 @@ pub fn is_shadowed3(world: &World, point: &Tuple, light: &Light) -> bool {
     let to_light = light.position - *point;
     let distance = magnitude(&to_light);
     let ray = ray(point, &normalize(&to_light));
-    // We look for closest hit here, instead of looking for all hits.
-    // TODO Optimize this to look for any hit closer than `distance`
-    let xs = world.intersect_closest_hit(&ray);
-    if let Some(i) = xs.hit_for_shadow() {
-        i.t < distance
-    } else {
-        false
-    }
+    world.is_occluded(&ray, distance)
 }
 
 pub fn reflected_color2(world: &World, comps: &C) -> Color {
@@ -130,16 +343,16 @@ pub fn reflected_color2(world: &World, comps: &C) -> Color {
 }
 
 pub fn reflected_color3(world: &World, comps: &C, remaining: usize) -> Color {
-    if remaining == 0 || comps.object.material.reflective == 0. {
+    if remaining == 0 || comps.material.reflective == 0. {
         return BLACK;
     }
     let r = ray(&comps.over_point, &comps.reflectv);
     let c = color_at3(world, &r, remaining - 1);
-    c * comps.object.material.reflective
+    c * comps.material.reflective
 }
 
 pub fn refracted_color(world: &World, comps: &C, remaining: usize) -> Color {
-    if remaining == 0 || comps.object.material.transparency == 0. {
+    if remaining == 0 || comps.material.transparency == 0. {
         return BLACK;
     }
     let n_ratio = comps.n1 / comps.n2;
@@ -152,5 +365,5 @@ pub fn refracted_color(world: &World, comps: &C, remaining: usize) -> Color {
     let direction = comps.normalv * (n_ratio * cos_i - cos_t) -
         comps.eyev * n_ratio;
     let refract_ray = ray(&comps.under_point, &direction);
-    color_at3(world, &refract_ray, remaining - 1) * comps.object.material.transparency
+    color_at3(world, &refract_ray, remaining - 1) * comps.material.transparency
 }
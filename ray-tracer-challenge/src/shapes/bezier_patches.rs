@@ -0,0 +1,220 @@
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, vector, point, EPSILON, LocalShape, Intersection, BoundingBox, BaseBoundingBox, intersection_with_uv, group, add_child, smooth_triangle};
+
+// Resolution of the grid we intersect first, to get starting guesses for Newton's method.
+const GUESS_RESOLUTION: usize = 8;
+// How far outside a coarse triangle we still accept a guess. The coarse grid can sit inside the
+// true surface near silhouettes, so we need some slack there.
+const GUESS_SLACK: f64 = 0.1;
+const NEWTON_STEPS: usize = 16;
+const NEWTON_TOLERANCE: f64 = EPSILON * 1e-3;
+
+fn bernstein(t: f64) -> [f64; 4] {
+    let s = 1. - t;
+    [s * s * s, 3. * t * s * s, 3. * t * t * s, t * t * t]
+}
+
+fn bernstein_derivative(t: f64) -> [f64; 4] {
+    let s = 1. - t;
+    [-3. * s * s, 3. * s * s - 6. * t * s, 6. * t * s - 3. * t * t, 3. * t * t]
+}
+
+/// A bicubic Bézier patch. Control points are stored in rows of four, where moving along a row
+/// changes u and moving between rows changes v.
+#[derive(PartialEq, Debug, Clone)]
+pub struct BezierPatch {
+    pub control_points: [Tuple; 16],
+    // Surface points on a coarse grid, used for the initial guesses when intersecting.
+    grid: Vec<Tuple>,
+}
+
+impl BezierPatch {
+    pub fn new(control_points: &[Tuple; 16]) -> BezierPatch {
+        let mut p = BezierPatch { control_points: *control_points, grid: vec![] };
+        for j in 0..=GUESS_RESOLUTION {
+            for i in 0..=GUESS_RESOLUTION {
+                let (u, v) = (i as f64 / GUESS_RESOLUTION as f64, j as f64 / GUESS_RESOLUTION as f64);
+                p.grid.push(p.point_at(u, v));
+            }
+        }
+        p
+    }
+
+    fn sum(&self, bu: &[f64; 4], bv: &[f64; 4]) -> Tuple {
+        let mut rv = vector(0., 0., 0.);
+        for (j, bj) in bv.iter().enumerate() {
+            for (i, bi) in bu.iter().enumerate() {
+                let cp = self.control_points[j * 4 + i];
+                rv = rv + vector(cp.x, cp.y, cp.z) * (bi * bj);
+            }
+        }
+        rv
+    }
+
+    pub fn point_at(&self, u: f64, v: f64) -> Tuple {
+        let p = self.sum(&bernstein(u), &bernstein(v));
+        point(p.x, p.y, p.z)
+    }
+
+    pub fn derivatives_at(&self, u: f64, v: f64) -> (Tuple, Tuple) {
+        (
+            self.sum(&bernstein_derivative(u), &bernstein(v)),
+            self.sum(&bernstein(u), &bernstein_derivative(v)),
+        )
+    }
+
+    pub fn normal_at(&self, u: f64, v: f64) -> Tuple {
+        let (su, sv) = self.derivatives_at(u, v);
+        let n = su.cross(&sv);
+        if n.magnitude() > EPSILON * EPSILON {
+            return n;
+        }
+        // Degenerate corner, like the top of the teapot's lid, where a whole row of control points
+        // coincides. We nudge towards the middle of the patch, where the normal is well defined.
+        let nudge = |x: f64| x + (0.5 - x) * EPSILON;
+        let (su, sv) = self.derivatives_at(nudge(u), nudge(v));
+        su.cross(&sv)
+    }
+
+    fn refine(&self, ray: &Ray, guess: (f64, f64, f64)) -> Option<(f64, f64, f64)> {
+        let (mut u, mut v, mut t) = guess;
+        let nd = -ray.direction;
+        for _ in 0..NEWTON_STEPS {
+            let f = self.point_at(u, v) - (ray.origin + ray.direction * t);
+            if f.magnitude() < NEWTON_TOLERANCE {
+                let inside = |x: f64| (-EPSILON..=1. + EPSILON).contains(&x);
+                return if inside(u) && inside(v) { Some((t, u.clamp(0., 1.), v.clamp(0., 1.))) } else { None };
+            }
+            // Solve [su sv -d] * [du dv dt] = -f with Cramer's rule.
+            let (su, sv) = self.derivatives_at(u, v);
+            let det = su.dot(&sv.cross(&nd));
+            if det.abs() < 1e-12 {
+                return None;
+            }
+            let f = -f;
+            u += f.dot(&sv.cross(&nd)) / det;
+            v += su.dot(&f.cross(&nd)) / det;
+            t += su.dot(&sv.cross(&f)) / det;
+            // Keep iterates from wandering far off the patch, where the polynomial blows up.
+            u = u.clamp(-0.5, 1.5);
+            v = v.clamp(-0.5, 1.5);
+        }
+        None
+    }
+
+    fn guesses(&self, ray: &Ray) -> Vec<(f64, f64, f64)> {
+        let n = GUESS_RESOLUTION;
+        let mut rv = vec![];
+        for j in 0..n {
+            for i in 0..n {
+                let corner = |di: usize, dj: usize| {
+                    let (ii, jj) = (i + di, j + dj);
+                    (self.grid[jj * (n + 1) + ii], ii as f64 / n as f64, jj as f64 / n as f64)
+                };
+                let (c00, c10, c11, c01) = (corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1));
+                for (a, b, c) in [(c00, c10, c11), (c00, c11, c01)] {
+                    if let Some((t, b1, b2)) = intersect_triangle_loosely(ray, &a.0, &b.0, &c.0) {
+                        let b0 = 1. - b1 - b2;
+                        rv.push((
+                            a.1 * b0 + b.1 * b1 + c.1 * b2,
+                            a.2 * b0 + b.2 * b1 + c.2 * b2,
+                            t,
+                        ));
+                    }
+                }
+            }
+        }
+        rv
+    }
+}
+
+fn intersect_triangle_loosely(ray: &Ray, p1: &Tuple, p2: &Tuple, p3: &Tuple) -> Option<(f64, f64, f64)> {
+    let e1 = *p2 - *p1;
+    let e2 = *p3 - *p1;
+    let dir_cross_e2 = ray.direction.cross(&e2);
+    let det = e1.dot(&dir_cross_e2);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let f = 1. / det;
+    let p1_to_origin = ray.origin - *p1;
+    let u = f * p1_to_origin.dot(&dir_cross_e2);
+    let origin_cross_e1 = p1_to_origin.cross(&e1);
+    let v = f * ray.direction.dot(&origin_cross_e1);
+    if u < -GUESS_SLACK || v < -GUESS_SLACK || u + v > 1. + GUESS_SLACK {
+        return None;
+    }
+    Some((f * e2.dot(&origin_cross_e1), u, v))
+}
+
+impl LocalShape for BezierPatch {
+    fn local_intersect<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection<'a>> {
+        if self.local_bounding_box().local_intersect_ts(ray).is_empty() {
+            return vec![];
+        }
+        let mut hits: Vec<(f64, f64, f64)> = vec![];
+        for guess in self.guesses(ray) {
+            if let Some(hit) = self.refine(ray, guess) {
+                // Neighbouring guesses often converge to the same root.
+                if !hits.iter().any(|h| (h.0 - hit.0).abs() < EPSILON * 1e-2) {
+                    hits.push(hit);
+                }
+            }
+        }
+        hits.into_iter().map(|(t, u, v)| intersection_with_uv(t, shape, u, v)).collect()
+    }
+
+    fn local_normal_at(&self, _object_point: &Tuple, intersection: &Intersection) -> Tuple {
+        self.normal_at(intersection.u, intersection.v)
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        // The patch lies within the convex hull of its control points.
+        BoundingBox::from_points(&self.control_points)
+    }
+}
+
+impl Shape {
+    pub fn as_bezier_patch(&self) -> Option<&BezierPatch> {
+        if let ShapeType::BezierPatch(p) = &self.shape_type { Some(p) } else { None }
+    }
+}
+
+pub fn bezier_patch(control_points: &[Tuple; 16]) -> Shape {
+    let mut s = test_shape();
+    s.shape_type = ShapeType::BezierPatch(Box::new(BezierPatch::new(control_points)));
+    s
+}
+
+/// Tessellates a patch into a group of `resolution` x `resolution` quads, each split into two
+/// smooth triangles with normals taken from the patch.
+pub fn tessellate_bezier_patch(patch: &BezierPatch, resolution: usize) -> Shape {
+    let n = resolution.max(1);
+    let mut points = vec![];
+    let mut normals = vec![];
+    for j in 0..=n {
+        for i in 0..=n {
+            let (u, v) = (i as f64 / n as f64, j as f64 / n as f64);
+            points.push(patch.point_at(u, v));
+            normals.push(patch.normal_at(u, v).normalized());
+        }
+    }
+    let idx = |i: usize, j: usize| j * (n + 1) + i;
+    let mut g = group();
+    for j in 0..n {
+        for i in 0..n {
+            for (a, b, c) in [
+                (idx(i, j), idx(i + 1, j), idx(i + 1, j + 1)),
+                (idx(i, j), idx(i + 1, j + 1), idx(i, j + 1)),
+            ] {
+                // Degenerate patch edges collapse some triangles to lines or points.
+                let area = (points[b] - points[a]).cross(&(points[c] - points[a])).magnitude();
+                if area < 1e-12 {
+                    continue;
+                }
+                let t = smooth_triangle(&points[a], &points[b], &points[c], &normals[a], &normals[b], &normals[c]);
+                add_child(&mut g, &t);
+            }
+        }
+    }
+    g
+}
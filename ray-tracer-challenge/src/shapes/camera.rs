@@ -1,4 +1,6 @@
-use crate::{Matrix, identity_matrix, Ray, canvas, World, Canvas, normalize, point, inverse, ray, color_at, write_pixel};
+use std::{f64::consts::PI, sync::atomic::{AtomicBool, AtomicUsize, Ordering}, thread};
+
+use crate::{Matrix, identity_matrix, Ray, canvas, World, Canvas, normalize, point, inverse, ray, color_at_packet, write_pixel, Color, BLACK, Tuple, vector, view_transform};
 
 #[derive(Debug)]
 pub struct Camera {
@@ -8,6 +10,8 @@ pub struct Camera {
     pub half_height: f64,
     pub half_width: f64,
     pub pixel_size: f64,
+    // To pick one of a scene's cameras.
+    pub name: Option<String>,
     transform: Matrix,
     inverse: Matrix,
 }
@@ -19,6 +23,14 @@ impl Camera {
         self.transform = *m;
         self.inverse = inverse(m);
     }
+
+    /// The same view at another resolution, with the field of view across the longer side.
+    pub fn with_size(&self, hsize: usize, vsize: usize) -> Camera {
+        let mut c = camera(hsize as f64, vsize as f64, self.field_of_view);
+        c.set_transform(&self.transform);
+        c.name = self.name.clone();
+        c
+    }
 }
 
 // Note: hsize/vsize should be usizes, but this seems a bit easier to manage for now
@@ -42,14 +54,20 @@ pub fn camera(hsize: f64, vsize: f64, field_of_view: f64) -> Camera {
         pixel_size,
         half_width,
         half_height,
+        name: None,
         transform: identity_matrix,
         inverse: identity_matrix,
     }
 }
 
 pub fn ray_for_pixel(camera: &Camera, x: usize, y: usize) -> Ray {
-    let xoffset = (x as f64 + 0.5) * camera.pixel_size;
-    let yoffset = (y as f64 + 0.5) * camera.pixel_size;
+    ray_through(camera, x as f64 + 0.5, y as f64 + 0.5)
+}
+
+/// The ray through a point on the canvas, in pixels from its top left corner.
+pub fn ray_through(camera: &Camera, x: f64, y: f64) -> Ray {
+    let xoffset = x * camera.pixel_size;
+    let yoffset = y * camera.pixel_size;
     let canvas_point = camera.inverse() * point(
         camera.half_width - xoffset,
         camera.half_height - yoffset,
@@ -61,13 +79,130 @@ pub fn ray_for_pixel(camera: &Camera, x: usize, y: usize) -> Ray {
 }
 
 pub fn render(camera: &Camera, world: &World) -> Canvas {
+    render_with(camera, world, &RenderOptions::default())
+}
+
+/// How to render: the number of rays averaged for each pixel, and the number of threads, where 0
+/// means one for each core.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct RenderOptions {
+    pub samples: usize,
+    pub threads: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions { samples: 1, threads: 1 }
+    }
+}
+
+// Where in the pixel sample `i` goes. This is the R2 sequence, which spreads any number of
+// samples evenly, starting from the middle so a single sample is what `render` always did.
+fn sample_offset(i: usize) -> (f64, f64) {
+    const A1: f64 = 0.754_877_666_246_692_8;
+    const A2: f64 = 0.569_840_290_998_053_3;
+    ((0.5 + A1 * i as f64).fract(), (0.5 + A2 * i as f64).fract())
+}
+
+// Renders rows y and y + 1 in 2 by 2 tiles, so each packet's rays start out close together and
+// mostly visit the same BVH nodes.
+fn render_rows(camera: &Camera, world: &World, y: usize, samples: usize) -> Vec<(usize, Vec<Color>)> {
+    let ys = y..(y + 2).min(camera.vsize);
+    let mut rows = vec![vec![BLACK; camera.hsize]; ys.len()];
+    for x in (0..camera.hsize).step_by(2) {
+        let pixels: Vec<(usize, usize)> = ys.clone().flat_map(|y| (x..(x + 2).min(camera.hsize)).map(move |x| (x, y))).collect();
+        for i in 0..samples {
+            let (dx, dy) = sample_offset(i);
+            let rays: Vec<Ray> = pixels.iter().map(|&(px, py)| ray_through(camera, px as f64 + dx, py as f64 + dy)).collect();
+            for (&(px, py), color) in pixels.iter().zip(color_at_packet(world, &rays)) {
+                rows[py - y][px] = rows[py - y][px] + color;
+            }
+        }
+    }
+    ys.zip(rows).map(|(y, row)| (y, row.into_iter().map(|sum| sum * (1. / samples as f64)).collect())).collect()
+}
+
+/// Renders with several samples for each pixel, to smooth jagged edges, and on several threads,
+/// which take pairs of rows as they finish their last ones.
+pub fn render_with(camera: &Camera, world: &World, options: &RenderOptions) -> Canvas {
+    render_cancellable(camera, world, options, &AtomicBool::new(false)).expect("never cancelled")
+}
+
+/// Like `render_with`, but the threads stop taking rows once `cancel` is set, from another
+/// thread, and then it returns `None`.
+pub fn render_cancellable(camera: &Camera, world: &World, options: &RenderOptions, cancel: &AtomicBool) -> Option<Canvas> {
+    let samples = options.samples.max(1);
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }.min(camera.vsize.div_ceil(2).max(1));
+    let next_rows = AtomicUsize::new(0);
+    let rows: Vec<(usize, Vec<Color>)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads).map(|_| s.spawn(|| {
+            let mut rows = vec![];
+            loop {
+                let y = 2 * next_rows.fetch_add(1, Ordering::Relaxed);
+                if y >= camera.vsize || cancel.load(Ordering::Relaxed) {
+                    return rows;
+                }
+                rows.extend(render_rows(camera, world, y, samples));
+            }
+        })).collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    });
+    if cancel.load(Ordering::Relaxed) {
+        return None;
+    }
     let mut c = canvas(camera.hsize, camera.vsize);
-    for x in 0..c.width {
-        for y in 0..c.height {
-            let ray = ray_for_pixel(camera, x, y);
-            let color = color_at(world, &ray);
-            write_pixel(&mut c, x as i64, y as i64, &color);
+    for (y, row) in rows {
+        for (x, color) in row.iter().enumerate() {
+            write_pixel(&mut c, x as i64, y as i64, color);
         }
     }
-    c
+    Some(c)
+}
+
+/// A camera circling a point, for looking around a scene. `yaw` is the angle around the y axis,
+/// with 0 looking from +z, and `pitch` the angle above the target.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Orbit {
+    pub target: Tuple,
+    pub distance: f64,
+    pub yaw: f64,
+    pub pitch: f64,
+}
+
+// Looking straight down or up would leave the camera without an up direction.
+const MAX_PITCH: f64 = PI / 2. - 0.01;
+
+impl Orbit {
+    /// The orbit `camera` is on when looking at `target`.
+    pub fn around(camera: &Camera, target: &Tuple) -> Orbit {
+        let offset = camera.inverse() * point(0., 0., 0.) - *target;
+        let distance = offset.magnitude();
+        let pitch = (offset.y / distance).clamp(-1., 1.).asin().clamp(-MAX_PITCH, MAX_PITCH);
+        Orbit { target: *target, distance, yaw: offset.x.atan2(offset.z), pitch }
+    }
+
+    pub fn rotate(&mut self, yaw: f64, pitch: f64) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves closer for factors below 1, and further away above.
+    pub fn zoom(&mut self, factor: f64) {
+        self.distance = (self.distance * factor).max(1e-3);
+    }
+
+    /// `camera` moved onto the orbit, looking at the target with y up.
+    pub fn camera(&self, camera: &Camera) -> Camera {
+        let from = self.target + vector(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        ) * self.distance;
+        let mut c = camera.with_size(camera.hsize, camera.vsize);
+        c.set_transform(&view_transform(&from, &self.target, &vector(0., 1., 0.)));
+        c
+    }
 }
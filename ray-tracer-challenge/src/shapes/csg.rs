@@ -1,6 +1,6 @@
 use std::{str::FromStr};
 
-use crate::{Shape, LocalShape, test_shape, ShapeType, Intersections, intersections, BoundingBox, transform};
+use crate::{Shape, LocalShape, BaseBoundingBox, test_shape, ShapeType, Intersections, intersections, BoundingBox, transform};
 
 #[derive(PartialEq, Debug, Clone)]
 enum Operation {
@@ -73,6 +73,16 @@ impl LocalShape for CSG {
         let is = intersections(xs);
         filter_intersections(shape, &is).data
     }
+
+    fn local_intersect_occluder<'a>(&'a self, shape: &'a Shape, object_ray: &crate::Ray, t_max: f64) -> Option<crate::Intersection<'a>> {
+        // Which hits survive depends on all the hits before them, so past the bounding box check
+        // we need the full list.
+        let (hit, tmin, tmax) = self.local_bounding_box().local_intersect_tmin_tmax(object_ray);
+        if !hit || t_max <= tmin || tmax < 0. {
+            return None;
+        }
+        self.local_intersect(shape, object_ray).into_iter().rev().find(|i| 0. < i.t && i.t < t_max && i.casts_shadow())
+    }
 }
 
 impl Shape {
@@ -80,6 +90,15 @@ impl Shape {
         if let ShapeType::CSG(c) = &self.shape_type { Some(c) } else { None }
     }
 
+    pub fn contains_csg(&self) -> bool {
+        match &self.shape_type {
+            ShapeType::CSG(_) => true,
+            ShapeType::Group(g) => g.children.iter().any(|c| c.contains_csg()),
+            ShapeType::Instance(i) => i.prototype.contains_csg(),
+            _ => false,
+        }
+    }
+
     pub fn left(&self) -> Shape {
         self.as_csg().unwrap().children[0].clone()
     }
@@ -126,7 +145,8 @@ pub fn filter_intersections<'a>(shape: &'a Shape, xs: &Intersections<'a>) -> Int
     let mut inr = false;
     let mut result = vec![];
     for i in &xs.data {
-        let lhit = csg.children[0].includes(&i.object);
+        // Instances share their prototype's shapes, so we look for the instance instead.
+        let lhit = csg.children[0].includes(i.instance.unwrap_or(i.object));
         if intersection_allowed(&csg.op.to_string(), &lhit, &inl, &inr) {
             result.push(i.clone());
         }
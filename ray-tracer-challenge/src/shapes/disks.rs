@@ -0,0 +1,56 @@
+use std::f64::consts::PI;
+
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, EPSILON, vector, LocalShape, Intersection, BoundingBox, point, intersection_with_uv};
+
+// A flat disk in the xz plane, centered on the origin and facing +y, like a plane.
+// With a non-zero inner radius, it's an annulus.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Disk {
+    pub inner_radius: f64,
+    pub outer_radius: f64,
+}
+
+impl Shape {
+    pub fn as_disk(&self) -> Option<&Disk> {
+        if let ShapeType::Disk(d) = &self.shape_type { Some(d) } else { None }
+    }
+}
+
+impl LocalShape for Disk {
+    fn local_intersect<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection<'a>> {
+        if ray.direction.y.abs() < EPSILON {
+            return vec![];
+        }
+        let t = -ray.origin.y / ray.direction.y;
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        let r = (x * x + z * z).sqrt();
+        if r < self.inner_radius || r > self.outer_radius {
+            return vec![];
+        }
+        // u goes around the disk, v goes from the inner to the outer edge.
+        let u = (z.atan2(x) / (2. * PI)).rem_euclid(1.);
+        let v = (r - self.inner_radius) / (self.outer_radius - self.inner_radius);
+        vec![intersection_with_uv(t, shape, u, v)]
+    }
+
+    fn local_normal_at(&self, _object_point: &Tuple, _intersection: &Intersection) -> Tuple {
+        vector(0., 1., 0.)
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        let r = self.outer_radius;
+        BoundingBox::new(point(-r, 0., -r), point(r, 0., r))
+    }
+}
+
+pub fn disk() -> Shape {
+    annulus(0., 1.)
+}
+
+pub fn annulus(inner_radius: f64, outer_radius: f64) -> Shape {
+    assert!(0. <= inner_radius && inner_radius < outer_radius);
+    let mut s = test_shape();
+    s.shape_type = ShapeType::Disk(Disk { inner_radius, outer_radius });
+    s
+}
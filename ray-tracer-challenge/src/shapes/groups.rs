@@ -1,13 +1,13 @@
 use core::panic;
 use std::{time::Instant};
 
-use crate::{Shape, test_shape, ShapeType, Ray, Tuple, transform, Intersection, LocalShape, BaseBoundingBox, BoundingBox, BVHNode};
+use crate::{Shape, test_shape, ShapeType, Ray, Tuple, transform, Intersection, LocalShape, BaseBoundingBox, BoundingBox, LinearBVH, BVHTraversalPolicy, BVHBuildOptions, BVHStats, shape_items};
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Group {
     pub children: Vec<Shape>,
     pub bb: BoundingBox,
-    pub bvh: Option<BVHNode>,
+    pub bvh: Option<LinearBVH>,
 }
 
 impl Shape {
@@ -20,7 +20,7 @@ impl Shape {
         }
     }
 
-    fn as_group_mut(&mut self) -> Option<&mut Group> {
+    pub fn as_group_mut(&mut self) -> Option<&mut Group> {
         match &mut self.shape_type {
             ShapeType::Group(g) => Some(g),
             _ => None,
@@ -31,23 +31,66 @@ impl Shape {
         self.children().len() == 0
     }
 
-    pub fn freeze_and_optimize(&mut self) {
+    pub fn freeze_and_optimize(&mut self) -> BVHStats {
+        self.freeze_and_optimize_with(&BVHBuildOptions::default())
+    }
+
+    pub fn freeze_and_optimize_with(&mut self, options: &BVHBuildOptions) -> BVHStats {
         if let Some(g) = self.as_group_mut() {
             // NOTE We should freeze children before adding, since we copy when adding.
-            for c in &mut g.children {
-                c.freeze_and_optimize();
-            }
+            let nested: Vec<BVHStats> = g.children.iter_mut()
+                .map(|c| c.freeze_and_optimize_with(options))
+                .filter(|s| s.nodes > 0)
+                .collect();
             let now = Instant::now();
-            let bvh = BVHNode::build(g.bb, &g.children);
-            let elapsed_time = now.elapsed();
-            println!(
-                "Built BVH. {} children. Max depth {}. {} seconds.",
-                g.children.len(),
-                bvh.max_depth(),
-                (elapsed_time.as_millis() as f64)/1000.,
-            );
+            let bvh = LinearBVH::build_with(&shape_items(&g.children), options);
+            let mut stats = bvh.stats();
+            stats.build_time = now.elapsed();
+            stats.nested = nested;
             g.bvh = Some(bvh);
+            stats
+        } else {
+            BVHStats::default()
+        }
+    }
+}
+
+impl Group {
+    /// Recomputes our bounding box after children moved, refitting the BVH rather than rebuilding it.
+    pub fn refit(&mut self) {
+        let bbs: Vec<BoundingBox> = self.children.iter().map(|c| BoundingBox::from_transformed_shapes(&[c])).collect();
+        self.bb = BoundingBox::new_empty();
+        for bb in &bbs {
+            self.bb.include_bb(bb);
+        }
+        if let Some(bvh) = &mut self.bvh {
+            bvh.refit(&bbs);
+        }
+    }
+
+    /// Like `refit`, after only child `i` moved. With a BVH, that's just the boxes from the child's
+    /// leaf up to the root, and this group's box, which is the root's.
+    pub fn refit_child(&mut self, i: usize) {
+        match &mut self.bvh {
+            Some(bvh) if !bvh.nodes.is_empty() => {
+                let children = &self.children;
+                bvh.refit_primitive(i, |j| BoundingBox::from_transformed_shapes(&[&children[j]]));
+                self.bb = bvh.nodes[0].bb;
+            }
+            _ => self.refit(),
+        }
+    }
+
+    /// Removes and returns child `i`, keeping the BVH if there is one.
+    pub fn remove_child(&mut self, i: usize) -> Shape {
+        let child = self.children.remove(i);
+        if let Some(bvh) = &mut self.bvh {
+            if !bvh.remove_primitive(i) {
+                *bvh = LinearBVH::build_for_shapes(&self.children);
+            }
         }
+        self.refit();
+        child
     }
 }
 
@@ -58,7 +101,9 @@ impl LocalShape for Group {
 
     fn local_intersect(&self, _group: &Shape, object_ray: &Ray) -> Vec<Intersection> {
         if let Some(bvh) = &self.bvh {
-            return bvh.intersect(object_ray);
+            let mut p = BVHTraversalPolicy::new_all_hits();
+            bvh.intersect_shapes(&self.children, object_ray, &mut p);
+            return p.intersections();
         }
 
         if self.bb.local_intersect_ts(object_ray).len() == 0 {
@@ -81,16 +126,32 @@ impl LocalShape for Group {
 
     fn local_intersect_closest_hit<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection> {
         if let Some(bvh) = &self.bvh {
-            return bvh.intersect_closest(&ray);
+            let mut p = BVHTraversalPolicy::new_closest_hit();
+            bvh.intersect_shapes(&self.children, ray, &mut p);
+            return p.intersections();
         }
         LocalShape::local_intersect_closest_hit(self, shape, ray)
     }
     fn local_intersect_any_hit<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection> {
         if let Some(bvh) = &self.bvh {
-            return bvh.intersect_any(&ray);
+            let mut p = BVHTraversalPolicy::new_any_hit();
+            bvh.intersect_shapes(&self.children, ray, &mut p);
+            return p.intersections();
         }
         LocalShape::local_intersect_any_hit(self, shape, ray)
     }
+    fn local_intersect_occluder<'a>(&'a self, _group: &'a Shape, ray: &Ray, t_max: f64) -> Option<Intersection<'a>> {
+        if let Some(bvh) = &self.bvh {
+            let mut p = BVHTraversalPolicy::new_any_hit_before(t_max);
+            bvh.intersect_shapes(&self.children, ray, &mut p);
+            return p.intersections().first().copied();
+        }
+        let (hit, tmin, tmax) = self.bb.local_intersect_tmin_tmax(ray);
+        if !hit || t_max <= tmin || tmax < 0. {
+            return None;
+        }
+        self.children.iter().find_map(|c| c.intersect_occluder(ray, t_max))
+    }
 }
 
 pub fn group() -> Shape {
@@ -0,0 +1,181 @@
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, vector, point, LocalShape, Intersection, BoundingBox, BaseBoundingBox, position};
+
+/// A grid of heights over the unit square in the xz plane. Sample (i, j) sits at
+/// x = i / (width - 1), z = j / (depth - 1), y = heights[j * width + i].
+/// Each cell is split into two triangles along its (1, 0)-(0, 1) diagonal, with normals interpolated
+/// from per-sample normals. Rays walk the grid cell by cell (2D DDA), so we never build triangle shapes.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Heightfield {
+    pub width: usize,
+    pub depth: usize,
+    pub heights: Vec<f64>,
+    normals: Vec<Tuple>,
+    bb: BoundingBox,
+}
+
+impl Heightfield {
+    pub fn new(heights: &[f64], width: usize, depth: usize) -> Heightfield {
+        assert!(width >= 2 && depth >= 2);
+        assert_eq!(heights.len(), width * depth);
+        let min = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = heights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let mut h = Heightfield {
+            width,
+            depth,
+            heights: heights.to_vec(),
+            normals: vec![],
+            bb: BoundingBox::new(point(0., min, 0.), point(1., max, 1.)),
+        };
+        h.normals = (0..width * depth).map(|idx| h.sample_normal(idx % width, idx / width)).collect();
+        h
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.width + i]
+    }
+
+    pub(crate) fn vertex(&self, i: usize, j: usize) -> Tuple {
+        point(
+            i as f64 / (self.width - 1) as f64,
+            self.height(i, j),
+            j as f64 / (self.depth - 1) as f64,
+        )
+    }
+
+    pub(crate) fn vertex_normal(&self, i: usize, j: usize) -> Tuple {
+        self.normals[j * self.width + i]
+    }
+
+    fn sample_normal(&self, i: usize, j: usize) -> Tuple {
+        // Central differences, falling back to one-sided ones at the edges.
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.width - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.depth - 1));
+        let dx = (i1 - i0) as f64 / (self.width - 1) as f64;
+        let dz = (j1 - j0) as f64 / (self.depth - 1) as f64;
+        let dhdx = (self.height(i1, j) - self.height(i0, j)) / dx;
+        let dhdz = (self.height(i, j1) - self.height(i, j0)) / dz;
+        vector(-dhdx, 1., -dhdz).normalized()
+    }
+
+    fn cell_of(&self, x: f64, z: f64) -> (usize, usize, f64, f64) {
+        let (w, d) = ((self.width - 1) as f64, (self.depth - 1) as f64);
+        let i = ((x * w).floor().max(0.) as usize).min(self.width - 2);
+        let j = ((z * d).floor().max(0.) as usize).min(self.depth - 2);
+        (i, j, x * w - i as f64, z * d - j as f64)
+    }
+
+    fn intersect_cell(&self, ray: &Ray, i: usize, j: usize, trange: (f64, f64), ts: &mut Vec<f64>) {
+        // Skip cells where the ray stays entirely above or below the heights.
+        let corners = [self.height(i, j), self.height(i + 1, j), self.height(i, j + 1), self.height(i + 1, j + 1)];
+        let cmin = corners.iter().cloned().fold(f64::INFINITY, f64::min);
+        let cmax = corners.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let (y0, y1) = (position(ray, trange.0).y, position(ray, trange.1).y);
+        if (y0 > cmax && y1 > cmax) || (y0 < cmin && y1 < cmin) {
+            return;
+        }
+
+        let (p00, p10, p01, p11) = (self.vertex(i, j), self.vertex(i + 1, j), self.vertex(i, j + 1), self.vertex(i + 1, j + 1));
+        for (a, b, c) in [(p00, p10, p01), (p11, p01, p10)] {
+            if let Some(t) = intersect_triangle(ray, &a, &b, &c) {
+                // Hits on the shared diagonal are found by both triangles.
+                if trange.0 <= t && t <= trange.1 && !ts.iter().any(|x| (x - t).abs() < 1e-9) {
+                    ts.push(t);
+                }
+            }
+        }
+    }
+}
+
+fn intersect_triangle(ray: &Ray, p1: &Tuple, p2: &Tuple, p3: &Tuple) -> Option<f64> {
+    let e1 = *p2 - *p1;
+    let e2 = *p3 - *p1;
+    let dir_cross_e2 = ray.direction.cross(&e2);
+    let det = e1.dot(&dir_cross_e2);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let f = 1. / det;
+    let p1_to_origin = ray.origin - *p1;
+    let u = f * p1_to_origin.dot(&dir_cross_e2);
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let origin_cross_e1 = p1_to_origin.cross(&e1);
+    let v = f * ray.direction.dot(&origin_cross_e1);
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+    Some(f * e2.dot(&origin_cross_e1))
+}
+
+impl LocalShape for Heightfield {
+    fn local_intersect_ts(&self, ray: &Ray) -> Vec<f64> {
+        let (hit, tmin, tmax) = self.bb.local_intersect_tmin_tmax(ray);
+        if !hit {
+            return vec![];
+        }
+        let (w, d) = ((self.width - 1) as f64, (self.depth - 1) as f64);
+        let entry = position(ray, tmin);
+        let (mut i, mut j, _, _) = self.cell_of(entry.x, entry.z);
+
+        // Set up the DDA: t at the next cell boundary on each axis, and t between boundaries.
+        let axis = |o: f64, dir: f64, cell: usize, n: f64| -> (i64, f64, f64) {
+            if dir > 0. {
+                (1, ((cell + 1) as f64 / n - o) / dir, 1. / n / dir)
+            } else if dir < 0. {
+                (-1, (cell as f64 / n - o) / dir, -1. / n / dir)
+            } else {
+                (0, f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (step_x, mut next_x, delta_x) = axis(ray.origin.x, ray.direction.x, i, w);
+        let (step_z, mut next_z, delta_z) = axis(ray.origin.z, ray.direction.z, j, d);
+
+        let mut ts = vec![];
+        let mut t = tmin;
+        while t <= tmax {
+            let t_exit = next_x.min(next_z).min(tmax);
+            self.intersect_cell(ray, i, j, (t, t_exit), &mut ts);
+            if next_x < next_z {
+                let ni = i as i64 + step_x;
+                if ni < 0 || ni > self.width as i64 - 2 { break; }
+                i = ni as usize;
+                t = next_x;
+                next_x += delta_x;
+            } else {
+                let nj = j as i64 + step_z;
+                if step_z == 0 || nj < 0 || nj > self.depth as i64 - 2 { break; }
+                j = nj as usize;
+                t = next_z;
+                next_z += delta_z;
+            }
+        }
+        ts
+    }
+
+    fn local_normal_at(&self, object_point: &Tuple, _intersection: &Intersection) -> Tuple {
+        let (i, j, fx, fz) = self.cell_of(object_point.x, object_point.z);
+        let n = |di: usize, dj: usize| self.normals[(j + dj) * self.width + i + di];
+        if fx + fz <= 1. {
+            n(0, 0) * (1. - fx - fz) + n(1, 0) * fx + n(0, 1) * fz
+        } else {
+            n(1, 1) * (fx + fz - 1.) + n(0, 1) * (1. - fx) + n(1, 0) * (1. - fz)
+        }
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        self.bb
+    }
+}
+
+impl Shape {
+    pub fn as_heightfield(&self) -> Option<&Heightfield> {
+        if let ShapeType::Heightfield(h) = &self.shape_type { Some(h) } else { None }
+    }
+}
+
+pub fn heightfield(heights: &[f64], width: usize, depth: usize) -> Shape {
+    let mut s = test_shape();
+    s.shape_type = ShapeType::Heightfield(Box::new(Heightfield::new(heights, width, depth)));
+    s
+}
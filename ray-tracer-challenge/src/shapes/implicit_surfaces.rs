@@ -0,0 +1,199 @@
+use std::{fmt, sync::Arc};
+
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, EPSILON, vector, point, LocalShape, Intersection, BoundingBox, BaseBoundingBox, position};
+
+// Sphere tracing has to resolve hits well below EPSILON, since we offset over_point/under_point by
+// EPSILON when shading. Otherwise secondary rays would immediately re-hit the surface they start on.
+const SDF_EPSILON: f64 = EPSILON / 100.;
+const SDF_NORMAL_DELTA: f64 = EPSILON / 10.;
+const SDF_MAX_STEPS: usize = 512;
+
+/// Wraps a user-supplied signed distance function, so that it can live inside a `Sdf`.
+#[derive(Clone)]
+pub struct SdfFunction(pub Arc<dyn Fn(&Tuple) -> f64 + Send + Sync>);
+
+impl PartialEq for SdfFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for SdfFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SdfFunction")
+    }
+}
+
+/// A signed distance function: negative inside the surface, positive outside.
+/// Primitives are centered at the origin, and can be combined with the (smooth) CSG combinators.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Sdf {
+    Sphere { radius: f64 },
+    Cuboid { half_extents: Tuple },
+    RoundCuboid { half_extents: Tuple, radius: f64 },
+    Torus { major_radius: f64, minor_radius: f64 },
+    Capsule { a: Tuple, b: Tuple, radius: f64 },
+    Cylinder { radius: f64, half_height: f64 },
+    Translate(Box<Sdf>, Tuple),
+    Scale(Box<Sdf>, f64),
+    // The last argument is the blend radius. A radius of 0 gives the sharp version of each operation.
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f64),
+    SmoothSubtract(Box<Sdf>, Box<Sdf>, f64),
+    SmoothIntersect(Box<Sdf>, Box<Sdf>, f64),
+    Custom(SdfFunction),
+}
+
+fn mix(a: f64, b: f64, h: f64) -> f64 {
+    a * (1. - h) + b * h
+}
+
+fn length2(x: f64, y: f64) -> f64 {
+    (x * x + y * y).sqrt()
+}
+
+impl Sdf {
+    pub fn sphere(radius: f64) -> Sdf { Sdf::Sphere { radius } }
+    pub fn cuboid(x: f64, y: f64, z: f64) -> Sdf { Sdf::Cuboid { half_extents: vector(x, y, z) } }
+    pub fn round_cuboid(x: f64, y: f64, z: f64, radius: f64) -> Sdf { Sdf::RoundCuboid { half_extents: vector(x, y, z), radius } }
+    pub fn torus(major_radius: f64, minor_radius: f64) -> Sdf { Sdf::Torus { major_radius, minor_radius } }
+    pub fn capsule(a: &Tuple, b: &Tuple, radius: f64) -> Sdf { Sdf::Capsule { a: *a, b: *b, radius } }
+    pub fn cylinder(radius: f64, half_height: f64) -> Sdf { Sdf::Cylinder { radius, half_height } }
+    pub fn custom(f: impl Fn(&Tuple) -> f64 + Send + Sync + 'static) -> Sdf { Sdf::Custom(SdfFunction(Arc::new(f))) }
+
+    pub fn translate(&self, x: f64, y: f64, z: f64) -> Sdf { Sdf::Translate(Box::new(self.clone()), vector(x, y, z)) }
+    pub fn scale(&self, s: f64) -> Sdf { Sdf::Scale(Box::new(self.clone()), s) }
+    pub fn smooth_union(&self, other: &Sdf, k: f64) -> Sdf { Sdf::SmoothUnion(Box::new(self.clone()), Box::new(other.clone()), k) }
+    pub fn smooth_subtract(&self, other: &Sdf, k: f64) -> Sdf { Sdf::SmoothSubtract(Box::new(self.clone()), Box::new(other.clone()), k) }
+    pub fn smooth_intersect(&self, other: &Sdf, k: f64) -> Sdf { Sdf::SmoothIntersect(Box::new(self.clone()), Box::new(other.clone()), k) }
+    pub fn union(&self, other: &Sdf) -> Sdf { self.smooth_union(other, 0.) }
+    pub fn subtract(&self, other: &Sdf) -> Sdf { self.smooth_subtract(other, 0.) }
+    pub fn intersect(&self, other: &Sdf) -> Sdf { self.smooth_intersect(other, 0.) }
+
+    pub fn distance(&self, p: &Tuple) -> f64 {
+        // Formulas follow https://iquilezles.org/articles/distfunctions/
+        match self {
+            Sdf::Sphere { radius } => vector(p.x, p.y, p.z).magnitude() - radius,
+            Sdf::Cuboid { half_extents } => {
+                let q = vector(p.x.abs() - half_extents.x, p.y.abs() - half_extents.y, p.z.abs() - half_extents.z);
+                let outside = vector(q.x.max(0.), q.y.max(0.), q.z.max(0.)).magnitude();
+                outside + q.x.max(q.y).max(q.z).min(0.)
+            }
+            Sdf::RoundCuboid { half_extents, radius } => {
+                let inner = *half_extents - vector(*radius, *radius, *radius);
+                Sdf::Cuboid { half_extents: inner }.distance(p) - radius
+            }
+            Sdf::Torus { major_radius, minor_radius } => {
+                length2(length2(p.x, p.z) - major_radius, p.y) - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let pa = *p - *a;
+                let ba = *b - *a;
+                let h = (pa.dot(&ba) / ba.dot(&ba)).clamp(0., 1.);
+                (pa - ba * h).magnitude() - radius
+            }
+            Sdf::Cylinder { radius, half_height } => {
+                let dx = length2(p.x, p.z) - radius;
+                let dy = p.y.abs() - half_height;
+                dx.max(dy).min(0.) + length2(dx.max(0.), dy.max(0.))
+            }
+            Sdf::Translate(s, offset) => s.distance(&(*p - *offset)),
+            Sdf::Scale(s, factor) => s.distance(&point(p.x / factor, p.y / factor, p.z / factor)) * factor,
+            Sdf::SmoothUnion(a, b, k) => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                if *k <= 0. {
+                    return d1.min(d2);
+                }
+                let h = (0.5 + 0.5 * (d2 - d1) / k).clamp(0., 1.);
+                mix(d2, d1, h) - k * h * (1. - h)
+            }
+            Sdf::SmoothSubtract(a, b, k) => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                if *k <= 0. {
+                    return d1.max(-d2);
+                }
+                let h = (0.5 - 0.5 * (d1 + d2) / k).clamp(0., 1.);
+                mix(d1, -d2, h) + k * h * (1. - h)
+            }
+            Sdf::SmoothIntersect(a, b, k) => {
+                let (d1, d2) = (a.distance(p), b.distance(p));
+                if *k <= 0. {
+                    return d1.max(d2);
+                }
+                let h = (0.5 - 0.5 * (d2 - d1) / k).clamp(0., 1.);
+                mix(d2, d1, h) + k * h * (1. - h)
+            }
+            Sdf::Custom(f) => (f.0)(p),
+        }
+    }
+
+    pub fn gradient(&self, p: &Tuple) -> Tuple {
+        let h = SDF_NORMAL_DELTA;
+        let dx = vector(h, 0., 0.);
+        let dy = vector(0., h, 0.);
+        let dz = vector(0., 0., h);
+        vector(
+            self.distance(&(*p + dx)) - self.distance(&(*p - dx)),
+            self.distance(&(*p + dy)) - self.distance(&(*p - dy)),
+            self.distance(&(*p + dz)) - self.distance(&(*p - dz)),
+        )
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ImplicitSurface {
+    pub sdf: Sdf,
+    // Sphere tracing only happens inside these bounds, so they must be finite and contain the surface.
+    pub bounds: BoundingBox,
+}
+
+impl LocalShape for ImplicitSurface {
+    fn local_intersect_ts(&self, ray: &Ray) -> Vec<f64> {
+        let (hit, tmin, tmax) = self.bounds.local_intersect_tmin_tmax(ray);
+        if !hit {
+            return vec![];
+        }
+
+        // Object space rays aren't normalized, so we convert distances into units of t.
+        let speed = ray.direction.magnitude();
+        let mut xs = vec![];
+        let mut t = tmin;
+        let mut inside = self.sdf.distance(&position(ray, t)) < 0.;
+        for _ in 0..SDF_MAX_STEPS {
+            if t > tmax {
+                break;
+            }
+            let d = self.sdf.distance(&position(ray, t));
+            let dist = if inside { -d } else { d };
+            if dist < SDF_EPSILON {
+                // We report every crossing, not just the first, so refraction and CSG see entries and exits.
+                // A grazing hit that doesn't cross produces a pair of hits, like a tangent ray on a sphere.
+                xs.push(t);
+                inside = !inside;
+                t += 2. * SDF_EPSILON / speed;
+            } else {
+                t += dist / speed;
+            }
+        }
+        xs
+    }
+
+    fn local_normal_at(&self, object_point: &Tuple, _intersection: &Intersection) -> Tuple {
+        self.sdf.gradient(object_point)
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        self.bounds
+    }
+}
+
+impl Shape {
+    pub fn as_implicit_surface(&self) -> Option<&ImplicitSurface> {
+        if let ShapeType::ImplicitSurface(s) = &self.shape_type { Some(s) } else { None }
+    }
+}
+
+pub fn implicit_surface(sdf: Sdf, bounds: BoundingBox) -> Shape {
+    let mut s = test_shape();
+    s.shape_type = ShapeType::ImplicitSurface(Box::new(ImplicitSurface { sdf, bounds }));
+    s
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, LocalShape, Intersection, BoundingBox, Material, identity_matrix};
+
+/// A transformed copy of shared geometry. All instances of a prototype point at the same frozen
+/// shape, so adding many of them to a world or group only costs a transform and a material each.
+///
+/// Intersections with an instance report the prototype's leaf shape as their object, and the
+/// instance itself in `Intersection::instance`, which we need for normals and materials.
+/// Prototypes can't contain instances themselves.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub prototype: Arc<Shape>,
+    // When set, replaces the materials of every shape in the prototype.
+    pub material: Option<Material>,
+    bb: BoundingBox,
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.prototype, &other.prototype) && self.material == other.material
+    }
+}
+
+impl Instance {
+    pub fn new(prototype: &Arc<Shape>, material: Option<Material>) -> Instance {
+        assert!(!contains_instance(prototype), "Instances can't be nested");
+        let mut bb = BoundingBox::new_empty();
+        bb.include_transformed_shape(prototype);
+        Instance { prototype: prototype.clone(), material, bb }
+    }
+
+    fn tag<'a>(shape: &'a Shape, xs: Vec<Intersection<'a>>) -> Vec<Intersection<'a>> {
+        xs.into_iter().map(|i| Intersection { instance: Some(shape), ..i }).collect()
+    }
+}
+
+fn contains_instance(shape: &Shape) -> bool {
+    shape.as_instance().is_some() || shape.children_option().is_some_and(|c| c.iter().any(contains_instance))
+}
+
+impl LocalShape for Instance {
+    fn local_normal_at(&self, _object_point: &Tuple, _intersection: &Intersection) -> Tuple {
+        panic!("Should not be called")
+    }
+
+    fn local_intersect<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection<'a>> {
+        Instance::tag(shape, self.prototype.intersect(ray))
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        self.bb
+    }
+
+    fn local_intersect_closest_hit<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection<'a>> {
+        Instance::tag(shape, self.prototype.intersect_closest_hit(ray))
+    }
+    fn local_intersect_any_hit<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection<'a>> {
+        Instance::tag(shape, self.prototype.intersect_any_hit(ray))
+    }
+    fn local_intersect_occluder<'a>(&'a self, shape: &'a Shape, ray: &Ray, t_max: f64) -> Option<Intersection<'a>> {
+        self.prototype.intersect_occluder(ray, t_max).map(|x| Intersection { instance: Some(shape), ..x })
+    }
+}
+
+impl Shape {
+    pub fn as_instance(&self) -> Option<&Instance> {
+        if let ShapeType::Instance(i) = &self.shape_type { Some(i) } else { None }
+    }
+}
+
+/// Freezes a copy of `shape` so it can be shared by instances.
+pub fn prototype(shape: &Shape) -> Arc<Shape> {
+    let mut s = shape.clone();
+    // The prototype's own frame is the instance's object space.
+    s.parent_to_global_transform = identity_matrix;
+    s.recompute_transform();
+    s.freeze_and_optimize();
+    Arc::new(s)
+}
+
+pub fn instance(prototype: &Arc<Shape>) -> Shape {
+    let mut s = test_shape();
+    s.shape_type = ShapeType::Instance(Box::new(Instance::new(prototype, None)));
+    s
+}
+
+pub fn instance_with_material(prototype: &Arc<Shape>, material: &Material) -> Shape {
+    let mut s = test_shape();
+    s.shape_type = ShapeType::Instance(Box::new(Instance::new(prototype, Some(*material))));
+    s.material = *material;
+    s
+}
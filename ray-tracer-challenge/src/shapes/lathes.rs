@@ -0,0 +1,114 @@
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, equal, vector, EPSILON, LocalShape, Intersection, BoundingBox, BaseBoundingBox, point, intersection_with_uv};
+
+/// A surface of revolution around the y axis. The profile is a polyline of (radius, y) points,
+/// and each segment sweeps out a cone frustum, cylinder or annulus. A profile that starts and ends
+/// on the axis (radius 0) encloses a solid, which is what refraction and CSG expect.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Lathe {
+    pub profile: Vec<(f64, f64)>,
+    bb: BoundingBox,
+}
+
+impl Lathe {
+    fn intersect_segment(&self, ray: &Ray, idx: usize, ts: &mut Vec<(f64, usize)>) {
+        let (r0, y0) = self.profile[idx];
+        let (r1, y1) = self.profile[idx + 1];
+        let o = ray.origin;
+        let d = ray.direction;
+        // Segments share endpoints, so each one owns its start but not its end, to avoid reporting
+        // a hit at a joint twice. The last segment owns both.
+        let last = idx + 2 == self.profile.len();
+        let on_segment = |s: f64| 0. <= s && (s < 1. || (last && s <= 1.));
+
+        if equal(y0, y1) {
+            // Flat segment, sweeps out an annulus.
+            if equal(d.y, 0.) {
+                return;
+            }
+            let t = (y0 - o.y) / d.y;
+            let x = o.x + t * d.x;
+            let z = o.z + t * d.z;
+            let r = (x * x + z * z).sqrt();
+            if on_segment((r - r0) / (r1 - r0)) {
+                ts.push((t, idx));
+            }
+            return;
+        }
+
+        // Radius along the ray is linear in t: p + q t.
+        let k = (r1 - r0) / (y1 - y0);
+        let p = r0 + k * (o.y - y0);
+        let q = k * d.y;
+        let a = d.x * d.x + d.z * d.z - q * q;
+        let b = 2. * (o.x * d.x + o.z * d.z - p * q);
+        let c = o.x * o.x + o.z * o.z - p * p;
+
+        // Relative to the other terms, as for quadrics, so large lathes aren't treated as planes.
+        let candidates = if a.abs() <= 1e-12 * (b.abs() + c.abs()) {
+            if b == 0. { vec![] } else { vec![-c / b] }
+        } else {
+            let disc = b * b - 4. * a * c;
+            if disc < 0. {
+                return;
+            }
+            vec![(-b - disc.sqrt()) / (2. * a), (-b + disc.sqrt()) / (2. * a)]
+        };
+        for t in candidates {
+            let y = o.y + t * d.y;
+            // Discard hits outside the segment, or on the mirrored half of the cone (negative radius).
+            if on_segment((y - y0) / (y1 - y0)) && p + q * t >= -EPSILON {
+                ts.push((t, idx));
+            }
+        }
+    }
+}
+
+impl LocalShape for Lathe {
+    fn local_intersect<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection<'a>> {
+        if self.bb.local_intersect_ts(ray).is_empty() {
+            return vec![];
+        }
+        let mut ts = vec![];
+        for idx in 0..self.profile.len() - 1 {
+            self.intersect_segment(ray, idx, &mut ts);
+        }
+        // We stash the segment index in u, so that we can find the normal later.
+        ts.into_iter().map(|(t, idx)| intersection_with_uv(t, shape, idx as f64, 0.)).collect()
+    }
+
+    fn local_normal_at(&self, object_point: &Tuple, intersection: &Intersection) -> Tuple {
+        let idx = (intersection.u as usize).min(self.profile.len() - 2);
+        let (r0, y0) = self.profile[idx];
+        let (r1, y1) = self.profile[idx + 1];
+        // Perpendicular to the segment in the (radius, y) plane.
+        let (nr, ny) = (y1 - y0, r0 - r1);
+        let r = (object_point.x * object_point.x + object_point.z * object_point.z).sqrt();
+        if r < EPSILON {
+            return vector(0., ny.signum(), 0.);
+        }
+        vector(nr * object_point.x / r, ny, nr * object_point.z / r)
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        self.bb
+    }
+}
+
+impl Shape {
+    pub fn as_lathe(&self) -> Option<&Lathe> {
+        if let ShapeType::Lathe(l) = &self.shape_type { Some(l) } else { None }
+    }
+}
+
+pub fn lathe(profile: &[(f64, f64)]) -> Shape {
+    assert!(profile.len() >= 2);
+    let rmax = profile.iter().map(|(r, _)| r.abs()).fold(0., f64::max);
+    let ymin = profile.iter().map(|(_, y)| *y).fold(f64::INFINITY, f64::min);
+    let ymax = profile.iter().map(|(_, y)| *y).fold(f64::NEG_INFINITY, f64::max);
+    let mut s = test_shape();
+    s.shape_type = ShapeType::Lathe(Box::new(Lathe {
+        profile: profile.to_vec(),
+        bb: BoundingBox::new(point(-rmax, ymin, -rmax), point(rmax, ymax, rmax)),
+    }));
+    s
+}
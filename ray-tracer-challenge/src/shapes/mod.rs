@@ -28,6 +28,33 @@ pub use csg::*;
 pub mod spheres;
 pub use spheres::*;
 
+pub mod implicit_surfaces;
+pub use implicit_surfaces::*;
+
+pub mod quadrics;
+pub use quadrics::*;
+
+pub mod lathes;
+pub use lathes::*;
+
+pub mod bezier_patches;
+pub use bezier_patches::*;
+
+pub mod disks;
+pub use disks::*;
+
+pub mod quads;
+pub use quads::*;
+
+pub mod heightfields;
+pub use heightfields::*;
+
+pub mod instances;
+pub use instances::*;
+
+pub mod tessellation;
+pub use tessellation::*;
+
 pub mod camera;
 pub use camera::*;
 
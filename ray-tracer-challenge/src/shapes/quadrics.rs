@@ -0,0 +1,124 @@
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, vector, EPSILON, LocalShape, Intersection, BoundingBox, point, f64_for_bound, position};
+
+/// A general quadric surface, the zero set of
+/// a x^2 + b y^2 + c z^2 + d xy + e xz + f yz + g x + h y + i z + j.
+/// Hits outside of the axis-aligned clipping box [minimum, maximum] are discarded, leaving the surface open.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Quadric {
+    pub coefficients: [f64; 10],
+    pub minimum: Tuple,
+    pub maximum: Tuple,
+}
+
+impl Quadric {
+    fn contains(&self, p: &Tuple) -> bool {
+        (0..3).all(|dim| self.minimum[dim] - EPSILON <= p[dim] && p[dim] <= self.maximum[dim] + EPSILON)
+    }
+}
+
+impl Shape {
+    pub fn as_quadric(&self) -> Option<&Quadric> {
+        if let ShapeType::Quadric(q) = &self.shape_type { Some(q) } else { None }
+    }
+    fn as_quadric_mut(&mut self) -> Option<&mut Quadric> {
+        if let ShapeType::Quadric(q) = &mut self.shape_type { Some(q) } else { None }
+    }
+
+    pub fn set_clip(&mut self, minimum: &Tuple, maximum: &Tuple) {
+        let q = self.as_quadric_mut().unwrap();
+        q.minimum = *minimum;
+        q.maximum = *maximum;
+    }
+}
+
+impl LocalShape for Quadric {
+    fn local_intersect_ts(&self, ray: &Ray) -> Vec<f64> {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        let o = ray.origin;
+        let v = ray.direction;
+
+        let qa = a * v.x * v.x + b * v.y * v.y + c * v.z * v.z +
+            d * v.x * v.y + e * v.x * v.z + f * v.y * v.z;
+        let qb = 2. * (a * o.x * v.x + b * o.y * v.y + c * o.z * v.z) +
+            d * (o.x * v.y + o.y * v.x) + e * (o.x * v.z + o.z * v.x) + f * (o.y * v.z + o.z * v.y) +
+            g * v.x + h * v.y + i * v.z;
+        let qc = a * o.x * o.x + b * o.y * o.y + c * o.z * o.z +
+            d * o.x * o.y + e * o.x * o.z + f * o.y * o.z +
+            g * o.x + h * o.y + i * o.z + j;
+
+        // Relative to the other terms, since the coefficients of a large quadric are tiny.
+        let ts = if qa.abs() <= 1e-12 * (qb.abs() + qc.abs()) {
+            // Ray is parallel to an asymptote, like in the cone case.
+            if qb == 0. { vec![] } else { vec![-qc / qb] }
+        } else {
+            let disc = qb * qb - 4. * qa * qc;
+            if disc < 0. {
+                return vec![];
+            }
+            let mut t0 = (-qb - disc.sqrt()) / (2. * qa);
+            let mut t1 = (-qb + disc.sqrt()) / (2. * qa);
+            if t0 > t1 {
+                (t0, t1) = (t1, t0);
+            }
+            vec![t0, t1]
+        };
+        ts.into_iter().filter(|t| self.contains(&position(ray, *t))).collect()
+    }
+
+    fn local_normal_at(&self, object_point: &Tuple, _intersection: &Intersection) -> Tuple {
+        let [a, b, c, d, e, f, g, h, i, _j] = self.coefficients;
+        let p = object_point;
+        vector(
+            2. * a * p.x + d * p.y + e * p.z + g,
+            2. * b * p.y + d * p.x + f * p.z + h,
+            2. * c * p.z + e * p.x + f * p.y + i,
+        )
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(
+            point(f64_for_bound(self.minimum.x), f64_for_bound(self.minimum.y), f64_for_bound(self.minimum.z)),
+            point(f64_for_bound(self.maximum.x), f64_for_bound(self.maximum.y), f64_for_bound(self.maximum.z)),
+        )
+    }
+}
+
+pub fn quadric(coefficients: &[f64; 10]) -> Shape {
+    let mut s = test_shape();
+    s.shape_type = ShapeType::Quadric(Box::new(Quadric {
+        coefficients: *coefficients,
+        minimum: point(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY),
+        maximum: point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+    }));
+    s
+}
+
+pub fn ellipsoid(rx: f64, ry: f64, rz: f64) -> Shape {
+    let mut s = quadric(&[1. / (rx * rx), 1. / (ry * ry), 1. / (rz * rz), 0., 0., 0., 0., 0., 0., -1.]);
+    s.set_clip(&point(-rx, -ry, -rz), &point(rx, ry, rz));
+    s
+}
+
+/// The paraboloid y = x^2 + z^2, clipped to 0 <= y <= height.
+pub fn paraboloid(height: f64) -> Shape {
+    let mut s = quadric(&[1., 0., 1., 0., 0., 0., 0., -1., 0., 0.]);
+    let r = height.sqrt();
+    s.set_clip(&point(-r, 0., -r), &point(r, height, r));
+    s
+}
+
+/// The hyperboloid of one sheet x^2 + z^2 - y^2 = waist^2, clipped to -height <= y <= height.
+pub fn hyperboloid(waist: f64, height: f64) -> Shape {
+    let mut s = quadric(&[1., -1., 1., 0., 0., 0., 0., 0., 0., -waist * waist]);
+    let r = (waist * waist + height * height).sqrt();
+    s.set_clip(&point(-r, -height, -r), &point(r, height, r));
+    s
+}
+
+/// The hyperboloid of two sheets y^2 - x^2 - z^2 = 1, clipped to -height <= y <= height.
+pub fn hyperboloid_two_sheets(height: f64) -> Shape {
+    let mut s = quadric(&[-1., 1., -1., 0., 0., 0., 0., 0., 0., -1.]);
+    let r = (height * height - 1.).max(0.).sqrt();
+    s.set_clip(&point(-r, -height, -r), &point(r, height, r));
+    s
+}
@@ -0,0 +1,35 @@
+use crate::{Shape, ShapeType, test_shape, Tuple, Ray, EPSILON, vector, LocalShape, Intersection, BoundingBox, point, intersection_with_uv};
+
+// A square in the xz plane, from -1 to 1 on both axes and facing +y.
+// Use a scaling transform to make a rectangle of any size.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Quad {}
+
+impl LocalShape for Quad {
+    fn local_intersect<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection<'a>> {
+        if ray.direction.y.abs() < EPSILON {
+            return vec![];
+        }
+        let t = -ray.origin.y / ray.direction.y;
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        if x.abs() > 1. || z.abs() > 1. {
+            return vec![];
+        }
+        vec![intersection_with_uv(t, shape, (x + 1.) / 2., (z + 1.) / 2.)]
+    }
+
+    fn local_normal_at(&self, _object_point: &Tuple, _intersection: &Intersection) -> Tuple {
+        vector(0., 1., 0.)
+    }
+
+    fn local_bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(point(-1., 0., -1.), point(1., 0., 1.))
+    }
+}
+
+pub fn quad() -> Shape {
+    let mut s = test_shape();
+    s.shape_type = ShapeType::Quad(Quad {});
+    s
+}
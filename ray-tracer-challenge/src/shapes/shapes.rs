@@ -1,7 +1,7 @@
 use core::panic;
 use std::{f64::INFINITY};
 
-use crate::{Matrix, Material, Tuple, Intersections, Ray, inverse, transpose, normalize, point, transform, intersection, Intersection, ray, identity_matrix, material, vector, Cylinder, Cone, Group, intersections, Triangle, SmoothTriangle, Cube, Sphere, Plane, BoundingBox, CSG};
+use crate::{Matrix, Material, Tuple, Intersections, Ray, inverse, transpose, normalize, point, transform, intersection, Intersection, ray, identity_matrix, material, vector, Cylinder, Cone, Group, intersections, Triangle, SmoothTriangle, Cube, Sphere, Plane, BoundingBox, CSG, ImplicitSurface, Quadric, Lathe, BezierPatch, Disk, Quad, Heightfield, Instance};
 
 pub trait LocalShape {
     fn local_normal_at(&self, object_point: &Tuple, intersection: &Intersection) -> Tuple;
@@ -18,6 +18,13 @@ pub trait LocalShape {
     // Only implemented by group, which manages BVH
     fn local_intersect_closest_hit<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection> { self.local_intersect(shape, ray) }
     fn local_intersect_any_hit<'a>(&'a self, shape: &'a Shape, ray: &Ray) -> Vec<Intersection> { self.local_intersect(shape, ray) }
+
+    // Any shadow casting hit with 0 < t < t_max. Primitives only have a few hits, so the default
+    // checks them all, keeping the last like the AnyHit policy does. Aggregates override this to
+    // stop at the first child that blocks the ray.
+    fn local_intersect_occluder<'a>(&'a self, shape: &'a Shape, ray: &Ray, t_max: f64) -> Option<Intersection<'a>> {
+        self.local_intersect(shape, ray).into_iter().rev().find(|i| 0. < i.t && i.t < t_max && i.casts_shadow())
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -52,6 +59,14 @@ pub enum ShapeType {
     Triangle(Triangle),
     SmoothTriangle(SmoothTriangle),
     CSG(CSG),
+    ImplicitSurface(Box<ImplicitSurface>),
+    Quadric(Box<Quadric>),
+    Lathe(Box<Lathe>),
+    BezierPatch(Box<BezierPatch>),
+    Disk(Disk),
+    Quad(Quad),
+    Heightfield(Box<Heightfield>),
+    Instance(Box<Instance>),
     TestShape(TestShape),
 }
 
@@ -68,6 +83,14 @@ impl ShapeType {
             ShapeType::Triangle(s) => s,
             ShapeType::SmoothTriangle(s) => s,
             ShapeType::CSG(s) => s,
+            ShapeType::ImplicitSurface(s) => s.as_ref(),
+            ShapeType::Quadric(s) => s.as_ref(),
+            ShapeType::Lathe(s) => s.as_ref(),
+            ShapeType::BezierPatch(s) => s.as_ref(),
+            ShapeType::Disk(s) => s,
+            ShapeType::Quad(s) => s,
+            ShapeType::Heightfield(s) => s.as_ref(),
+            ShapeType::Instance(s) => s.as_ref(),
         }
     }
 
@@ -137,6 +160,18 @@ impl Shape {
         self.local_intersect_any_hit(&self.local_from_parent_ray(parent_ray))
     }
 
+    pub fn local_intersect_occluder(&self, object_ray: &Ray, t_max: f64) -> Option<Intersection<'_>> {
+        self.shape_type.as_local_shape().local_intersect_occluder(self, object_ray, t_max)
+    }
+    /// Finds any hit that would block light, between the ray's origin and `t_max`. Shapes with
+    /// `shadow` turned off, or inside groups with it turned off, don't block anything.
+    pub fn intersect_occluder(&self, parent_ray: &Ray, t_max: f64) -> Option<Intersection<'_>> {
+        if !self.shadow {
+            return None;
+        }
+        self.local_intersect_occluder(&self.local_from_parent_ray(parent_ray), t_max)
+    }
+
     pub fn as_local_shape(&self) -> &dyn LocalShape {
         self.shape_type.as_local_shape()
     }
@@ -226,6 +261,13 @@ pub fn normal_at2(shape: &Shape, world_point: &Tuple) -> Tuple {
 }
 
 pub fn normal_at3(shape: &Shape, world_point: &Tuple, intersection: &Intersection) -> Tuple {
+    if let Some(instance) = intersection.instance {
+        // The shape's transforms are relative to the prototype, which sits in the instance's object space.
+        let prototype_point = world_to_object(instance, world_point);
+        let object_point = world_to_object(shape, &prototype_point);
+        let object_normal = shape.shape_type.local_normal_at(&object_point, intersection);
+        return normal_to_world(instance, &normal_to_world(shape, &object_normal));
+    }
     let object_point = world_to_object(shape, world_point);
     let object_normal = shape.shape_type.local_normal_at(&object_point, intersection);
     normal_to_world(shape, &object_normal)
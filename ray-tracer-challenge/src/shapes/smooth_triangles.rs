@@ -9,6 +9,7 @@ use crate::ShapeType;
 use crate::Tuple;
 use crate::equal;
 use crate::intersection_with_uv;
+use crate::interpolate_uvs;
 use crate::test_shape;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
@@ -22,6 +23,16 @@ pub struct SmoothTriangle {
     pub n1: Tuple,
     pub n2: Tuple,
     pub n3: Tuple,
+
+    // Texture coordinates at p1, p2 and p3.
+    pub uvs: Option<[(f64, f64); 3]>,
+}
+
+impl SmoothTriangle {
+    /// Texture coordinates where an intersection hit us.
+    pub fn uv_at(&self, intersection: &Intersection) -> Option<(f64, f64)> {
+        Some(interpolate_uvs(&self.uvs?, intersection.u, intersection.v))
+    }
 }
 
 impl LocalShape for SmoothTriangle {
@@ -92,6 +103,7 @@ pub fn smooth_triangle(
         n1: *n1,
         n2: *n2,
         n3: *n3,
+        uvs: None,
     });
     s
 }
@@ -0,0 +1,400 @@
+use std::f64::consts::PI;
+
+use crate::{Tuple, Matrix, Shape, ShapeType, Material, World, BoundingBox, point, vector, ray, inverse, transpose, determinant, identity_matrix, intersection_allowed, tessellate_bezier_patch, EPSILON};
+
+/// Triangles with a normal at each vertex, all sharing one material. Triangles wind
+/// counter-clockwise when seen from the side their normals point to.
+#[derive(Debug, Clone)]
+pub struct TriangleMesh {
+    pub material: Material,
+    pub positions: Vec<Tuple>,
+    pub normals: Vec<Tuple>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+/// How finely to tessellate curved and unbounded shapes.
+#[derive(Debug, Clone, Copy)]
+pub struct TessellationOptions {
+    /// Divisions around spheres, cylinders, cones, disks and lathes.
+    pub segments: usize,
+    /// Divisions from pole to pole of spheres.
+    pub rings: usize,
+    /// Grid cells along each axis for implicit surfaces and quadrics, and along each side of
+    /// Bézier patches.
+    pub resolution: usize,
+    /// Planes become squares reaching this far from their origin, and infinite cylinders, cones
+    /// and quadrics are cut off this far out.
+    pub extent: f64,
+}
+
+impl Default for TessellationOptions {
+    fn default() -> Self {
+        TessellationOptions { segments: 32, rings: 16, resolution: 32, extent: 50. }
+    }
+}
+
+impl TriangleMesh {
+    pub fn new(material: &Material) -> TriangleMesh {
+        TriangleMesh { material: *material, positions: vec![], normals: vec![], triangles: vec![] }
+    }
+
+    fn add_vertex(&mut self, p: &Tuple, n: &Tuple) -> usize {
+        self.positions.push(*p);
+        self.normals.push(*n);
+        self.positions.len() - 1
+    }
+
+    // Adds a triangle, flipping it if needed to wind counter-clockwise around its vertex normals.
+    // Degenerate ones, like those at the poles of spheres, are dropped.
+    fn add_oriented(&mut self, [a, b, c]: [usize; 3]) {
+        let (pa, pb, pc) = (self.positions[a], self.positions[b], self.positions[c]);
+        let face = (pb - pa).cross(&(pc - pa));
+        if face.magnitude() < 1e-12 {
+            return;
+        }
+        let n = self.normals[a] + self.normals[b] + self.normals[c];
+        self.triangles.push(if face.dot(&n) < 0. { [a, c, b] } else { [a, b, c] });
+    }
+
+    fn append(&mut self, other: &TriangleMesh) {
+        let offset = self.positions.len();
+        self.positions.extend(&other.positions);
+        self.normals.extend(&other.normals);
+        self.triangles.extend(other.triangles.iter().map(|t| t.map(|i| i + offset)));
+    }
+
+    /// Moves the mesh by `m`, keeping its normals perpendicular to the surface and its triangles
+    /// winding the same way around them.
+    pub fn transform(&mut self, m: &Matrix) {
+        let normal_matrix = transpose(&inverse(m));
+        for p in &mut self.positions {
+            *p = *m * *p;
+        }
+        for n in &mut self.normals {
+            let mut t = normal_matrix * *n;
+            t.w = 0.;
+            *n = t.normalized();
+        }
+        if determinant(m) < 0. {
+            for t in &mut self.triangles {
+                t.swap(1, 2);
+            }
+        }
+    }
+
+    // Turns the mesh inside out.
+    fn flip(&mut self) {
+        for n in &mut self.normals {
+            *n = -*n;
+        }
+        for t in &mut self.triangles {
+            t.swap(1, 2);
+        }
+    }
+}
+
+// A grid of `rows` by `cols` quads over u and v in [0, 1], each split into two triangles. `f` gives
+// the point and normal at (u, v).
+fn surface(mesh: &mut TriangleMesh, rows: usize, cols: usize, f: impl Fn(f64, f64) -> (Tuple, Tuple)) {
+    let start = mesh.positions.len();
+    for j in 0..=rows {
+        for i in 0..=cols {
+            let (p, n) = f(i as f64 / cols as f64, j as f64 / rows as f64);
+            mesh.add_vertex(&p, &n);
+        }
+    }
+    let at = |i: usize, j: usize| start + j * (cols + 1) + i;
+    for j in 0..rows {
+        for i in 0..cols {
+            mesh.add_oriented([at(i, j), at(i + 1, j), at(i, j + 1)]);
+            mesh.add_oriented([at(i + 1, j + 1), at(i, j + 1), at(i + 1, j)]);
+        }
+    }
+}
+
+// A flat ring in the plane at `y`, facing up or down.
+fn annulus(mesh: &mut TriangleMesh, segments: usize, y: f64, inner: f64, outer: f64, up: bool) {
+    let n = vector(0., if up { 1. } else { -1. }, 0.);
+    surface(mesh, 1, segments, |u, v| {
+        let (r, phi) = (inner + (outer - inner) * v, 2. * PI * u);
+        (point(r * phi.cos(), y, r * phi.sin()), n)
+    });
+}
+
+// Marching tetrahedra over a grid in `bounds`, finding the surface where `f` crosses zero.
+fn isosurface(mesh: &mut TriangleMesh, bounds: &BoundingBox, n: usize, f: impl Fn(&Tuple) -> f64, gradient: impl Fn(&Tuple) -> Tuple) {
+    // Six tetrahedra around the diagonal from corner 0 to corner 7, where bit 0 of a corner is
+    // its x offset, bit 1 its y offset and bit 2 its z offset.
+    const TETRAHEDRA: [[usize; 4]; 6] = [[0, 1, 3, 7], [0, 3, 2, 7], [0, 2, 6, 7], [0, 6, 4, 7], [0, 4, 5, 7], [0, 5, 1, 7]];
+    let size = bounds.max - bounds.min;
+    let grid_point = |i: usize, j: usize, k: usize| point(
+        bounds.min.x + size.x * i as f64 / n as f64,
+        bounds.min.y + size.y * j as f64 / n as f64,
+        bounds.min.z + size.z * k as f64 / n as f64,
+    );
+    let index = |i: usize, j: usize, k: usize| (k * (n + 1) + j) * (n + 1) + i;
+    let mut values = vec![0.; (n + 1) * (n + 1) * (n + 1)];
+    for k in 0..=n {
+        for j in 0..=n {
+            for i in 0..=n {
+                values[index(i, j, k)] = f(&grid_point(i, j, k));
+            }
+        }
+    }
+
+    for k in 0..n {
+        for j in 0..n {
+            for i in 0..n {
+                let corner = |c: usize| (i + (c & 1), j + ((c >> 1) & 1), k + ((c >> 2) & 1));
+                for tet in TETRAHEDRA {
+                    let ps = tet.map(|c| { let (a, b, c) = corner(c); grid_point(a, b, c) });
+                    let vs = tet.map(|c| { let (a, b, c) = corner(c); values[index(a, b, c)] });
+                    let (inside, outside): (Vec<usize>, Vec<usize>) = (0..4).partition(|&a| vs[a] < 0.);
+                    let crossing = |a: usize, b: usize| ps[a] + (ps[b] - ps[a]) * (vs[a] / (vs[a] - vs[b]));
+                    let polygon: Vec<Tuple> = match (inside.as_slice(), outside.as_slice()) {
+                        ([lone], others) | (others, [lone]) if others.len() == 3 => others.iter().map(|&o| crossing(*lone, o)).collect(),
+                        ([a, b], [c, d]) => vec![crossing(*a, *c), crossing(*a, *d), crossing(*b, *d), crossing(*b, *c)],
+                        _ => continue,
+                    };
+                    let face = (polygon[1] - polygon[0]).cross(&(polygon[2] - polygon[0]));
+                    let start = mesh.positions.len();
+                    for p in &polygon {
+                        let g = gradient(p);
+                        // Where the gradient vanishes, the face is the best guess we have.
+                        let normal = if g.magnitude() > 1e-12 { g.normalized() } else { face.normalized() };
+                        mesh.add_vertex(p, &normal);
+                    }
+                    mesh.add_oriented([start, start + 1, start + 2]);
+                    if polygon.len() == 4 {
+                        mesh.add_oriented([start, start + 2, start + 3]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Whether `p` is inside a closed shape, by counting the crossings of a ray from it.
+fn is_inside(shape: &Shape, p: &Tuple) -> bool {
+    let r = ray(p, &vector(0.267, 0.534, 0.802));
+    shape.intersect(&r).iter().filter(|x| x.t > EPSILON).count() % 2 == 1
+}
+
+// Adds a mesh to the list, merging it into the last one when they share a material.
+fn push(out: &mut Vec<TriangleMesh>, mesh: TriangleMesh) {
+    if mesh.triangles.is_empty() {
+        return;
+    }
+    match out.last_mut() {
+        Some(last) if last.material == mesh.material => last.append(&mesh),
+        _ => out.push(mesh),
+    }
+}
+
+// The triangles of `mesh` for which `keep` is true.
+fn filter_triangles(mesh: &TriangleMesh, keep: impl Fn(&Tuple) -> bool) -> TriangleMesh {
+    let mut kept = TriangleMesh::new(&mesh.material);
+    let mut remap = vec![usize::MAX; mesh.positions.len()];
+    for t in &mesh.triangles {
+        let [a, b, c] = t.map(|i| mesh.positions[i]);
+        let centroid = a + ((b - a) + (c - a)) / 3.;
+        if !keep(&centroid) {
+            continue;
+        }
+        let t = t.map(|i| {
+            if remap[i] == usize::MAX {
+                remap[i] = kept.add_vertex(&mesh.positions[i], &mesh.normals[i]);
+            }
+            remap[i]
+        });
+        kept.triangles.push(t);
+    }
+    kept
+}
+
+fn walk(shape: &Shape, parent_to_world: &Matrix, material_override: Option<Material>, options: &TessellationOptions, out: &mut Vec<TriangleMesh>) {
+    let to_world = *parent_to_world * shape.local_transform();
+    let mut mesh = TriangleMesh::new(&material_override.unwrap_or(shape.material));
+    let (segments, e) = (options.segments.max(3), options.extent);
+    match &shape.shape_type {
+        ShapeType::Group(g) => {
+            for c in &g.children {
+                walk(c, &to_world, material_override, options, out);
+            }
+            return;
+        }
+        ShapeType::Instance(i) => {
+            walk(&i.prototype, &to_world, i.material.or(material_override), options, out);
+            return;
+        }
+        ShapeType::BezierPatch(p) => {
+            let material = material_override.unwrap_or(shape.material);
+            walk(&tessellate_bezier_patch(p, options.resolution), &to_world, Some(material), options, out);
+            return;
+        }
+        ShapeType::CSG(csg) => {
+            // Each child keeps the triangles the operation would let a ray hit, judged at their
+            // centers, so edges along the cut are jagged.
+            let op = shape.operation();
+            for (k, child) in csg.children.iter().enumerate() {
+                let other = &csg.children[1 - k];
+                let mut parts = vec![];
+                walk(child, &identity_matrix, material_override, options, &mut parts);
+                for part in &parts {
+                    let mut kept = filter_triangles(part, |p| {
+                        let in_other = is_inside(other, p);
+                        if k == 0 {
+                            intersection_allowed(&op, &true, &false, &in_other)
+                        } else {
+                            intersection_allowed(&op, &false, &in_other, &false)
+                        }
+                    });
+                    // What's left of the right shape lines the hole it leaves.
+                    if k == 1 && op == "difference" {
+                        kept.flip();
+                    }
+                    kept.transform(&to_world);
+                    push(out, kept);
+                }
+            }
+            return;
+        }
+        ShapeType::Sphere(_) => {
+            surface(&mut mesh, options.rings.max(2), segments, |u, v| {
+                let (theta, phi) = (PI * v, 2. * PI * u);
+                let p = point(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                (p, p - point(0., 0., 0.))
+            });
+        }
+        ShapeType::Plane(_) | ShapeType::Quad(_) => {
+            let size = if matches!(shape.shape_type, ShapeType::Plane(_)) { e } else { 1. };
+            surface(&mut mesh, 1, 1, |u, v| (point(size * (2. * u - 1.), 0., size * (2. * v - 1.)), vector(0., 1., 0.)));
+        }
+        ShapeType::Disk(d) => {
+            annulus(&mut mesh, segments, 0., d.inner_radius, d.outer_radius, true);
+        }
+        ShapeType::Cube(_) => {
+            for axis in 0..3 {
+                for sign in [-1., 1.] {
+                    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+                    surface(&mut mesh, 1, 1, |u, v| {
+                        let (mut p, mut n) = (point(0., 0., 0.), vector(0., 0., 0.));
+                        p[axis] = sign;
+                        p[a] = 2. * u - 1.;
+                        p[b] = 2. * v - 1.;
+                        n[axis] = sign;
+                        (p, n)
+                    });
+                }
+            }
+        }
+        ShapeType::Cylinder(c) => {
+            let (lo, hi) = (c.minimum.max(-e), c.maximum.min(e));
+            surface(&mut mesh, 1, segments, |u, v| {
+                let (y, phi) = (lo + (hi - lo) * v, 2. * PI * u);
+                (point(phi.cos(), y, phi.sin()), vector(phi.cos(), 0., phi.sin()))
+            });
+            if c.closed {
+                if c.minimum.is_finite() {
+                    annulus(&mut mesh, segments, lo, 0., 1., false);
+                }
+                if c.maximum.is_finite() {
+                    annulus(&mut mesh, segments, hi, 0., 1., true);
+                }
+            }
+        }
+        ShapeType::Cone(c) => {
+            let (lo, hi) = (c.minimum.max(-e), c.maximum.min(e));
+            // The two nappes meet at the apex, where the normals change direction.
+            let bands = if lo < 0. && 0. < hi { vec![(lo, 0.), (0., hi)] } else { vec![(lo, hi)] };
+            for (a, b) in bands {
+                let side = -(a + b).signum();
+                surface(&mut mesh, 1, segments, |u, v| {
+                    let (y, phi) = (a + (b - a) * v, 2. * PI * u);
+                    (point(y.abs() * phi.cos(), y, y.abs() * phi.sin()), vector(phi.cos(), side, phi.sin()).normalized())
+                });
+            }
+            if c.closed {
+                if c.minimum.is_finite() {
+                    annulus(&mut mesh, segments, lo, 0., lo.abs(), false);
+                }
+                if c.maximum.is_finite() {
+                    annulus(&mut mesh, segments, hi, 0., hi.abs(), true);
+                }
+            }
+        }
+        ShapeType::Triangle(t) => {
+            // Keeping the vertex order, so meshes that came from files keep their winding.
+            let n = t.e1.cross(&t.e2).normalized();
+            let start = mesh.positions.len();
+            for p in [t.p1, t.p2, t.p3] {
+                mesh.add_vertex(&p, &n);
+            }
+            mesh.triangles.push([start, start + 1, start + 2]);
+        }
+        ShapeType::SmoothTriangle(t) => {
+            let start = mesh.positions.len();
+            for (p, n) in [(t.p1, t.n1), (t.p2, t.n2), (t.p3, t.n3)] {
+                mesh.add_vertex(&p, &n.normalized());
+            }
+            mesh.triangles.push([start, start + 1, start + 2]);
+        }
+        ShapeType::Lathe(l) => {
+            for w in l.profile.windows(2) {
+                let ((r0, y0), (r1, y1)) = (w[0], w[1]);
+                // Perpendicular to the segment in the (radius, y) plane, as when rendering.
+                let (nr, ny) = (y1 - y0, r0 - r1);
+                surface(&mut mesh, 1, segments, |u, v| {
+                    let (r, y, phi) = (r0 + (r1 - r0) * v, y0 + (y1 - y0) * v, 2. * PI * u);
+                    (point(r * phi.cos(), y, r * phi.sin()), vector(nr * phi.cos(), ny, nr * phi.sin()).normalized())
+                });
+            }
+        }
+        ShapeType::Heightfield(h) => {
+            let (w, d) = (h.width - 1, h.depth - 1);
+            surface(&mut mesh, d, w, |u, v| {
+                let (i, j) = ((u * w as f64).round() as usize, (v * d as f64).round() as usize);
+                (h.vertex(i, j), h.vertex_normal(i, j))
+            });
+        }
+        ShapeType::ImplicitSurface(s) => {
+            isosurface(&mut mesh, &s.bounds, options.resolution.max(1), |p| s.sdf.distance(p), |p| s.sdf.gradient(p));
+        }
+        ShapeType::Quadric(q) => {
+            let bounds = BoundingBox::new(
+                point(q.minimum.x.max(-e), q.minimum.y.max(-e), q.minimum.z.max(-e)),
+                point(q.maximum.x.min(e), q.maximum.y.min(e), q.maximum.z.min(e)),
+            );
+            let [a, b, c, d, e, f, g, h, i, j] = q.coefficients;
+            isosurface(
+                &mut mesh, &bounds, options.resolution.max(1),
+                |p| a * p.x * p.x + b * p.y * p.y + c * p.z * p.z + d * p.x * p.y + e * p.x * p.z + f * p.y * p.z + g * p.x + h * p.y + i * p.z + j,
+                |p| vector(
+                    2. * a * p.x + d * p.y + e * p.z + g,
+                    2. * b * p.y + d * p.x + f * p.z + h,
+                    2. * c * p.z + e * p.x + f * p.y + i,
+                ),
+            );
+        }
+        ShapeType::TestShape(_) => {}
+    }
+    mesh.transform(&to_world);
+    push(out, mesh);
+}
+
+/// Turns a shape into triangle meshes in its parent's space, one for each run of shapes with the
+/// same material. Groups, instances and CSG are flattened, with their transforms applied.
+pub fn tessellate(shape: &Shape, options: &TessellationOptions) -> Vec<TriangleMesh> {
+    let mut out = vec![];
+    walk(shape, &identity_matrix, None, options, &mut out);
+    out
+}
+
+/// Tessellates every object in the world, in world space.
+pub fn tessellate_world(world: &World, options: &TessellationOptions) -> Vec<TriangleMesh> {
+    let mut out = vec![];
+    for o in &world.objects {
+        walk(o, &identity_matrix, None, options, &mut out);
+    }
+    out
+}
@@ -18,6 +18,31 @@ pub struct Triangle {
     pub e1: Tuple,
     pub e2: Tuple,
     pub normal: Tuple,
+    // Texture coordinates at p1, p2 and p3.
+    pub uvs: Option<[(f64, f64); 3]>,
+}
+
+// Interpolates texture coordinates, given barycentric weights for p2 and p3.
+pub(crate) fn interpolate_uvs(uvs: &[(f64, f64); 3], u: f64, v: f64) -> (f64, f64) {
+    let w = 1. - u - v;
+    (
+        uvs[0].0 * w + uvs[1].0 * u + uvs[2].0 * v,
+        uvs[0].1 * w + uvs[1].1 * u + uvs[2].1 * v,
+    )
+}
+
+impl Triangle {
+    /// Texture coordinates at a point on the triangle, in object space.
+    pub fn uv_at(&self, object_point: &Tuple) -> Option<(f64, f64)> {
+        let uvs = self.uvs?;
+        let p = *object_point - self.p1;
+        let (d00, d01, d11) = (self.e1.dot(&self.e1), self.e1.dot(&self.e2), self.e2.dot(&self.e2));
+        let (d20, d21) = (p.dot(&self.e1), p.dot(&self.e2));
+        let denom = d00 * d11 - d01 * d01;
+        let u = (d11 * d20 - d01 * d21) / denom;
+        let v = (d00 * d21 - d01 * d20) / denom;
+        Some(interpolate_uvs(&uvs, u, v))
+    }
 }
 
 impl LocalShape for Triangle {
@@ -77,6 +102,22 @@ impl Shape {
     pub fn e1(&self) -> Tuple { self.as_triangle().unwrap().e1 }
     pub fn e2(&self) -> Tuple { self.as_triangle().unwrap().e2 }
     pub fn normal(&self) -> Tuple { self.as_triangle().unwrap().normal }
+
+    pub fn uvs(&self) -> Option<[(f64, f64); 3]> {
+        match &self.shape_type {
+            ShapeType::Triangle(t) => t.uvs,
+            ShapeType::SmoothTriangle(t) => t.uvs,
+            _ => None,
+        }
+    }
+
+    pub fn set_uvs(&mut self, uvs: &[(f64, f64); 3]) {
+        match &mut self.shape_type {
+            ShapeType::Triangle(t) => t.uvs = Some(*uvs),
+            ShapeType::SmoothTriangle(t) => t.uvs = Some(*uvs),
+            _ => panic!("Only triangles have texture coordinates"),
+        }
+    }
 }
 
 pub fn triangle(
@@ -94,6 +135,7 @@ pub fn triangle(
         e1,
         e2,
         normal: e2.cross(&e1).normalized(),
+        uvs: None,
     });
     s
 }
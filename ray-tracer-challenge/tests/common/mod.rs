@@ -0,0 +1,50 @@
+// Helpers shared by the integration tests. Every test file is a crate of its own that uses some
+// of them, so the rest would be dead code there.
+#![allow(dead_code)]
+
+use ray_tracer_challenge::*;
+
+pub fn times(xs: &[Intersection]) -> Vec<f64> {
+    xs.iter().map(|i| i.t).collect()
+}
+
+// Where a ray in object space hits a shape, in order.
+pub fn local_times(s: &Shape, r: &Ray) -> Vec<f64> {
+    times(&local_intersect(s, r).data)
+}
+
+pub fn assert_times(actual: &[f64], expected: &[f64]) {
+    assert_eq!(actual.len(), expected.len(), "{actual:?} != {expected:?}");
+    for (a, e) in actual.iter().zip(expected) {
+        assert!(equal(*a, *e), "{actual:?} != {expected:?}");
+    }
+}
+
+// An n by n grid of spheres, at slightly different depths so their boxes don't line up.
+pub fn spheres(n: usize) -> Vec<Shape> {
+    let mut shapes = vec![];
+    for i in 0..n {
+        for j in 0..n {
+            let mut s = sphere();
+            s.set_transform(&translation(i as f64 * 3., j as f64 * 3., (i * j) as f64 * 0.1));
+            shapes.push(s);
+        }
+    }
+    shapes
+}
+
+// Skips every BVH, so stale ones can't hide.
+pub fn all_hits<'a>(s: &'a Shape, r: &Ray) -> Vec<Intersection<'a>> {
+    match s.as_group() {
+        Some(g) => {
+            let r = transform(r, &s.local_inverse());
+            g.children.iter().flat_map(|c| all_hits(c, &r)).collect()
+        }
+        None => s.intersect(r),
+    }
+}
+
+// What a world's BVHs should find, by testing every shape in it.
+pub fn brute_force<'a>(w: &'a World, r: &Ray) -> Intersections<'a> {
+    intersections(w.objects.iter().flat_map(|o| all_hits(o, r)).collect())
+}
@@ -0,0 +1,107 @@
+use ray_tracer_challenge::*;
+
+fn flat_patch() -> [Tuple; 16] {
+    // Control points on a regular grid in the xz plane, from -1.5 to 1.5.
+    let mut cps = [point(0., 0., 0.); 16];
+    for j in 0..4 {
+        for i in 0..4 {
+            cps[j * 4 + i] = point(i as f64 - 1.5, 0., j as f64 - 1.5);
+        }
+    }
+    cps
+}
+
+fn bumpy_patch() -> [Tuple; 16] {
+    let mut cps = flat_patch();
+    for idx in [5, 6, 9, 10] {
+        cps[idx].y = 1.;
+    }
+    cps
+}
+
+#[test]
+fn test_patch_evaluation() {
+    let p = BezierPatch::new(&flat_patch());
+    assert_eq!(p.point_at(0., 0.), point(-1.5, 0., -1.5));
+    assert_eq!(p.point_at(1., 1.), point(1.5, 0., 1.5));
+    assert_eq!(p.point_at(0.5, 0.5), point(0., 0., 0.));
+    assert_eq!(p.normal_at(0.25, 0.75).normalized(), vector(0., -1., 0.));
+
+    let p = BezierPatch::new(&bumpy_patch());
+    assert_eq!(p.point_at(0.5, 0.5), point(0., 0.5625, 0.));
+}
+
+#[test]
+fn test_patch_intersect() {
+    let s = bezier_patch(&flat_patch());
+    let xs = intersect(&s, &ray(&point(0.5, 2., -0.25), &vector(0., -1., 0.)));
+    assert_eq!(xs.count, 1);
+    assert!(equal(xs[0].t, 2.));
+    assert!(equal(xs[0].u, 2. / 3.));
+    assert!(equal(xs[0].v, 5. / 12.));
+
+    let xs = intersect(&s, &ray(&point(2., 2., 0.), &vector(0., -1., 0.)));
+    assert!(xs.is_empty());
+
+    // Grazing the bump from the side enters and leaves it.
+    let s = bezier_patch(&bumpy_patch());
+    let xs = intersect(&s, &ray(&point(-5., 0.25, 0.), &vector(1., 0., 0.)));
+    assert_eq!(xs.count, 2);
+    let xs = intersect(&s, &ray(&point(0., 5., 0.), &vector(0., -1., 0.)));
+    assert_eq!(xs.count, 1);
+    assert!(equal(xs[0].t, 5. - 0.5625));
+    let n = normal_at3(&s, &point(0., 0.5625, 0.), &xs[0]);
+    assert_eq!(n, vector(0., -1., 0.));
+}
+
+#[test]
+fn test_patch_tessellation() {
+    let p = BezierPatch::new(&bumpy_patch());
+    let g = tessellate_bezier_patch(&p, 4);
+    assert_eq!(g.children().len(), 32);
+    let bb = g.as_local_shape().local_bounding_box();
+    assert_eq!(bb.min, point(-1.5, 0., -1.5));
+    assert_eq!(bb.max, point(1.5, 0.5625, 1.5));
+
+    // Slightly off center, to avoid hitting the shared edge of two triangles.
+    let xs = intersect(&g, &ray(&point(0.01, 5., 0.02), &vector(0., -1., 0.)));
+    assert_eq!(xs.count, 1);
+    assert!((xs[0].t - (5. - 0.5625)).abs() < 0.01);
+}
+
+#[test]
+fn test_parse_patch_file() {
+    let data = b"1\n1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16\n16\n\
+        0,0,0\n1,0,0\n2,0,0\n3,0,0\n0,0,1\n1,0,1\n2,0,1\n3,0,1\n\
+        0,0,2\n1,0,2\n2,0,2\n3,0,2\n0,0,3\n1,0,3\n2,0,3\n3,0,3\n";
+    let patches = parse_patch_file(data).unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0][5], point(1., 0., 1.));
+    assert_eq!(patches[0][15], point(3., 0., 3.));
+
+    assert!(parse_patch_file(b"1\n1,2,3\n").is_err());
+    assert!(parse_patch_file(b"1\n1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,99\n1\n0,0,0\n").is_err());
+}
+
+#[test]
+fn test_teapot() {
+    let patches = teapot_patches();
+    assert_eq!(patches.len(), 32);
+
+    let r = ray(&point(0., 1.5, -10.), &vector(0., 0., 1.));
+    let mut direct = teapot(None);
+    direct.freeze_and_optimize();
+    let mut tessellated = teapot(Some(8));
+    tessellated.freeze_and_optimize();
+    assert_eq!(tessellated.children()[0].children().len(), 32);
+
+    let xs_direct = intersections(direct.intersect(&r));
+    let xs_tess = intersections(tessellated.intersect(&r));
+    let hit_direct = xs_direct.hit().unwrap().t;
+    let hit_tess = xs_tess.hit().unwrap().t;
+    // The body has radius 2 at its widest, a little below this height.
+    // We hit the seam between two patches, so each side appears twice.
+    assert_eq!(xs_direct.count, 4);
+    assert!(8. < hit_direct && hit_direct < 8.5);
+    assert!((hit_direct - hit_tess).abs() < 0.01);
+}
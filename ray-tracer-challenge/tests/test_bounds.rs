@@ -247,8 +247,10 @@ fn test_bvh_traversal_policies() {
     }
 
     assert_eq!(times_from_intersections(&p_all.intersections()), [-1., -1.]);
-    assert_eq!(times_from_intersections(&p_closest.intersections()), []);
-    assert_eq!(times_from_intersections(&p_any.intersections()), []);
+    // Typed, since serde_json's comparisons with f64 leave an empty array's type ambiguous.
+    let none: [f64; 0] = [];
+    assert_eq!(times_from_intersections(&p_closest.intersections()), none);
+    assert_eq!(times_from_intersections(&p_any.intersections()), none);
 
     assert_eq!(p_all.should_traverse(&bb, &r), (true, 9.));
     assert_eq!(p_closest.should_traverse(&bb, &r), (true, 9.));
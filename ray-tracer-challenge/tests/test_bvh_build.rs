@@ -0,0 +1,110 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+fn assert_valid(bvh: &LinearBVH, count: usize) {
+    let mut indices = bvh.indices.clone();
+    indices.sort();
+    assert_eq!(indices, (0..count).collect::<Vec<usize>>());
+    for (idx, node) in bvh.nodes.iter().enumerate() {
+        if node.is_leaf() {
+            continue;
+        }
+        for child in [idx + 1, node.offset] {
+            let mut bb = node.bb;
+            bb.include_bb(&bvh.nodes[child].bb);
+            assert_eq!(bb, node.bb);
+        }
+    }
+}
+
+#[test]
+fn test_default_options_match_build() {
+    let shapes = spheres(8);
+    let bvh = LinearBVH::build_with(&shape_items(&shapes), &BVHBuildOptions::default());
+    assert_eq!(bvh, LinearBVH::build_for_shapes(&shapes));
+}
+
+#[test]
+fn test_split_methods_find_same_hits() {
+    let shapes = spheres(8);
+    let rays = [
+        ray(&point(3., 3., -10.), &vector(0., 0., 1.)),
+        ray(&point(-5., 3.1, 0.), &vector(1., 0., 0.)),
+        ray(&point(-5., -5., -5.), &vector(1., 1., 0.9)),
+        ray(&point(100., 3., 0.), &vector(0., 1., 0.)),
+    ];
+    for split in [SplitMethod::Sah, SplitMethod::Median, SplitMethod::EqualCounts] {
+        let options = BVHBuildOptions { split, ..Default::default() };
+        let bvh = LinearBVH::build_with(&shape_items(&shapes), &options);
+        assert_valid(&bvh, shapes.len());
+        for r in &rays {
+            let mut p = BVHTraversalPolicy::new_all_hits();
+            bvh.intersect_shapes(&shapes, r, &mut p);
+            let brute: Vec<Intersection> = shapes.iter().flat_map(|s| s.intersect(r)).collect();
+            assert_eq!(times(&intersections(p.intersections()).data), times(&intersections(brute).data));
+        }
+    }
+}
+
+#[test]
+fn test_leaf_sizes() {
+    // Identical shapes can't be split by SAH, so max_leaf_size has to force it.
+    let shapes: Vec<Shape> = (0..20).map(|_| sphere()).collect();
+    let bvh = LinearBVH::build_for_shapes(&shapes);
+    assert_eq!(bvh.nodes.len(), 1);
+
+    let options = BVHBuildOptions { max_leaf_size: 3, ..Default::default() };
+    let bvh = LinearBVH::build_with(&shape_items(&shapes), &options);
+    assert_valid(&bvh, shapes.len());
+    assert!(bvh.nodes.iter().filter(|n| n.is_leaf()).all(|n| n.count <= 3));
+
+    let options = BVHBuildOptions { split: SplitMethod::EqualCounts, min_split_size: 8, ..Default::default() };
+    let bvh = LinearBVH::build_with(&shape_items(&spheres(8)), &options);
+    assert!(bvh.nodes.iter().filter(|n| n.is_leaf()).all(|n| (4..8).contains(&n.count)));
+}
+
+#[test]
+fn test_parallel_build_matches_serial() {
+    let shapes = spheres(70);
+    let items = shape_items(&shapes);
+    let serial = LinearBVH::build_with(&items, &BVHBuildOptions::default());
+    let parallel = LinearBVH::build_with(&items, &BVHBuildOptions { parallel: true, ..Default::default() });
+    assert_eq!(serial, parallel);
+}
+
+#[test]
+fn test_stats() {
+    let options = BVHBuildOptions { split: SplitMethod::EqualCounts, min_split_size: 2, ..Default::default() };
+    let bvh = LinearBVH::build_with(&shape_items(&spheres(4)), &options);
+    let stats = bvh.stats();
+    // A perfectly balanced tree over 16 primitives.
+    assert_eq!(stats.primitives, 16);
+    assert_eq!(stats.nodes, 31);
+    assert_eq!(stats.leaves, 16);
+    assert_eq!(stats.max_depth, 5);
+    assert_eq!(stats.leaf_sizes, vec![0, 16]);
+    assert_eq!(stats.depth_histogram, vec![0, 0, 0, 0, 16]);
+    assert!(stats.sah_cost > 0.);
+
+    assert_eq!(LinearBVH::build(&[]).stats(), BVHStats::default());
+}
+
+#[test]
+fn test_group_stats() {
+    let mut inner = group();
+    for s in spheres(4) {
+        add_child(&mut inner, &s);
+    }
+    let mut g = group();
+    add_child(&mut g, &inner);
+    add_child(&mut g, &sphere());
+    let stats = g.freeze_and_optimize();
+    assert_eq!(stats.primitives, 2);
+    assert_eq!(stats.nested.len(), 1);
+    assert_eq!(stats.nested[0].primitives, 16);
+    assert!(stats.to_string().contains("2 primitives"));
+
+    assert_eq!(sphere().freeze_and_optimize(), BVHStats::default());
+}
@@ -0,0 +1,58 @@
+use std::f64::consts::PI;
+
+use ray_tracer_challenge::*;
+
+fn mixed_world() -> World {
+    let mut w = default_world();
+    let mut s = sphere();
+    s.set_transform(&translation(0.5, 0., 0.));
+    let mut c = csg("difference", &cube(), &s);
+    c.set_transform(&translation(-2., 0., 1.));
+    c.material.reflective = 0.5;
+    w.add(&c);
+    let mut g = glass_sphere();
+    g.set_transform(&translation(2., 0., 0.));
+    w.add(&g);
+    let mut m = sphere();
+    m.material.reflective = 0.9;
+    m.set_transform(&translation(0., 0., 4.).scale(2., 2., 2.));
+    w.add(&m);
+    let mut p = plane();
+    p.set_transform(&translation(0., -1., 0.));
+    p.material.reflective = 0.3;
+    w.add(&p);
+    w
+}
+
+// What color_at used to do at the top level: shade the hit from the full list of intersections.
+fn color_from_all_hits(w: &World, r: &Ray) -> Color {
+    let xs = intersect_world(w, r);
+    match xs.hit() {
+        Some(i) => shade_hit2(w, &prepare_computations3(i, r, &xs)),
+        None => BLACK,
+    }
+}
+
+#[test]
+fn test_closest_hit_in_csg() {
+    let w = mixed_world();
+    let (xs, in_csg) = w.intersect_closest_hit_in_csg(&ray(&point(-2., 0., -5.), &vector(0., 0., 1.)));
+    assert_eq!(xs.count, 1);
+    assert!(in_csg);
+    let (xs, in_csg) = w.intersect_closest_hit_in_csg(&ray(&point(2., 0., -5.), &vector(0., 0., 1.)));
+    assert!(equal(xs[0].t, 4.));
+    assert!(!in_csg);
+}
+
+#[test]
+fn test_color_at_matches_all_hits() {
+    let w = mixed_world();
+    let mut c = camera(40., 30., PI / 2.);
+    c.set_transform(&view_transform(&point(0., 1.5, -6.), &point(0., 0., 0.), &vector(0., 1., 0.)));
+    for y in 0..c.vsize {
+        for x in 0..c.hsize {
+            let r = ray_for_pixel(&c, x, y);
+            assert_eq!(color_at(&w, &r), color_from_all_hits(&w, &r));
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use ray_tracer_challenge::*;
+
+#[test]
+fn test_arithmetic() {
+    let none = HashMap::new();
+    for (expr, expected) in [
+        ("1 + 2 * 3", 7.),
+        ("(1 + 2) * 3", 9.),
+        ("10 - 4 - 3", 3.),
+        ("2 ^ 3 ^ 2", 512.),
+        ("-2^2", -4.),
+        ("-(3) + +1", -2.),
+        ("1.5e1 / 3", 5.),
+        ("pi/4", PI / 4.),
+        ("sqrt(2) * cos(0)", 2_f64.sqrt()),
+    ] {
+        assert_eq!(evaluate(expr, &none), Ok(expected), "{expr}");
+    }
+}
+
+#[test]
+fn test_variables_and_errors() {
+    let variables = HashMap::from([("radius".to_owned(), 2.), ("pi".to_owned(), 3.)]);
+    assert_eq!(evaluate("2*radius", &variables), Ok(4.));
+    // Variables hide constants.
+    assert_eq!(evaluate("pi", &variables), Ok(3.));
+    assert_eq!(evaluate("2*radus", &variables), Err("unknown name `radus`".to_owned()));
+    assert_eq!(evaluate("log(2)", &variables), Err("unknown function `log`".to_owned()));
+    assert_eq!(evaluate("(1 + 2", &variables), Err("missing `)`".to_owned()));
+    assert_eq!(evaluate("1 2", &variables), Err("unexpected `2`".to_owned()));
+    assert_eq!(evaluate("1 +", &variables), Err("expected a number".to_owned()));
+    assert!(is_variable_name("turn_2") && !is_variable_name("2turns") && !is_variable_name("field-of-view"));
+}
@@ -0,0 +1,55 @@
+use ray_tracer_challenge::*;
+
+#[test]
+fn test_disk() {
+    let d = disk();
+    assert_eq!(d.as_local_shape().local_bounding_box(), BoundingBox::new(point(-1., 0., -1.), point(1., 0., 1.)));
+
+    for (o, dir, hits) in [
+        (point(0., 1., 0.), vector(0., -1., 0.), 1),
+        (point(0.9, 1., 0.), vector(0., -1., 0.), 1),
+        (point(0.8, 1., 0.8), vector(0., -1., 0.), 0),
+        (point(0., 1., 0.), vector(0., 0., 1.), 0),
+        (point(0., -1., 0.), vector(0., 1., 0.), 1),
+    ] {
+        assert_eq!(local_intersect(&d, &ray(&o, &dir)).count, hits);
+    }
+    assert_eq!(local_normal_at(&d, &point(0.5, 0., 0.)), vector(0., 1., 0.));
+
+    let xs = local_intersect(&d, &ray(&point(0., 1., 0.5), &vector(0., -1., 0.)));
+    assert!(equal(xs[0].t, 1.));
+    assert!(equal(xs[0].u, 0.25));
+    assert!(equal(xs[0].v, 0.5));
+}
+
+#[test]
+fn test_annulus() {
+    let a = annulus(0.5, 2.);
+    assert_eq!(a.as_local_shape().local_bounding_box(), BoundingBox::new(point(-2., 0., -2.), point(2., 0., 2.)));
+    for (x, hits) in [(0., 0), (0.4, 0), (0.5, 1), (1.5, 1), (2.5, 0)] {
+        assert_eq!(local_intersect(&a, &ray(&point(x, 1., 0.), &vector(0., -1., 0.))).count, hits);
+    }
+    let xs = local_intersect(&a, &ray(&point(-1.25, 1., 0.), &vector(0., -1., 0.)));
+    assert!(equal(xs[0].u, 0.5));
+    assert!(equal(xs[0].v, 0.5));
+}
+
+#[test]
+fn test_quad() {
+    let mut q = quad();
+    assert_eq!(q.as_local_shape().local_bounding_box(), BoundingBox::new(point(-1., 0., -1.), point(1., 0., 1.)));
+    let xs = local_intersect(&q, &ray(&point(0.5, 2., -0.5), &vector(0., -1., 0.)));
+    assert_eq!(xs.count, 1);
+    assert!(equal(xs[0].t, 2.));
+    assert!(equal(xs[0].u, 0.75));
+    assert!(equal(xs[0].v, 0.25));
+    assert!(local_intersect(&q, &ray(&point(1.5, 2., 0.), &vector(0., -1., 0.))).is_empty());
+
+    // Stand it up as a wall, 4 wide and 2 tall.
+    q.set_transform(&rotation_x(std::f64::consts::PI / 2.).scale(2., 1., 1.));
+    let xs = intersect(&q, &ray(&point(1.5, 0.5, -5.), &vector(0., 0., 1.)));
+    assert_eq!(xs.count, 1);
+    assert!(equal(xs[0].t, 5.));
+    assert_eq!(normal_at2(&q, &point(1.5, 0.5, 0.)), vector(0., 0., 1.));
+    assert!(intersect(&q, &ray(&point(2.5, 0.5, -5.), &vector(0., 0., 1.))).is_empty());
+}
@@ -0,0 +1,243 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use ray_tracer_challenge::*;
+
+// The prototype with the triangles of the mesh on a node.
+fn node_mesh(node: &Shape) -> &Shape {
+    &node.children()[0].as_instance().unwrap().prototype
+}
+
+fn world_positions(shape: &Shape) -> Vec<Tuple> {
+    tessellate(shape, &TessellationOptions::default()).iter().flat_map(|m| m.positions.clone()).collect()
+}
+
+#[test]
+fn test_round_trip_through_the_exporter() {
+    let mut w = world();
+    let mut s = sphere();
+    s.material.color = color(1., 0.5, 0.);
+    s.material.transparency = 0.25;
+    w.add(&s);
+    let mut c = cube();
+    c.set_transform(&translation(3., 0., 0.));
+    w.add(&c);
+    let meshes = tessellate_world(&w, &TessellationOptions { segments: 8, rings: 4, ..TessellationOptions::default() });
+    let mut cam = camera(200., 100., PI / 2.);
+    cam.set_transform(&view_transform(&point(1., 2., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)));
+    let (json, bin) = meshes_to_gltf(&meshes, Some(&cam), None);
+
+    let import = parse_gltf(&gltf_to_glb(&json, &bin), None).unwrap();
+    let triangles: usize = import.root.children().iter().map(|node| node_mesh(node).children().len()).sum();
+    assert_eq!(triangles, meshes.iter().map(|m| m.triangles.len()).sum::<usize>());
+    let sphere_mesh = node_mesh(&import.root.children()[0]);
+    assert_eq!(sphere_mesh.children()[0].material.color, color(1., 0.5, 0.));
+    assert!(equal(sphere_mesh.children()[0].material.transparency, 0.25));
+
+    // Mirroring on the way out and back in puts everything where it was.
+    let mut imported = world();
+    imported.add(&import.root);
+    let xs = intersections(imported.objects[0].intersect(&ray(&point(3., 0., -5.), &vector(0., 0., 1.))));
+    assert!(equal(xs.hit().unwrap().t, 4.));
+
+    assert_eq!(import.cameras.len(), 1);
+    let c = &import.cameras[0];
+    assert_eq!((c.hsize, c.vsize), (GLTF_CAMERA_WIDTH, GLTF_CAMERA_WIDTH / 2));
+    assert!(equal(c.field_of_view, PI / 2.));
+    assert_eq!(c.transform(), cam.transform());
+}
+
+#[test]
+fn test_glb_with_a_node_hierarchy() {
+    let mut bin = vec![];
+    for p in [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.], [1., 1., 0.]] {
+        for x in p {
+            bin.extend((x as f32).to_le_bytes());
+        }
+    }
+    for i in [0_u16, 1, 2, 3] {
+        bin.extend(i.to_le_bytes());
+    }
+    let s = (PI / 4.).sin();
+    let json = format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "scene": 0,
+        "scenes": [{{ "nodes": [0] }}],
+        "nodes": [
+            {{ "translation": [0, 0, 5], "rotation": [0, {s}, 0, {s}], "children": [1] }},
+            {{ "scale": [2, 2, 2], "mesh": 0 }}
+        ],
+        "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "mode": 5 }}] }}],
+        "accessors": [
+            {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3" }},
+            {{ "bufferView": 0, "byteOffset": 48, "componentType": 5123, "count": 4, "type": "SCALAR" }}
+        ],
+        "bufferViews": [{{ "buffer": 0, "byteLength": 56 }}],
+        "buffers": [{{ "byteLength": 56 }}]
+    }}"#);
+    let import = parse_gltf(&gltf_to_glb(&json, &bin), None).unwrap();
+    let meshes = tessellate(&import.root, &TessellationOptions::default());
+    // The strip's second triangle is turned around to keep the winding.
+    assert_eq!(meshes[0].triangles.len(), 2);
+    let p = world_positions(&import.root);
+    let expected = [point(0., 0., -5.), point(0., 0., -3.), point(0., 2., -5.), point(0., 2., -5.), point(0., 0., -3.), point(0., 2., -3.)];
+    for (a, b) in p.iter().zip(&expected) {
+        assert_eq!(a, b);
+    }
+    // glTF's default material is a rough metal.
+    assert_eq!(node_mesh(&import.root.children()[0].children()[0]).children()[0].material.reflective, 0.);
+}
+
+#[test]
+fn test_embedded_buffers_materials_cameras_and_lights() {
+    let json = br#"{
+        "asset": { "version": "2.0" },
+        "nodes": [
+            { "mesh": 0 },
+            { "camera": 0, "translation": [0, 0, 10] },
+            { "translation": [1, 2, 3], "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+            { "rotation": [-0.7071068, 0, 0, 0.7071068], "extensions": { "KHR_lights_punctual": { "light": 1 } } }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "material": 0 }] }],
+        "materials": [{
+            "pbrMetallicRoughness": { "baseColorFactor": [0.2, 0.4, 0.6, 0.5], "metallicFactor": 1, "roughnessFactor": 0.1 },
+            "alphaMode": "BLEND",
+            "extensions": { "KHR_materials_transmission": { "transmissionFactor": 0.9 }, "KHR_materials_ior": { "ior": 1.33 } }
+        }],
+        "cameras": [{ "name": "main", "type": "perspective", "perspective": { "yfov": 0.8, "aspectRatio": 0.5, "znear": 0.1 } }],
+        "extensions": { "KHR_lights_punctual": { "lights": [
+            { "type": "point", "color": [1, 0.5, 0.25], "intensity": 10 },
+            { "type": "directional" }
+        ] } },
+        "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "buffers": [{ "byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA" }]
+    }"#;
+    let import = parse_gltf(json, None).unwrap();
+    // Without a scene, every root node is loaded.
+    let t = &node_mesh(&import.root.children()[0]).children()[0];
+    assert_eq!((t.p1(), t.p2(), t.p3()), (point(0., 0., 0.), point(1., 0., 0.), point(0., 1., 0.)));
+
+    let m = t.material;
+    assert_eq!(m.color, color(0.2, 0.4, 0.6));
+    assert_eq!((m.transparency, m.refractive_index), (0.9, 1.33));
+    assert!(equal(m.reflective, 0.9));
+    assert!(equal(m.shininess, 198.));
+
+    // Taller than wide, so the field of view is glTF's.
+    let c = &import.cameras[0];
+    assert_eq!((c.hsize, c.vsize, c.field_of_view), (GLTF_CAMERA_WIDTH, 2 * GLTF_CAMERA_WIDTH, 0.8));
+    assert_eq!(c.inverse() * point(0., 0., 0.), point(0., 0., -10.));
+    assert_eq!(c.name.as_deref(), Some("main"));
+
+    assert_eq!(import.lights.len(), 2);
+    assert_eq!(import.lights[0].position, point(1., 2., -3.));
+    assert_eq!(import.lights[0].intensity, color(1., 0.5, 0.25));
+    // A directional light pointing down is far above.
+    let sun = import.lights[1].position;
+    assert!(sun.y > 1e5 && sun.x.abs() < 1. && sun.z.abs() < 1.);
+}
+
+#[test]
+fn test_nodes_scaled_to_nothing_are_left_out() {
+    let json = br#"{
+        "asset": { "version": "2.0" },
+        "nodes": [{ "mesh": 0 }, { "mesh": 0, "scale": [0, 1, 1] }, { "scale": [1, 0, 1], "children": [3] }, { "camera": 0 }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.8, "znear": 0.1 } }],
+        "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "buffers": [{ "byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA" }]
+    }"#;
+    let import = parse_gltf(json, None).unwrap();
+    assert_eq!(import.root.children().len(), 1);
+    assert!(import.cameras.is_empty());
+}
+
+#[test]
+fn test_nodes_share_their_meshes() {
+    let json = br#"{
+        "asset": { "version": "2.0" },
+        "nodes": [{ "mesh": 0 }, { "mesh": 0, "translation": [5, 0, 0] }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+        "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+        "buffers": [{ "byteLength": 36, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA" }]
+    }"#;
+    let import = parse_gltf(json, None).unwrap();
+    let nodes = import.root.children();
+    let (a, b) = (nodes[0].children()[0].as_instance().unwrap(), nodes[1].children()[0].as_instance().unwrap());
+    assert!(Arc::ptr_eq(&a.prototype, &b.prototype));
+
+    // Each copy is still hit where its node puts it.
+    let mut w = world();
+    w.add(&import.root);
+    for x in [0.25, 5.25] {
+        let xs = intersections(w.objects[0].intersect(&ray(&point(x, 0.25, -5.), &vector(0., 0., 1.))));
+        assert!(equal(xs.hit().unwrap().t, 5.));
+    }
+}
+
+#[test]
+fn test_escaped_names() {
+    let json = br#"{
+        "asset": { "version": "2.0" },
+        "nodes": [{ "camera": 0 }],
+        "cameras": [{ "name": "\ud83d\ude00 \u00e9\/\\ud83d", "type": "perspective", "perspective": { "yfov": 1e0, "znear": 0.1 } }]
+    }"#;
+    let import = parse_gltf(json, None).unwrap();
+    assert_eq!(import.cameras[0].name.as_deref(), Some("\u{1f600} \u{e9}/\\ud83d"));
+}
+
+#[test]
+fn test_invalid_files() {
+    let error = |json: &str| parse_gltf(json.as_bytes(), None).err().unwrap().to_string();
+    assert!(error(r#"{ "asset": { "version": "1.0" } }"#).contains("unsupported glTF version"));
+    assert!(error(r#"{ "asset": { "version": "2.0" }, "buffers": [{ "byteLength": 4, "uri": "data.bin" }] }"#).contains("data.bin"));
+    assert!(error(r#"{
+        "asset": { "version": "2.0" },
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1 }] }],
+        "accessors": [
+            { "componentType": 5126, "count": 3, "type": "VEC3" },
+            { "bufferView": 0, "componentType": 5125, "count": 3, "type": "SCALAR" }
+        ],
+        "bufferViews": [{ "buffer": 0, "byteLength": 12 }],
+        "buffers": [{ "byteLength": 12, "uri": "data:application/octet-stream;base64,AAAAAAEAAAAFAAAA" }]
+    }"#).contains("vertex index 5 is out of range"));
+    assert!(error(r#"{
+        "asset": { "version": "2.0" },
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "children": [0] }]
+    }"#).contains("cycle"));
+
+    // Sizes and offsets that would overflow or not fit in the data are errors before allocating.
+    let positions = |accessor: &str, view: &str| error(&format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "nodes": [{{ "mesh": 0 }}],
+        "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }} }}] }}],
+        "accessors": [{{ "componentType": 5126, "type": "VEC3", {accessor} }}],
+        "bufferViews": [{{ "buffer": 0, {view} }}],
+        "buffers": [{{ "byteLength": 12, "uri": "data:application/octet-stream;base64,AAAAAAEAAAAFAAAA" }}]
+    }}"#));
+    assert!(positions(r#""bufferView": 0, "count": 1000000000000000000"#, r#""byteLength": 12"#).contains("outside its buffer view"));
+    assert!(positions(r#""bufferView": 0, "count": 1, "byteOffset": 18446744073709551615"#, r#""byteLength": 12"#).contains("outside its buffer view"));
+    assert!(positions(r#""bufferView": 0, "count": 1"#, r#""byteLength": 12, "byteOffset": 18446744073709551615"#).contains("outside its buffer"));
+    assert!(positions(r#""count": 1000000000000000000"#, r#""byteLength": 12"#).contains("too many elements"));
+
+    // Attributes of the wrong type, or with a different number of values than the positions.
+    let attributes = |attributes: &str, accessors: &str| error(&format!(r#"{{
+        "asset": {{ "version": "2.0" }},
+        "nodes": [{{ "mesh": 0 }}],
+        "meshes": [{{ "primitives": [{{ "attributes": {{ {attributes} }} }}] }}],
+        "accessors": [{accessors}]
+    }}"#));
+    let accessors = |list: &[(&str, usize)]| list.iter()
+        .map(|(type_, count)| format!(r#"{{ "componentType": 5126, "count": {count}, "type": "{type_}" }}"#))
+        .collect::<Vec<String>>().join(", ");
+    assert_eq!(attributes(r#""POSITION": 0"#, &accessors(&[("SCALAR", 3)])), "POSITION needs to be VEC3, not SCALAR");
+    assert_eq!(attributes(r#""POSITION": 0, "NORMAL": 1"#, &accessors(&[("VEC3", 3), ("VEC3", 1)])), "NORMAL has 1 values for 3 positions");
+    assert_eq!(attributes(r#""POSITION": 0, "NORMAL": 1"#, &accessors(&[("VEC3", 3), ("VEC2", 3)])), "NORMAL needs to be VEC3, not VEC2");
+    assert_eq!(attributes(r#""POSITION": 0, "TEXCOORD_0": 1"#, &accessors(&[("VEC3", 3), ("VEC3", 3)])), "TEXCOORD_0 needs to be VEC2, not VEC3");
+    assert_eq!(attributes(r#""POSITION": 0, "TEXCOORD_0": 1"#, &accessors(&[("VEC3", 3), ("VEC2", 2)])), "TEXCOORD_0 has 2 values for 3 positions");
+    assert!(parse_gltf(b"glTF\x02\0\0\0", None).is_err());
+}
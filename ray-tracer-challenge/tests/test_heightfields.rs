@@ -0,0 +1,100 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+// Heights rise linearly with x, from 0 to 1.
+fn ramp(width: usize, depth: usize) -> Shape {
+    let heights: Vec<f64> = (0..width * depth).map(|idx| (idx % width) as f64 / (width - 1) as f64).collect();
+    heightfield(&heights, width, depth)
+}
+
+#[test]
+fn test_flat_heightfield() {
+    let h = heightfield(&[0.5; 9], 3, 3);
+    let ts = local_times(&h, &ray(&point(0.3, 2., 0.7), &vector(0., -1., 0.)));
+    assert_eq!(ts.len(), 1);
+    assert!(equal(ts[0], 1.5));
+    // Right on a shared diagonal, we still only get one hit.
+    let ts = local_times(&h, &ray(&point(0.25, 2., 0.25), &vector(0., -1., 0.)));
+    assert_eq!(ts.len(), 1);
+    assert!(local_times(&h, &ray(&point(1.5, 2., 0.5), &vector(0., -1., 0.))).is_empty());
+    assert_eq!(local_normal_at(&h, &point(0.3, 0.5, 0.7)).normalized(), vector(0., 1., 0.));
+    assert_eq!(
+        h.as_local_shape().local_bounding_box(),
+        BoundingBox::new(point(0., 0.5, 0.), point(1., 0.5, 1.))
+    );
+}
+
+#[test]
+fn test_ramp_traversal() {
+    let h = ramp(11, 5);
+    // A horizontal ray walks through many cells before hitting the ramp at x = 0.45.
+    let ts = local_times(&h, &ray(&point(-1., 0.45, 0.6), &vector(1., 0., 0.)));
+    assert_eq!(ts.len(), 1);
+    assert!(equal(ts[0], 1.45));
+    // Going the other way, we start inside the ramp.
+    let ts = local_times(&h, &ray(&point(2., 0.45, 0.6), &vector(-1., 0., 0.)));
+    assert_eq!(ts.len(), 1);
+    assert!(equal(ts[0], 1.55));
+    // A diagonal ray above the ramp misses it.
+    assert!(local_times(&h, &ray(&point(-1., 1.2, -1.), &vector(1., 0., 1.))).is_empty());
+    // The normal is interpolated from per-sample normals, which all match the slope here.
+    let n = local_normal_at(&h, &point(0.45, 0.45, 0.6)).normalized();
+    assert_eq!(n, vector(-1., 1., 0.).normalized());
+}
+
+#[test]
+fn test_smooth_normals() {
+    // A single bump in the middle.
+    let mut heights = vec![0.; 9];
+    heights[4] = 1.;
+    let h = heightfield(&heights, 3, 3);
+    assert_eq!(local_normal_at(&h, &point(0.5, 1., 0.5)).normalized(), vector(0., 1., 0.));
+    // Halfway down the slope, the normal blends between the peak and the flat corner.
+    let n = local_normal_at(&h, &point(0.25, 0.5, 0.25)).normalized();
+    assert!(n.x < 0. && n.z < 0. && n.y > 0.);
+    assert!(equal(n.x, n.z));
+
+    let ts = local_times(&h, &ray(&point(0.5, 5., 0.5), &vector(0., -1., 0.)));
+    assert_eq!(ts.len(), 1);
+    assert!(equal(ts[0], 4.));
+}
+
+#[test]
+fn test_heightfield_in_bvh() {
+    let mut g = group();
+    for i in 0..4 {
+        let mut h = ramp(9, 9);
+        h.set_transform(&translation(i as f64 * 2., 0., 0.));
+        add_child(&mut g, &h);
+    }
+    g.freeze_and_optimize();
+    let xs = intersections(g.intersect(&ray(&point(4.5, 5., 0.5), &vector(0., -1., 0.))));
+    assert_eq!(xs.count, 1);
+    assert!(equal(xs.hit().unwrap().t, 4.5));
+}
+
+#[test]
+fn test_parse_pnm() {
+    let img = parse_pnm(b"P2\n# a comment\n3 2\n4\n0 1 2\n3 4 0\n").unwrap();
+    assert_eq!((img.width, img.height), (3, 2));
+    assert_eq!(img.data, vec![0., 0.25, 0.5, 0.75, 1., 0.]);
+
+    let img = parse_pnm(b"P5 2 2 255\n\x00\x33\xff\x00").unwrap();
+    assert_eq!(img.data, vec![0., 0.2, 1., 0.]);
+
+    let img = parse_pnm(b"P3 1 1 255 255 255 255").unwrap();
+    assert!(equal(img.data[0], 1.));
+
+    assert!(parse_pnm(b"P4 1 1\n").is_err());
+    assert!(parse_pnm(b"P2 2 2 255 0 0 0").is_err());
+    // Headers asking for more pixels than the file has, or than fit in memory.
+    assert_eq!(parse_pnm(b"P5 100000 100000 255\n\x00").err().unwrap().to_string(), "a 100000x100000 image needs more data than the file has");
+    assert_eq!(parse_pnm(b"P6 18446744073709551615 2 65535\n").err().unwrap().to_string(), "image is too large");
+
+    let h = heightfield_from_pnm(b"P2 3 2 4 0 1 2 3 4 0").unwrap();
+    let hf = h.as_heightfield().unwrap();
+    assert_eq!((hf.width, hf.depth), (3, 2));
+    assert!(heightfield_from_pnm(b"P2 1 1 4 0").is_err());
+}
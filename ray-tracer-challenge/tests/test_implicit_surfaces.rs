@@ -0,0 +1,91 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+fn unit_bounds() -> BoundingBox {
+    BoundingBox::new(point(-2., -2., -2.), point(2., 2., 2.))
+}
+
+#[test]
+fn test_sdf_primitives() {
+    let p = point(2., 0., 0.);
+    assert!(equal(Sdf::sphere(1.).distance(&p), 1.));
+    assert!(equal(Sdf::cuboid(1., 1., 1.).distance(&p), 1.));
+    assert!(equal(Sdf::cuboid(1., 1., 1.).distance(&point(0., 0., 0.)), -1.));
+    assert!(equal(Sdf::torus(1., 0.25).distance(&p), 0.75));
+    assert!(equal(Sdf::cylinder(1., 1.).distance(&point(0., 3., 0.)), 2.));
+    assert!(equal(Sdf::capsule(&point(0., -1., 0.), &point(0., 1., 0.), 0.5).distance(&point(0., 2., 0.)), 0.5));
+    assert!(equal(Sdf::sphere(1.).translate(2., 0., 0.).distance(&p), -1.));
+    assert!(equal(Sdf::sphere(1.).scale(2.).distance(&point(3., 0., 0.)), 1.));
+}
+
+#[test]
+fn test_sdf_combinators() {
+    let a = Sdf::sphere(1.);
+    let b = Sdf::sphere(1.).translate(1., 0., 0.);
+    let p = point(-2., 0., 0.);
+    assert!(equal(a.union(&b).distance(&p), 1.));
+    assert!(equal(a.intersect(&b).distance(&p), 2.));
+    assert!(equal(a.subtract(&b).distance(&point(0.5, 0., 0.)), 0.5));
+
+    // Smooth blends pull the surface outward (union) or inward (intersect, subtract) near the seam.
+    let seam = point(0.5, 0., 0.);
+    assert!(a.smooth_union(&b, 0.5).distance(&seam) < a.union(&b).distance(&seam));
+    assert!(a.smooth_intersect(&b, 0.5).distance(&seam) > a.intersect(&b).distance(&seam));
+    let rim = point(0., 0.9, 0.);
+    assert!(a.smooth_subtract(&b, 0.5).distance(&rim) > a.subtract(&b).distance(&rim));
+}
+
+#[test]
+fn test_implicit_sphere_intersect() {
+    let s = implicit_surface(Sdf::sphere(1.), unit_bounds());
+    let r = ray(&point(0., 0., -5.), &vector(0., 0., 1.));
+    assert_times(&times(&intersect(&s, &r).data), &[4., 6.]);
+
+    // Starting inside reports the hit behind us as well.
+    let r = ray(&point(0., 0., 0.), &vector(0., 0., 1.));
+    assert_times(&times(&intersect(&s, &r).data), &[-1., 1.]);
+
+    let r = ray(&point(0., 2., -5.), &vector(0., 0., 1.));
+    assert!(intersect(&s, &r).is_empty());
+}
+
+#[test]
+fn test_implicit_transformed_intersect() {
+    let mut s = implicit_surface(Sdf::sphere(1.), unit_bounds());
+    s.set_transform(&scaling(2., 2., 2.));
+    let r = ray(&point(0., 0., -5.), &vector(0., 0., 1.));
+    assert_times(&times(&intersect(&s, &r).data), &[3., 7.]);
+}
+
+#[test]
+fn test_implicit_normal() {
+    let s = implicit_surface(Sdf::cuboid(1., 1., 1.), unit_bounds());
+    assert_eq!(normal_at2(&s, &point(1., 0.5, 0.)), vector(1., 0., 0.));
+    let s = implicit_surface(Sdf::sphere(1.), unit_bounds());
+    let k = 3_f64.sqrt() / 3.;
+    assert_eq!(normal_at2(&s, &point(k, k, k)), vector(k, k, k));
+}
+
+#[test]
+fn test_implicit_custom_and_bounds() {
+    let slab = Sdf::custom(|p| p.y.abs() - 0.5);
+    let bounds = BoundingBox::new(point(-1., -1., -1.), point(1., 1., 1.));
+    let s = implicit_surface(slab, bounds);
+    assert_eq!(s.as_local_shape().local_bounding_box(), bounds);
+    let r = ray(&point(0., 5., 0.), &vector(0., -1., 0.));
+    assert_times(&times(&intersect(&s, &r).data), &[4.5, 5.5]);
+
+    // Plugs into a group's BVH like any other shape.
+    let mut g = group();
+    for x in 0..8 {
+        let mut c = implicit_surface(Sdf::sphere(1.), unit_bounds());
+        c.set_transform(&translation(x as f64 * 3., 0., 0.));
+        add_child(&mut g, &c);
+    }
+    g.freeze_and_optimize();
+    let r = ray(&point(9., 0., -5.), &vector(0., 0., 1.));
+    let xs = intersections(g.intersect_closest_hit(&r));
+    assert_times(&times(&xs.data), &[4.]);
+}
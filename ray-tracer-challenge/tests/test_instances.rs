@@ -0,0 +1,124 @@
+use ray_tracer_challenge::*;
+
+fn two_spheres() -> Shape {
+    let mut g = group();
+    let mut s = sphere();
+    s.set_transform(&translation(-2., 0., 0.));
+    add_child(&mut g, &s);
+    let mut s = sphere();
+    s.set_transform(&translation(2., 0., 0.));
+    s.material.color = color(1., 0., 0.);
+    add_child(&mut g, &s);
+    g
+}
+
+#[test]
+fn test_instance_matches_copy() {
+    let proto = prototype(&two_spheres());
+    let transform = translation(0., 1., 5.) * rotation_y(0.5) * scaling(2., 2., 2.);
+    let mut inst = instance(&proto);
+    inst.set_transform(&transform);
+    let mut copy = two_spheres();
+    copy.set_transform(&transform);
+    copy.freeze_and_optimize();
+
+    let r = ray(&point(-10., 1.3, 4.), &vector(1., 0., 0.2));
+    let xs_inst = intersections(inst.intersect(&r));
+    let xs_copy = intersections(copy.intersect(&r));
+    assert_eq!(xs_inst.count, xs_copy.count);
+    assert!(xs_inst.count > 0);
+    for (a, b) in xs_inst.data.iter().zip(&xs_copy.data) {
+        assert!(equal(a.t, b.t));
+        assert!(std::ptr::eq(a.instance.unwrap(), &inst));
+        let p = position(&r, a.t);
+        assert_eq!(normal_at3(a.object, &p, a), normal_at3(b.object, &p, b));
+        assert_eq!(a.material(), &b.object.material);
+    }
+    assert_eq!(
+        inst.as_local_shape().local_bounding_box(),
+        BoundingBox::new(point(-3., -1., -1.), point(3., 1., 1.))
+    );
+}
+
+#[test]
+fn test_instances_share_geometry() {
+    let proto = prototype(&two_spheres());
+    let mut w = world();
+    let mut g = group();
+    for i in 0..100 {
+        let mut inst = instance(&proto);
+        inst.set_transform(&translation(0., i as f64 * 3., 0.));
+        add_child(&mut g, &inst);
+        w.add(&inst);
+    }
+    g.freeze_and_optimize();
+    w.add(&g);
+    assert!(std::ptr::eq(
+        w.objects[0].as_instance().unwrap().prototype.as_ref(),
+        g.children()[99].as_instance().unwrap().prototype.as_ref(),
+    ));
+
+    let xs = intersections(g.intersect(&ray(&point(2., 297., -5.), &vector(0., 0., 1.))));
+    assert_eq!(xs.count, 2);
+    assert!(equal(xs[0].t, 4.));
+}
+
+#[test]
+fn test_material_override() {
+    let proto = prototype(&two_spheres());
+    let mut m = material();
+    m.color = color(0., 0., 1.);
+    let mut w = world();
+    w.add_light(&point_light(&point(0., 0., -10.), &color(1., 1., 1.)));
+    w.add(&instance_with_material(&proto, &m));
+    let mut plain = instance(&proto);
+    plain.set_transform(&translation(0., 5., 0.));
+    w.add(&plain);
+
+    let c = color_at(&w, &ray(&point(2., 0., -5.), &vector(0., 0., 1.)));
+    // Specular highlights are white, so we only compare channels.
+    assert!(c.blue > 0.5 && c.red < 0.1 && c.green < 0.1);
+    // Without an override, each shape keeps its own material.
+    let c = color_at(&w, &ray(&point(2., 5., -5.), &vector(0., 0., 1.)));
+    assert!(c.red > 0.5 && c.blue < 0.1 && c.green < 0.1);
+}
+
+#[test]
+fn test_refraction_between_instances() {
+    // Rays through two instances of the same glass sphere must tell the instances apart.
+    let proto = prototype(&glass_sphere());
+    let mut a = instance(&proto);
+    a.set_transform(&translation(0., 0., -0.5));
+    let mut b = instance(&proto);
+    b.set_transform(&translation(0., 0., 0.5));
+    let r = ray(&point(0., 0., -5.), &vector(0., 0., 1.));
+    let mut v = a.intersect(&r);
+    v.append(&mut b.intersect(&r));
+    let xs = intersections(v);
+    assert_eq!(xs.count, 4);
+    let comps = prepare_computations3(&xs[1], &r, &xs);
+    assert_eq!((comps.n1, comps.n2), (1.5, 1.5));
+    let comps = prepare_computations3(&xs[3], &r, &xs);
+    assert_eq!((comps.n1, comps.n2), (1.5, 1.));
+}
+
+#[test]
+fn test_instance_csg_and_shadows() {
+    let proto = prototype(&sphere());
+    let mut right = instance(&proto);
+    right.set_transform(&translation(0., 0., 0.5));
+    let c = csg("difference", &instance(&proto), &right);
+    let xs = intersections(c.intersect(&ray(&point(0., 0., -5.), &vector(0., 0., 1.))));
+    assert_eq!(xs.count, 2);
+    assert!(equal(xs[0].t, 4.));
+    assert!(equal(xs[1].t, 4.5));
+
+    let mut w = world();
+    w.add_light(&point_light(&point(0., 10., 0.), &color(1., 1., 1.)));
+    let mut blocker = instance(&proto);
+    blocker.set_transform(&translation(0., 5., 0.));
+    w.add(&blocker);
+    assert!(is_shadowed(&w, &point(0., 0., 0.)));
+    w.objects[0].shadow = false;
+    assert!(!is_shadowed(&w, &point(0., 0., 0.)));
+}
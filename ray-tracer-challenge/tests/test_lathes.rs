@@ -0,0 +1,50 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn test_lathe_closed_cylinder() {
+    // A profile that matches a closed unit cylinder from y = 0 to y = 2.
+    let l = lathe(&[(0., 0.), (1., 0.), (1., 2.), (0., 2.)]);
+    assert_eq!(l.as_local_shape().local_bounding_box(), BoundingBox::new(point(-1., 0., -1.), point(1., 2., 1.)));
+
+    assert_eq!(local_times(&l, &ray(&point(-5., 1., 0.), &vector(1., 0., 0.))), vec![4., 6.]);
+    assert_eq!(local_times(&l, &ray(&point(0.5, 5., 0.), &vector(0., -1., 0.))), vec![3., 5.]);
+    assert!(local_times(&l, &ray(&point(-5., 3., 0.), &vector(1., 0., 0.))).is_empty());
+
+    let xs = local_intersect(&l, &ray(&point(-5., 1., 0.), &vector(1., 0., 0.)));
+    let n = l.shape_type.local_normal_at(&point(-1., 1., 0.), &xs[0]);
+    assert_eq!(n.normalized(), vector(-1., 0., 0.));
+    let xs = local_intersect(&l, &ray(&point(0.5, 5., 0.), &vector(0., -1., 0.)));
+    let n = l.shape_type.local_normal_at(&point(0.5, 2., 0.), &xs[0]);
+    assert_eq!(n.normalized(), vector(0., 1., 0.));
+}
+
+#[test]
+fn test_lathe_cone_frustum() {
+    // Radius shrinks from 2 at y = 0 to 1 at y = 1.
+    let l = lathe(&[(2., 0.), (1., 1.)]);
+    let xs = local_times(&l, &ray(&point(-5., 0.5, 0.), &vector(1., 0., 0.)));
+    assert_eq!(xs.len(), 2);
+    assert!(equal(xs[0], 3.5));
+    assert!(equal(xs[1], 6.5));
+
+    // The mirrored half of the cone isn't part of the surface.
+    assert!(local_times(&l, &ray(&point(-5., 3.5, 0.), &vector(1., 0., 0.))).is_empty());
+
+    let xs = local_intersect(&l, &ray(&point(-5., 0.5, 0.), &vector(1., 0., 0.)));
+    let n = l.shape_type.local_normal_at(&point(-1.5, 0.5, 0.), &xs[0]).normalized();
+    let k = 2_f64.sqrt() / 2.;
+    assert_eq!(n, vector(-k, k, 0.));
+}
+
+#[test]
+fn test_lathe_transformed() {
+    let mut l = lathe(&[(0., -1.), (1., 0.), (0., 1.)]);
+    l.set_transform(&translation(0., 0., 5.));
+    let xs = intersect(&l, &ray(&point(0., 0., 0.), &vector(0., 0., 1.)));
+    assert_eq!(xs.count, 2);
+    assert!(equal(xs[0].t, 4.));
+    assert!(equal(xs[1].t, 6.));
+}
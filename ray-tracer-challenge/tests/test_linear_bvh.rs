@@ -0,0 +1,75 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn test_linear_bvh_layout() {
+    let shapes = spheres(8);
+    let bvh = LinearBVH::build_for_shapes(&shapes);
+    // Every primitive appears exactly once.
+    let mut indices = bvh.indices.clone();
+    indices.sort();
+    assert_eq!(indices, (0..shapes.len()).collect::<Vec<usize>>());
+
+    // Depth first: an interior node's first child follows it, and children sit inside parents.
+    for (idx, node) in bvh.nodes.iter().enumerate() {
+        if node.is_leaf() {
+            assert!(node.count < 4);
+            continue;
+        }
+        for child in [idx + 1, node.offset] {
+            let mut bb = node.bb;
+            bb.include_bb(&bvh.nodes[child].bb);
+            assert_eq!(bb, node.bb);
+        }
+        assert!(node.offset > idx + 1);
+    }
+    assert!(bvh.max_depth() >= 4);
+
+    assert!(LinearBVH::build(&[]).nodes.is_empty());
+}
+
+#[test]
+fn test_linear_bvh_matches_recursive() {
+    let shapes = spheres(8);
+    let linear = LinearBVH::build_for_shapes(&shapes);
+    let bb = BoundingBox::from_transformed_shapes(&shapes.iter().collect::<Vec<&Shape>>());
+    let recursive = BVHNode::build(bb, &shapes);
+
+    for r in [
+        ray(&point(3., 3., -10.), &vector(0., 0., 1.)),
+        ray(&point(-5., 3.1, 0.), &vector(1., 0., 0.)),
+        ray(&point(-5., -5., -5.), &vector(1., 1., 0.9)),
+        ray(&point(10., 10., 0.2), &vector(0., 1., 0.)),
+        ray(&point(100., 3., 0.), &vector(0., 1., 0.)),
+    ] {
+        let mut p = BVHTraversalPolicy::new_all_hits();
+        linear.intersect_shapes(&shapes, &r, &mut p);
+        let all = intersections(p.intersections());
+        assert_eq!(times(&all.data), times(&intersections(recursive.intersect(&r)).data));
+
+        let mut p = BVHTraversalPolicy::new_closest_hit();
+        linear.intersect_shapes(&shapes, &r, &mut p);
+        assert_eq!(times(&p.intersections()), times(&recursive.intersect_closest(&r)));
+        assert_eq!(p.intersections().first().map(|i| i.t), all.hit().map(|i| i.t));
+
+        let mut p = BVHTraversalPolicy::new_any_hit();
+        linear.intersect_shapes(&shapes, &r, &mut p);
+        assert_eq!(p.intersections().is_empty(), recursive.intersect_any(&r).is_empty());
+    }
+}
+
+#[test]
+fn test_group_uses_indices() {
+    let mut g = group();
+    for s in spheres(8) {
+        add_child(&mut g, &s);
+    }
+    g.freeze_and_optimize();
+    let r = ray(&point(3., 3., -10.), &vector(0., 0., 1.));
+    let xs = intersections(g.intersect(&r));
+    assert_eq!(xs.count, 2);
+    // Intersections point at the group's own children, not at copies.
+    assert!(g.children().iter().any(|c| std::ptr::eq(c, xs[0].object)));
+}
@@ -0,0 +1,123 @@
+use std::{f64::consts::PI, fs};
+
+use ray_tracer_challenge::*;
+use serde_json::Value;
+
+fn scene_meshes() -> Vec<TriangleMesh> {
+    let mut w = world();
+    let mut s = sphere();
+    s.material.color = color(1., 0.5, 0.);
+    s.material.transparency = 0.25;
+    s.material.refractive_index = 1.5;
+    w.add(&s);
+    let mut c = cube();
+    c.set_transform(&translation(3., 0., 0.));
+    w.add(&c);
+    tessellate_world(&w, &TessellationOptions { segments: 8, rings: 4, ..TessellationOptions::default() })
+}
+
+#[test]
+fn test_obj_round_trip() {
+    let meshes = scene_meshes();
+    let (obj, mtl) = meshes_to_obj(&meshes, "scene.mtl");
+    assert!(obj.starts_with("mtllib scene.mtl\n"));
+
+    let p = try_parse_obj_file(obj.as_bytes()).unwrap();
+    for (k, mesh) in meshes.iter().enumerate() {
+        let object = p.named_object(&format!("mesh{k}")).unwrap();
+        assert_eq!(object.children().len(), mesh.triangles.len());
+        let t = &object.children()[0];
+        let [a, b, c] = mesh.triangles[0];
+        assert_eq!((t.p1(), t.p2(), t.p3()), (mesh.positions[a], mesh.positions[b], mesh.positions[c]));
+        assert_eq!(t.n1(), mesh.normals[a]);
+    }
+
+    let materials = parse_mtl_file(mtl.as_bytes()).unwrap();
+    let m = materials["material0"];
+    assert_eq!(m.color, color(1., 0.5, 0.));
+    assert!(equal(m.transparency, 0.25));
+    assert_eq!(m.refractive_index, 1.5);
+    assert!(equal(materials["material1"].ambient, material().ambient));
+}
+
+#[test]
+fn test_gltf() {
+    let meshes = scene_meshes();
+    let mut cam = camera(200., 100., PI / 2.);
+    cam.set_transform(&view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)));
+    let (json, bin) = meshes_to_gltf(&meshes, Some(&cam), Some("scene.bin"));
+    let doc: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(doc["asset"]["version"], "2.0");
+    assert_eq!(doc["buffers"][0]["uri"], "scene.bin");
+    assert_eq!(doc["buffers"][0]["byteLength"], bin.len());
+    assert_eq!(doc["meshes"].as_array().unwrap().len(), 2);
+    assert_eq!(doc["scenes"][0]["nodes"].as_array().unwrap().len(), 3);
+
+    let sphere = &doc["meshes"][0]["primitives"][0];
+    let positions = &doc["accessors"][sphere["attributes"]["POSITION"].as_u64().unwrap() as usize];
+    assert_eq!(positions["count"], meshes[0].positions.len());
+    assert_eq!(positions["min"], serde_json::json!([-1., -1., -1.]));
+    let indices = &doc["accessors"][sphere["indices"].as_u64().unwrap() as usize];
+    assert_eq!(indices["count"], meshes[0].triangles.len() * 3);
+    // The first index, read back from the buffer.
+    let view = &doc["bufferViews"][indices["bufferView"].as_u64().unwrap() as usize];
+    let offset = view["byteOffset"].as_u64().unwrap() as usize;
+    let first = u32::from_le_bytes(bin[offset..offset + 4].try_into().unwrap());
+    assert_eq!(first as usize, meshes[0].triangles[0][0]);
+
+    let m = &doc["materials"][sphere["material"].as_u64().unwrap() as usize];
+    assert_eq!(m["pbrMetallicRoughness"]["baseColorFactor"], serde_json::json!([1., 0.5, 0., 0.75]));
+    assert_eq!(m["alphaMode"], "BLEND");
+
+    let cam_node = &doc["nodes"][2];
+    assert_eq!(cam_node["camera"], 0);
+    // Column-major, so the translation is at the end, and mirrored in z.
+    assert_eq!(cam_node["matrix"][14], 5.);
+    let yfov = doc["cameras"][0]["perspective"]["yfov"].as_f64().unwrap();
+    assert!(equal(yfov, 2. * (0.5_f64).atan()));
+}
+
+#[test]
+fn test_glb() {
+    let meshes = scene_meshes();
+    let (json, bin) = meshes_to_gltf(&meshes, None, None);
+    assert!(!json.contains("\"uri\""));
+    let glb = gltf_to_glb(&json, &bin);
+    assert_eq!(&glb[0..4], b"glTF");
+    let word = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap()) as usize;
+    assert_eq!(word(4), 2);
+    assert_eq!(word(8), glb.len());
+    let json_length = word(12);
+    assert_eq!(json_length % 4, 0);
+    assert_eq!(&glb[16..20], b"JSON");
+    let doc: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+    assert_eq!(doc["buffers"][0]["byteLength"], bin.len());
+    assert_eq!(&glb[24 + json_length..28 + json_length], b"BIN\0");
+    assert_eq!(&glb[28 + json_length..28 + json_length + bin.len()], bin.as_slice());
+
+    // An empty scene leaves out what glTF doesn't allow to be empty.
+    let (json, _) = meshes_to_gltf(&[], None, None);
+    let doc: Value = serde_json::from_str(&json).unwrap();
+    assert!(doc.get("meshes").is_none() && doc.get("buffers").is_none());
+}
+
+#[test]
+fn test_export_meshes() {
+    let dir = std::env::temp_dir().join(format!("export-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let meshes = scene_meshes();
+    for (name, written) in [
+        ("scene.obj", vec!["scene.obj", "scene.mtl"]),
+        ("scene.gltf", vec!["scene.gltf", "scene.bin"]),
+        ("scene.glb", vec!["scene.glb"]),
+    ] {
+        export_meshes(&meshes, None, &dir.join(name)).unwrap();
+        for f in written {
+            assert!(dir.join(f).exists());
+        }
+    }
+    let loaded = load_obj_file(&dir.join("scene.obj")).unwrap();
+    assert_eq!(loaded.named_object("mesh0").unwrap().children()[0].material.refractive_index, 1.5);
+    assert!(export_meshes(&meshes, None, &dir.join("scene.stl")).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,138 @@
+use std::fs;
+
+use ray_tracer_challenge::*;
+
+fn area(points: &[Tuple], [a, b, c]: [usize; 3]) -> Tuple {
+    (points[b] - points[a]).cross(&(points[c] - points[a])) * 0.5
+}
+
+#[test]
+fn test_errors_have_line_numbers() {
+    for (file, line, message) in [
+        ("v 1 2 3\nv 1 x 3", 2, "invalid number \"x\""),
+        ("v 1 2", 1, "v needs 3 coordinates"),
+        ("v 0 0 0\nv 1 0 0\n\nf 1 2", 4, "faces need at least 3 vertices, not 2"),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4", 4, "vertex index 4 is out of range, with 3 defined"),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1//1 2//1 3//1", 4, "normal index 1 is out of range, with 0 defined"),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/2 2/1 3/1", 5, "texture index 2 is out of range, with 1 defined"),
+    ] {
+        let e = try_parse_obj_file(file.as_bytes()).err().unwrap();
+        assert_eq!(e, ObjError { line, message: message.to_owned() });
+        assert_eq!(e.to_string(), format!("line {line}: {message}"));
+    }
+}
+
+#[test]
+fn test_negative_indices() {
+    let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//-1 -2//-1 -1//-1\nv 5 5 5\nf 1 2 -1";
+    let p = try_parse_obj_file(file.as_bytes()).unwrap();
+    let g = p.default_group();
+    let t1 = &g.children()[0];
+    assert_eq!((t1.p1(), t1.p2(), t1.p3()), (p.vertices[1], p.vertices[2], p.vertices[3]));
+    assert_eq!(t1.n1(), vector(0., 0., 1.));
+    assert_eq!(g.children()[1].p3(), point(5., 5., 5.));
+}
+
+#[test]
+fn test_texture_coordinates() {
+    let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 -1\nvt 0 0\nvt 1 0\nvt 0 1\nf 1/1 2/2 3/3\nf 1/1/1 2/2/1 3/3/1";
+    let p = try_parse_obj_file(file.as_bytes()).unwrap();
+    let g = p.default_group();
+    let uvs = [(0., 0.), (1., 0.), (0., 1.)];
+    for c in g.children() {
+        assert_eq!(c.uvs(), Some(uvs));
+    }
+    let ShapeType::Triangle(t) = &g.children()[0].shape_type else { panic!() };
+    assert_eq!(t.uv_at(&point(0.25, 0.5, 0.)), Some((0.25, 0.5)));
+
+    let s = &g.children()[1];
+    let xs = s.intersect(&ray(&point(0.25, 0.5, -1.), &vector(0., 0., 1.)));
+    let ShapeType::SmoothTriangle(t) = &s.shape_type else { panic!() };
+    let (u, v) = t.uv_at(&xs[0]).unwrap();
+    assert!(equal(u, 0.25) && equal(v, 0.5));
+}
+
+#[test]
+fn test_objects_and_groups() {
+    let file = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\no Thing One\nf 1 2 3\ng Part\nf 1 2 3\nf 1 2 3\no Other\nf 1 2 3\ng Loose\nf 1 2 3";
+    let p = try_parse_obj_file(file.as_bytes()).unwrap();
+    assert_eq!(p.default_group().children().len(), 1);
+    let thing = p.named_object("Thing One").unwrap();
+    // One face of its own, and a group of two.
+    assert_eq!(thing.children().len(), 2);
+    assert_eq!(thing.children()[1].children().len(), 2);
+    assert_eq!(p.named_group("Part").unwrap().children().len(), 2);
+    assert_eq!(p.named_object("Other").unwrap().children().len(), 2);
+
+    let g = obj_to_group(&p);
+    // The default group, then both objects.
+    assert_eq!(g.children().len(), 3);
+}
+
+#[test]
+fn test_concave_polygons() {
+    // An L shape, whose fan from the first vertex would cover the missing corner.
+    let points = [
+        point(0., 0., 0.), point(2., 0., 0.), point(2., 1., 0.),
+        point(1., 1., 0.), point(1., 2., 0.), point(0., 2., 0.),
+    ];
+    let tris = triangulate(&points);
+    assert_eq!(tris.len(), 4);
+    let mut total = 0.;
+    for t in &tris {
+        // Every triangle keeps the polygon's winding.
+        let a = area(&points, *t);
+        assert!(a.z > 0.);
+        total += a.z;
+    }
+    assert!(equal(total, 3.));
+
+    let file = "v 0 0 0\nv 2 0 0\nv 2 1 0\nv 1 1 0\nv 1 2 0\nv 0 2 0\nf 1 2 3 4 5 6";
+    let g = parse_obj_file(file.as_bytes()).default_group();
+    // Nothing covers the missing corner.
+    let r = ray(&point(1.5, 1.5, -1.), &vector(0., 0., 1.));
+    assert!(g.intersect(&r).is_empty());
+    let r = ray(&point(0.3, 1.4, -1.), &vector(0., 0., 1.));
+    assert_eq!(g.intersect(&r).len(), 1);
+}
+
+#[test]
+fn test_mtl_files() {
+    let mtl = "# Materials\nnewmtl red shiny\nKd 1 0 0\nKa 0.3 0.3 0.3\nKs 0.6 0.6 0.6\nNs 50\n\nnewmtl glass\nKd 0 0 0\nd 0.1\nNi 1.5\n";
+    let materials = parse_mtl_file(mtl.as_bytes()).unwrap();
+    let red = materials["red shiny"];
+    assert_eq!(red.color, color(1., 0., 0.));
+    assert!(equal(red.ambient, 0.3) && equal(red.specular, 0.6));
+    assert_eq!(red.shininess, 50.);
+    let glass = materials["glass"];
+    assert!(equal(glass.transparency, 0.9));
+    assert_eq!(glass.refractive_index, 1.5);
+
+    let e = parse_mtl_file(b"newmtl a\nKd 1 0").err().unwrap();
+    assert_eq!(e.line, 2);
+
+    let dir = std::env::temp_dir().join(format!("obj-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("scene.mtl"), mtl).unwrap();
+    fs::write(dir.join("scene.obj"), "mtllib scene.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl glass\nf 1 2 3").unwrap();
+    fs::write(dir.join("missing.obj"), "mtllib scene.mtl\nv 0 0 0\nusemtl missing").unwrap();
+    fs::write(dir.join("broken.obj"), "v 0 0 0\nmtllib nowhere.mtl").unwrap();
+
+    let p = load_obj_file(&dir.join("scene.obj")).unwrap();
+    let faces = p.default_group();
+    assert_eq!(faces.children()[0].material, material());
+    assert_eq!(faces.children()[1].material, glass);
+    assert_eq!(p.material_libraries, vec!["scene.mtl".to_owned()]);
+
+    // Without a file to go from, the library isn't loaded.
+    let p = try_parse_obj_file(&fs::read(dir.join("scene.obj")).unwrap()).unwrap();
+    assert!(p.materials.is_empty());
+    assert_eq!(p.default_group().children()[1].material, material());
+
+    let e = load_obj_file(&dir.join("broken.obj")).err().unwrap();
+    assert_eq!(e.line, 2);
+    let e = load_obj_file(&dir.join("missing.obj")).err().unwrap();
+    assert_eq!((e.line, e.message.as_str()), (3, "no material \"missing\""));
+    assert!(load_obj_file(&dir.join("nothing.obj")).err().unwrap().message.contains("nothing.obj"));
+    fs::remove_dir_all(&dir).unwrap();
+}
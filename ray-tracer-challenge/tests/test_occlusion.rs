@@ -0,0 +1,118 @@
+use ray_tracer_challenge::*;
+
+fn spheres_group() -> Shape {
+    let mut g = group();
+    for i in 0..5 {
+        let mut s = sphere();
+        s.set_transform(&translation(0., 0., i as f64 * 3.));
+        add_child(&mut g, &s);
+    }
+    g
+}
+
+#[test]
+fn test_occluder_respects_t_max() {
+    let s = sphere();
+    let r = ray(&point(0., 0., -5.), &vector(0., 0., 1.));
+    assert!(s.intersect_occluder(&r, 4.).is_none());
+    assert!(s.intersect_occluder(&r, 4.5).is_some());
+    // Hits behind the origin don't count.
+    let r = ray(&point(0., 0., 5.), &vector(0., 0., 1.));
+    assert!(s.intersect_occluder(&r, f64::INFINITY).is_none());
+}
+
+#[test]
+fn test_occluder_respects_shadow_flag() {
+    let r = ray(&point(0., 0., -5.), &vector(0., 0., 1.));
+    let mut s = sphere();
+    s.shadow = false;
+    assert!(s.intersect_occluder(&r, 10.).is_none());
+
+    // Turning off shadows for a group turns them off for its children.
+    let mut g = spheres_group();
+    assert!(g.intersect_occluder(&r, 10.).is_some());
+    g.shadow = false;
+    assert!(g.intersect_occluder(&r, 10.).is_none());
+
+    // Only the shapes that cast shadows block the ray.
+    let mut g = group();
+    add_child(&mut g, &s);
+    let mut far = sphere();
+    far.set_transform(&translation(0., 0., 3.));
+    add_child(&mut g, &far);
+    for optimize in [false, true] {
+        if optimize {
+            g.freeze_and_optimize();
+        }
+        assert!(g.intersect_occluder(&r, 6.).is_none());
+        let x = g.intersect_occluder(&r, 10.).unwrap();
+        assert!(x.t > 6.);
+    }
+
+    let proto = prototype(&spheres_group());
+    let mut inst = instance(&proto);
+    assert!(inst.intersect_occluder(&r, 10.).unwrap().instance.is_some());
+    inst.shadow = false;
+    assert!(inst.intersect_occluder(&r, 10.).is_none());
+}
+
+#[test]
+fn test_occluder_in_groups_and_csg() {
+    let r = ray(&point(0., 0., -5.), &vector(0., 0., 1.));
+    let mut g = spheres_group();
+    assert!(g.intersect_occluder(&r, 3.).is_none());
+    assert!(g.intersect_occluder(&r, 4.5).is_some());
+    g.freeze_and_optimize();
+    assert!(g.intersect_occluder(&r, 3.).is_none());
+    assert!(g.intersect_occluder(&r, 4.5).is_some());
+
+    // The cube's front face is cut away, so the first thing blocking the ray is the hollow at t = 5.
+    let mut s = sphere();
+    s.set_transform(&translation(0., 0., -1.));
+    let c = csg("difference", &cube(), &s);
+    assert!(c.intersect_occluder(&r, 4.5).is_none());
+    assert!(c.intersect_occluder(&r, 5.5).is_some());
+}
+
+#[test]
+fn test_world_occlusion_matches_intersections() {
+    let mut w = world();
+    for i in 0..6 {
+        for j in 0..6 {
+            let mut s = if (i + j) % 3 == 0 { cube() } else { sphere() };
+            s.set_transform(&translation(i as f64 * 3., 0., j as f64 * 3.).scale(0.8, 0.8, 0.8));
+            s.shadow = (i * j) % 4 != 1;
+            w.add(&s);
+        }
+    }
+    let mut p = plane();
+    p.set_transform(&translation(0., -2., 0.));
+    w.add(&p);
+
+    for (r, t_max) in [
+        (ray(&point(-5., 0., 0.), &vector(1., 0., 0.)), 100.),
+        (ray(&point(-5., 0., 0.), &vector(1., 0., 0.)), 3.),
+        (ray(&point(-5., 0., -5.), &vector(1., 0., 1.)), 100.),
+        (ray(&point(1.5, 5., 1.5), &vector(0., -1., 0.)), 100.),
+        (ray(&point(1.5, 5., 1.5), &vector(0., -1., 0.)), 6.),
+        (ray(&point(3., 0., 3.), &vector(0., 0., 1.)), 100.),
+    ] {
+        let xs = w.intersect(&r);
+        let expected = xs.data.iter().any(|i| 0. < i.t && i.t < t_max && i.casts_shadow());
+        assert_eq!(w.is_occluded(&r, t_max), expected);
+        if let Some(x) = w.intersect_occluder(&r, t_max) {
+            assert!(0. < x.t && x.t < t_max && x.casts_shadow());
+        }
+    }
+}
+
+#[test]
+fn test_shadows_behind_non_shadow_casters() {
+    let mut w = default_world();
+    // A sphere that doesn't cast shadows doesn't hide the one behind it.
+    w.objects[1].shadow = false;
+    w.objects[0].set_transform(&translation(0., 0., 4.));
+    w.set_light(&point_light(&point(0., 0., -10.), &color(1., 1., 1.)));
+    assert!(is_shadowed(&w, &point(0., 0., 10.)));
+    assert!(!is_shadowed(&w, &point(0., 0., -5.)));
+}
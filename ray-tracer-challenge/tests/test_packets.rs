@@ -0,0 +1,116 @@
+use std::f64::consts::PI;
+
+use ray_tracer_challenge::*;
+
+fn camera_rays(c: &Camera, y: usize) -> Vec<Ray> {
+    (0..c.hsize).map(|x| ray_for_pixel(c, x, y)).collect()
+}
+
+fn assert_same_hits(w: &World, rays: &[Ray]) {
+    for chunk in rays.chunks(PACKET_SIZE) {
+        let packet = w.intersect_closest_hit_packet(chunk);
+        assert_eq!(packet.len(), chunk.len());
+        for (r, x) in chunk.iter().zip(packet) {
+            let scalar = w.intersect_closest_hit(r);
+            let expected = scalar.data.iter().find(|i| i.t >= 0.);
+            assert_eq!(x.map(|i| i.t), expected.map(|i| i.t));
+            if let (Some(a), Some(b)) = (x, expected) {
+                assert!(std::ptr::eq(a.object, b.object));
+                assert_eq!((a.u, a.v), (b.u, b.v));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_packet_bounding_box() {
+    let bb = BoundingBox::new(point(-1., -1., -1.), point(1., 1., 1.));
+    let rays = [
+        ray(&point(0., 0., -5.), &vector(0., 0., 1.)),
+        ray(&point(0., 2., -5.), &vector(0., 0., 1.)),
+        ray(&point(0.5, 0.5, 0.), &vector(1., 1., 0.)),
+    ];
+    let (hit, tmin, tmax) = bb.intersect_packet(&RayPacket::new(&rays));
+    for (lane, r) in rays.iter().enumerate() {
+        assert_eq!((hit[lane], tmin[lane], tmax[lane]), bb.local_intersect_tmin_tmax(r));
+    }
+    // The unused lane is inactive.
+    assert!(!hit[3]);
+}
+
+#[test]
+fn test_packet_triangles() {
+    let t = triangle(&point(0., 1., 0.), &point(-1., 0., 0.), &point(1., 0., 0.));
+    let rays = [
+        ray(&point(0., 0.5, -2.), &vector(0., 0., 1.)),
+        ray(&point(-1., 1., -2.), &vector(0., 0., 1.)),
+        ray(&point(0., -1., -2.), &vector(0., 1., 0.)),
+        ray(&point(0.2, 0.2, 3.), &vector(0., 0., -1.)),
+    ];
+    let mut hits = PacketHits::new();
+    t.intersect_closest_hit_packet(&RayPacket::new(&rays), &[true; PACKET_SIZE], &mut hits);
+    let ts: Vec<Option<f64>> = hits.hits.iter().map(|x| x.map(|i| i.t)).collect();
+    assert_eq!(ts, vec![Some(2.), None, None, Some(3.)]);
+}
+
+#[test]
+fn test_packet_world_matches_scalar() {
+    let mut w = world();
+    let mut teapot = teapot(Some(4));
+    teapot.set_transform(&rotation_y(0.3).translate(0., -1., 5.));
+    teapot.freeze_and_optimize();
+    w.add(&teapot);
+
+    // Instanced triangles, and a plane and sphere, which use the scalar fallback.
+    let mut tri = group();
+    add_child(&mut tri, &triangle(&point(0., 1., 0.), &point(-1., 0., 0.), &point(1., 0., 0.)));
+    let proto = prototype(&tri);
+    for i in 0..3 {
+        let mut inst = instance(&proto);
+        inst.set_transform(&translation(i as f64 * 2. - 2., 1.5, 3.));
+        w.add(&inst);
+    }
+    let mut p = plane();
+    p.set_transform(&translation(0., -1., 0.));
+    w.add(&p);
+    let mut s = sphere();
+    s.set_transform(&translation(-3., 0., 6.));
+    w.add(&s);
+
+    let mut c = camera(24., 16., PI / 2.);
+    c.set_transform(&view_transform(&point(0., 1., -2.), &point(0., 0., 5.), &vector(0., 1., 0.)));
+    for y in 0..c.vsize {
+        assert_same_hits(&w, &camera_rays(&c, y));
+    }
+}
+
+#[test]
+fn test_packet_render_matches_scalar() {
+    let mut w = world();
+    w.lights.push(point_light(&point(-10., 10., -10.), &color(1., 1., 1.)));
+    let mut teapot = teapot(Some(4));
+    teapot.set_transform(&rotation_y(0.3).translate(0., -1., 5.));
+    teapot.material.reflective = 0.3;
+    teapot.freeze_and_optimize();
+    w.add(&teapot);
+    // Transparent and CSG hits take the slow path.
+    let mut glass = glass_sphere();
+    glass.set_transform(&translation(1.5, 0.5, 2.));
+    w.add(&glass);
+    let mut c = csg("difference", &cube(), &sphere());
+    c.set_transform(&translation(-2.5, 0.5, 3.));
+    w.add(&c);
+    let mut p = plane();
+    p.set_transform(&translation(0., -1., 0.));
+    w.add(&p);
+
+    // Odd sizes, so some tiles are cut off at the edges.
+    let mut cam = camera(25., 15., PI / 2.);
+    cam.set_transform(&view_transform(&point(0., 1., -2.), &point(0., 0., 5.), &vector(0., 1., 0.)));
+    let image = render_with(&cam, &w, &RenderOptions { samples: 1, threads: 3 });
+    for y in 0..cam.vsize {
+        for x in 0..cam.hsize {
+            assert_eq!(pixel_at(&image, x as i64, y as i64), color_at(&w, &ray_for_pixel(&cam, x, y)), "at {x}, {y}");
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use ray_tracer_challenge::*;
+
+const SQUARE_HEADER: &str = "ply
+format FORMAT 1.0
+comment A unit square in z = 0
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+";
+
+fn binary_square() -> Vec<u8> {
+    let mut bytes = SQUARE_HEADER.replace("FORMAT", "binary_little_endian").into_bytes();
+    for (x, y) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+        for f in [x, y, 0_f32, 0., 0., 1.] {
+            bytes.extend(f.to_le_bytes());
+        }
+        bytes.extend([255, 0, 51]);
+    }
+    bytes.push(4);
+    for i in [0_i32, 1, 2, 3] {
+        bytes.extend(i.to_le_bytes());
+    }
+    for i in [0_i32, 1] {
+        bytes.extend(i.to_le_bytes());
+    }
+    bytes
+}
+
+fn ascii_square() -> Vec<u8> {
+    let body = "0 0 0 0 0 1 255 0 51\n1 0 0 0 0 1 255 0 51\n1 1 0 0 0 1 255 0 51\n0 1 0 0 0 1 255 0 51\n4 0 1 2 3\n0 1\n";
+    (SQUARE_HEADER.replace("FORMAT", "ascii") + body).into_bytes()
+}
+
+#[test]
+fn test_ascii_and_binary_agree() {
+    for bytes in [ascii_square(), binary_square()] {
+        let mesh = parse_ply(&bytes).unwrap();
+        assert_eq!(mesh.vertices, vec![point(0., 0., 0.), point(1., 0., 0.), point(1., 1., 0.), point(0., 1., 0.)]);
+        assert_eq!(mesh.normals.as_ref().unwrap()[2], vector(0., 0., 1.));
+        assert_eq!(mesh.colors.as_ref().unwrap()[0], color(1., 0., 0.2));
+        assert_eq!(mesh.faces, vec![vec![0, 1, 2, 3]]);
+
+        let g = ply_to_group(&mesh);
+        assert_eq!(g.children().len(), 2);
+        for t in g.children() {
+            assert!(matches!(t.shape_type, ShapeType::SmoothTriangle(_)));
+            assert_eq!(t.material.color, color(1., 0., 0.2));
+        }
+    }
+}
+
+#[test]
+fn test_vertex_colors_are_averaged_not_interpolated() {
+    let file = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0 255 0 0\n1 0 0 0 255 0\n0 1 0 0 0 255\n3 0 1 2\n";
+    let g = group_from_ply(file.as_bytes()).unwrap();
+    // The triangle has a single color, even though its corners are red, green and blue.
+    let t = &g.children()[0];
+    assert_eq!(t.material.color, color(1. / 3., 1. / 3., 1. / 3.));
+}
+
+#[test]
+fn test_plain_meshes() {
+    let file = "ply\r\nformat ascii 1.0\r\nelement vertex 3\r\nproperty double x\r\nproperty double y\r\nproperty double z\r\nelement face 1\r\nproperty list uchar uint vertex_index\r\nend_header\r\n0 1 0\r\n-1 0 0\r\n1 0 0\r\n3 0 1 2\r\n";
+    let mut g = group_from_ply(file.as_bytes()).unwrap();
+    let t = &g.children()[0];
+    assert!(matches!(t.shape_type, ShapeType::Triangle(_)));
+    assert_eq!((t.p1(), t.p2(), t.p3()), (point(0., 1., 0.), point(-1., 0., 0.), point(1., 0., 0.)));
+    assert_eq!(t.material, material());
+
+    g.freeze_and_optimize();
+    let xs = g.intersect(&ray(&point(0., 0.5, -2.), &vector(0., 0., 1.)));
+    assert_eq!(xs.len(), 1);
+    assert_eq!(xs[0].t, 2.);
+}
+
+#[test]
+fn test_invalid_files() {
+    let header = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+    for (file, message) in [
+        ("plx\n".to_owned(), "not a PLY file"),
+        ("ply\nformat ascii 1.0\nelement vertex 0\n".to_owned(), "header has no end_header"),
+        ("ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n".to_owned(), "unknown property type half"),
+        ("ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nend_header\n1\n".to_owned(), "vertices need x, y and z"),
+        (format!("{header}0 0 0\n3 0 0"), "unexpected end of file"),
+        (format!("{header}0 0 zero\n"), "invalid number \"zero\""),
+        (format!("{header}0 0 0\n2 0 0\n"), "faces need at least 3 vertices, not 2"),
+        (format!("{header}0 0 0\n3 0 0 1\n"), "vertex index 1 is out of range, with 1 defined"),
+        (format!("{}0 0 0\n4000000000 0\n", header.replace("uchar", "uint")), "list of 4000000000 items is longer than the file"),
+    ] {
+        assert_eq!(parse_ply(file.as_bytes()).err().unwrap().to_string(), message);
+    }
+}
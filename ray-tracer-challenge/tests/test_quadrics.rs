@@ -0,0 +1,66 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn test_quadric_matches_sphere() {
+    let q = quadric(&[1., 1., 1., 0., 0., 0., 0., 0., 0., -1.]);
+    let s = sphere();
+    for (o, d) in [
+        (point(0., 0., -5.), vector(0., 0., 1.)),
+        (point(0., 1., -5.), vector(0., 0., 1.)),
+        (point(0., 2., -5.), vector(0., 0., 1.)),
+        (point(0.3, -0.2, 0.), vector(0.5, 0.5, 1.)),
+    ] {
+        let r = ray(&o, &d);
+        assert_times(&local_times(&q, &r), &local_times(&s, &r));
+    }
+    assert_eq!(local_normal_at(&q, &point(0., 1., 0.)).normalized(), vector(0., 1., 0.));
+}
+
+#[test]
+fn test_ellipsoid() {
+    let e = ellipsoid(2., 1., 3.);
+    assert_times(&local_times(&e, &ray(&point(-5., 0., 0.), &vector(1., 0., 0.))), &[3., 7.]);
+    assert_times(&local_times(&e, &ray(&point(0., 0., -5.), &vector(0., 0., 1.))), &[2., 8.]);
+    assert_eq!(e.as_local_shape().local_bounding_box(), BoundingBox::new(point(-2., -1., -3.), point(2., 1., 3.)));
+}
+
+#[test]
+fn test_large_quadrics_are_not_treated_as_planes() {
+    let e = ellipsoid(200., 200., 200.);
+    assert_times(&local_times(&e, &ray(&point(-500., 0., 0.), &vector(1., 0., 0.))), &[300., 700.]);
+}
+
+#[test]
+fn test_scaled_coefficients_give_the_same_hits() {
+    let sphere = [1., 1., 1., 0., 0., 0., 0., 0., 0., -1.];
+    let tiny = quadric(&sphere.map(|c| c * 1e-13));
+    let r = ray(&point(0.3, -0.2, -5.), &vector(0., 0.1, 1.));
+    assert_times(&local_times(&tiny, &r), &local_times(&quadric(&sphere), &r));
+}
+
+#[test]
+fn test_paraboloid_clipping() {
+    let p = paraboloid(4.);
+    // Down the axis, the ray is parallel to the paraboloid, so there is a single hit at the vertex.
+    assert_times(&local_times(&p, &ray(&point(0., 10., 0.), &vector(0., -1., 0.))), &[10.]);
+    // Horizontally at y = 1, we cross the bowl at x = -1 and x = 1.
+    assert_times(&local_times(&p, &ray(&point(-5., 1., 0.), &vector(1., 0., 0.))), &[4., 6.]);
+    // Above the clipping height, nothing.
+    assert_times(&local_times(&p, &ray(&point(-5., 5., 0.), &vector(1., 0., 0.))), &[]);
+}
+
+#[test]
+fn test_hyperboloids() {
+    let h = hyperboloid(1., 2.);
+    // Through the waist, we cross both sides.
+    assert_times(&local_times(&h, &ray(&point(-5., 0., 0.), &vector(1., 0., 0.))), &[4., 6.]);
+    // Down the axis of one sheet is open.
+    assert_times(&local_times(&h, &ray(&point(0., 5., 0.), &vector(0., -1., 0.))), &[]);
+
+    let h = hyperboloid_two_sheets(3.);
+    assert_times(&local_times(&h, &ray(&point(0., 5., 0.), &vector(0., -1., 0.))), &[4., 6.]);
+    assert_eq!(local_normal_at(&h, &point(0., 1., 0.)).normalized(), vector(0., 1., 0.));
+}
@@ -0,0 +1,85 @@
+use std::{f64::consts::PI, path::Path, sync::atomic::{AtomicBool, Ordering}, thread, time::Duration};
+
+use ray_tracer_challenge::*;
+
+fn default_camera(hsize: f64, vsize: f64) -> Camera {
+    let mut c = camera(hsize, vsize, PI / 2.);
+    c.set_transform(&view_transform(&point(0., 0., -5.), &point(0., 0., 0.), &vector(0., 1., 0.)));
+    c
+}
+
+#[test]
+fn test_rendering_on_threads_and_with_samples() {
+    let w = default_world();
+    let c = default_camera(21., 11.);
+    let single = render(&c, &w);
+    let threaded = render_with(&c, &w, &RenderOptions { samples: 1, threads: 4 });
+    for y in 0..11 {
+        for x in 0..21 {
+            assert_eq!(pixel_at(&threaded, x, y), pixel_at(&single, x, y));
+        }
+    }
+
+    let smooth = render_with(&c, &w, &RenderOptions { samples: 16, threads: 0 });
+    assert_eq!(pixel_at(&smooth, 0, 0), BLACK);
+    let (a, b) = (pixel_at(&smooth, 10, 5), pixel_at(&single, 10, 5));
+    assert!((a.red - b.red).abs() < 0.02 && (a.green - b.green).abs() < 0.02);
+    // Pixels on the sphere's edge are a blend of it and the background.
+    let edge = (0..21).map(|x| pixel_at(&smooth, x, 5)).find(|c| c.green > 0. && c.green < pixel_at(&single, 10, 5).green / 2.);
+    assert!(edge.is_some());
+}
+
+#[test]
+fn test_cancelling_a_render() {
+    let w = default_world();
+    let c = default_camera(21., 11.);
+    let options = RenderOptions { samples: 1, threads: 2 };
+    let cancel = AtomicBool::new(false);
+    let full = render_cancellable(&c, &w, &options, &cancel).unwrap();
+    assert_eq!(canvas_to_raw_ppm(&full), canvas_to_raw_ppm(&render_with(&c, &w, &options)));
+
+    // Set from another thread while rendering, the render stops after the rows being worked on.
+    let big = default_camera(2000., 2000.);
+    thread::scope(|s| {
+        s.spawn(|| {
+            thread::sleep(Duration::from_millis(10));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        assert!(render_cancellable(&big, &w, &RenderOptions { samples: 64, threads: 2 }, &cancel).is_none());
+    });
+}
+
+#[test]
+fn test_image_formats() {
+    let mut c = canvas(2, 1);
+    write_pixel(&mut c, 0, 0, &color(1., 0.5, 0.));
+    write_pixel(&mut c, 1, 0, &color(2., -1., 0.25));
+    assert_eq!(canvas_to_raw_ppm(&c), b"P6\n2 1\n255\n\xff\x80\x00\xff\x00\x40");
+    let pfm = canvas_to_pfm(&c);
+    assert!(pfm.starts_with(b"PF\n2 1\n-1.0\n"));
+    assert_eq!(pfm[pfm.len() - 12..pfm.len() - 8], 2_f32.to_le_bytes());
+
+    assert_eq!(image_format(Path::new("out.ppm"), None).unwrap(), "ppm");
+    assert_eq!(image_format(Path::new("out.ppm"), Some("raw-ppm")).unwrap(), "raw-ppm");
+    assert_eq!(image_format(Path::new("out.png"), None).unwrap_err(), "can't save images as \"png\", expected ppm, raw-ppm or pfm");
+}
+
+#[test]
+fn test_orbiting_a_camera() {
+    let c = default_camera(11., 11.);
+    let mut o = Orbit::around(&c, &point(0., 0., 0.));
+    assert!((o.distance - 5.).abs() < EPSILON && o.pitch.abs() < EPSILON);
+    assert_eq!(o.camera(&c).transform(), c.transform());
+
+    o.rotate(PI / 2., 0.);
+    let r = ray_through(&o.camera(&c), 5.5, 5.5);
+    assert_eq!(r.origin, point(-5., 0., 0.));
+    assert_eq!(r.direction, vector(1., 0., 0.));
+
+    // Looking straight down would leave no way to tell which way is up.
+    o.rotate(0., PI);
+    assert!(o.pitch < PI / 2.);
+    o.zoom(0.5);
+    let from = ray_through(&o.camera(&c), 5.5, 5.5).origin;
+    assert!(((from - point(0., 0., 0.)).magnitude() - 2.5).abs() < EPSILON);
+}
@@ -0,0 +1,171 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+fn spheres_group() -> Shape {
+    let mut g = group();
+    for s in spheres(8) {
+        add_child(&mut g, &s);
+    }
+    g.freeze_and_optimize();
+    g
+}
+
+fn rays() -> Vec<Ray> {
+    let mut rays = vec![];
+    for i in 0..10 {
+        for j in 0..10 {
+            rays.push(ray(&point(i as f64 * 2.4 - 1., j as f64 * 2.4 - 1., -10.), &vector(0., 0., 1.)));
+        }
+    }
+    rays.push(ray(&point(-5., -5., -5.), &vector(1., 1., 0.5)));
+    rays
+}
+
+fn assert_matches_brute_force(w: &World) {
+    for r in rays() {
+        assert_eq!(times(&w.intersect(&r).data), times(&brute_force(w, &r).data));
+    }
+}
+
+#[test]
+fn test_handles() {
+    let mut w = world();
+    w.add(&spheres_group());
+    w.add(&plane());
+    let mut graph = SceneGraph::new(w);
+    let roots = graph.roots();
+    assert_eq!(roots.len(), 2);
+    let children = graph.children(roots[0]);
+    assert_eq!(children.len(), 64);
+
+    let s = graph.add(&sphere());
+    assert_eq!(graph.roots(), vec![roots[0], roots[1], s]);
+
+    // Removing shapes doesn't change what other handles point at.
+    let moved = graph.get(children[10]).unwrap().transform();
+    graph.remove(children[3]);
+    graph.remove(roots[1]);
+    assert!(!graph.contains(children[3]));
+    assert!(graph.get(roots[1]).is_none());
+    assert_eq!(graph.get(children[10]).unwrap().transform(), moved);
+    assert_eq!(graph.children(roots[0]).len(), 63);
+    assert_eq!(graph.roots(), vec![roots[0], s]);
+
+    // Removing a group forgets everything in it.
+    let removed = graph.remove(roots[0]);
+    assert_eq!(removed.children().len(), 63);
+    assert!(!graph.contains(children[0]));
+    assert_eq!(graph.world.objects.len(), 1);
+}
+
+#[test]
+fn test_moves_refit_bvhs() {
+    let mut w = world();
+    let mut outer = group();
+    add_child(&mut outer, &spheres_group());
+    outer.freeze_and_optimize();
+    w.add(&outer);
+    for i in 0..5 {
+        let mut s = cube();
+        s.set_transform(&translation(i as f64 * 4., -4., 0.));
+        w.add(&s);
+    }
+    let mut graph = SceneGraph::new(w);
+    assert_matches_brute_force(&graph.world);
+
+    let inner = graph.children(graph.roots()[0])[0];
+    let spheres = graph.children(inner);
+    graph.set_transform(spheres[0], &translation(10., 30., 0.));
+    graph.set_transform(spheres[20], &translation(-20., 0., 0.));
+    graph.set_transform(graph.roots()[3], &translation(5., 5., -5.));
+    assert_matches_brute_force(&graph.world);
+    let r = ray(&point(-20., 0., -10.), &vector(0., 0., 1.));
+    assert!(equal(graph.world.intersect(&r).hit().unwrap().t, 9.));
+
+    // Moving a whole group keeps its own BVH, and only refits the ones above it.
+    graph.set_transform(inner, &translation(0., 0., 10.));
+    assert_matches_brute_force(&graph.world);
+
+    graph.remove(spheres[5]);
+    graph.remove(graph.roots()[2]);
+    assert_matches_brute_force(&graph.world);
+}
+
+#[test]
+fn test_add_child_and_materials() {
+    let mut w = world();
+    w.add(&spheres_group());
+    let mut graph = SceneGraph::new(w);
+    let g = graph.roots()[0];
+    let mut s = sphere();
+    s.set_transform(&translation(-20., 0., 0.));
+    let h = graph.add_child(g, &s);
+    assert_eq!(graph.children(g).last(), Some(&h));
+    assert_matches_brute_force(&graph.world);
+    let r = ray(&point(-20., 0., -10.), &vector(0., 0., 1.));
+    assert!(equal(graph.world.intersect(&r).hit().unwrap().t, 9.));
+
+    let mut m = material();
+    m.ambient = 1.;
+    graph.set_material(g, &m);
+    assert_eq!(graph.world.intersect(&r).hit().unwrap().object.material.ambient, 1.);
+    assert!(graph.world.objects[0].children().iter().all(|c| c.material == m));
+
+    // CSG children get it too, and instances get it as an override, leaving the shared prototype.
+    let c = graph.add(&csg("union", &sphere(), &cube()));
+    let proto = prototype(&sphere());
+    let i = graph.add(&instance(&proto));
+    graph.set_material(c, &m);
+    graph.set_material(i, &m);
+    assert!(graph.get(c).unwrap().children().iter().all(|c| c.material == m));
+    assert_eq!(graph.get(i).unwrap().as_instance().unwrap().material, Some(m));
+    assert_eq!(proto.material, material());
+}
+
+#[test]
+fn test_moves_only_refit_the_path_above() {
+    let mut w = world();
+    let mut outer = group();
+    add_child(&mut outer, &spheres_group());
+    add_child(&mut outer, &sphere());
+    outer.freeze_and_optimize();
+    w.add(&outer);
+    let mut graph = SceneGraph::new(w);
+    let inner = graph.children(graph.roots()[0])[0];
+    let spheres = graph.children(inner);
+    for (k, h) in spheres.iter().enumerate().step_by(7) {
+        graph.set_transform(*h, &translation(k as f64, -(k as f64), 5.));
+    }
+    graph.set_transform(inner, &scaling(2., 1., 1.));
+
+    // Refitting every node from scratch gives the same boxes.
+    let mut refit = graph.world.objects[0].clone();
+    let inner_group = refit.as_group_mut().unwrap().children[0].as_group_mut().unwrap();
+    inner_group.refit();
+    assert_eq!(inner_group.bvh, graph.world.objects[0].children()[0].as_group().unwrap().bvh);
+    let outer_group = refit.as_group_mut().unwrap();
+    outer_group.refit();
+    assert_eq!(outer_group.bvh, graph.world.objects[0].as_group().unwrap().bvh);
+    assert_eq!(outer_group.bb, graph.world.objects[0].as_group().unwrap().bb);
+    assert_matches_brute_force(&graph.world);
+}
+
+#[test]
+fn test_refit_matches_rebuild() {
+    let shapes: Vec<Shape> = (0..40).map(|i| {
+        let mut s = sphere();
+        s.set_transform(&translation(i as f64 * 2., 0., 0.));
+        s
+    }).collect();
+    let mut bvh = LinearBVH::build_for_shapes(&shapes);
+    let root = bvh.nodes[0].bb;
+    bvh.refit(&shape_items(&shapes).iter().map(|(bb, _)| *bb).collect::<Vec<BoundingBox>>());
+    assert_eq!(bvh.nodes[0].bb, root);
+
+    assert!(bvh.remove_primitive(7));
+    let mut indices = bvh.indices.clone();
+    indices.sort();
+    assert_eq!(indices, (0..39).collect::<Vec<usize>>());
+}
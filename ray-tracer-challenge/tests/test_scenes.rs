@@ -0,0 +1,393 @@
+use std::{collections::HashMap, f64::consts::PI, path::Path};
+
+use ray_tracer_challenge::*;
+
+fn error(yaml: &str) -> SceneError {
+    Scene::from_yaml_str(yaml).err().unwrap()
+}
+
+#[test]
+fn test_book_scenes_load() {
+    for name in ["cover", "forum-scenes/pg159", "forum-scenes/table", "forum-scenes/cylinders", "forum-scenes/puppets", "forum-scenes/reflect-refract", "forum-scenes/groups"] {
+        let scene = Scene::from_path(Path::new(&format!("book-code/{name}.yml"))).unwrap_or_else(|e| panic!("{name}: {e}"));
+        assert!(!scene.world.objects.is_empty() && scene.cameras.len() == 1);
+    }
+}
+
+#[test]
+fn test_errors_say_where_they_are() {
+    let e = error("- add: light\n  at: [0, 0, 0]\n  intensity: [1, 1, 1]\n\n- add: sphere\n  material:\n    pattern:\n      type: marble\n      colors: [[1, 1, 1], [0, 0, 0]]\n");
+    assert_eq!(e, SceneError { file: String::new(), path: "[1].material.pattern".to_owned(), line: 5, message: "unknown pattern type \"marble\", expected checkers, stripes, gradient or rings".to_owned() });
+    assert_eq!(e.to_string(), "line 5, [1].material.pattern: unknown pattern type \"marble\", expected checkers, stripes, gradient or rings");
+
+    let e = error("- add: group\n  children:\n    - add: cube\n    - add: cube\n      transform:\n        - [scale, 1, 2, 3]\n        - [skew, 1, 0, 0, 0, 0, 0]\n");
+    assert_eq!((e.path.as_str(), e.line), ("[0].children[1].transform[1]", 1));
+    assert!(e.message.starts_with("unknown transform \"skew\""));
+    assert_eq!(error("- add: cube\n  transform:\n    - [translate, 1, 2]\n").message, "translate takes 3 arguments, got 2");
+}
+
+#[test]
+fn test_undefined_names_and_missing_fields() {
+    assert_eq!(error("- add: cube\n  material: shiny\n").message, "undefined material \"shiny\"");
+    assert_eq!(error("- add: cube\n  transform: [big]\n").message, "undefined transform \"big\"");
+    assert_eq!(error("- add: teapot\n").message, "unknown shape \"teapot\"");
+    assert_eq!(error("- define: m\n  extend: base\n  value:\n    ambient: 1\n").path, "[0].extend");
+    assert_eq!(error("- add: cylinder\n  min: 0\n  max: 1\n").message, "cylinder is missing `closed`");
+    assert_eq!(error("- add: cone\n  closed: true\n  max: 1\n").message, "cone is missing `min`");
+
+    // Entries that don't match say why.
+    let e = error("- add: camera\n  width: 100\n  height: 100\n  from: [0, 0, 0]\n  to: [0, 0, 1]\n  up: [0, 1, 0]\n");
+    assert_eq!(e.path, "[0]");
+    assert!(e.message.contains("field-of-view") || e.message.contains("field_of_view"), "{}", e.message);
+    assert!(error("- add: cube\n  colour: [1, 0, 0]\n").message.contains("unknown field `colour`"));
+    assert_eq!(error("- add: cube\n  - oops\n").line, 2);
+}
+
+#[test]
+fn test_invalid_disk_radii() {
+    assert_eq!(error("- add: annulus\n  inner-radius: 2\n").message, "annulus radii need 0 <= inner-radius < outer-radius, got 2 and 1");
+    assert_eq!(error("- add: disk\n  outer-radius: 0\n").message, "disk radii need 0 <= inner-radius < outer-radius, got 0 and 0");
+    assert_eq!(error("- add: disk\n  inner-radius: -1\n").path, "[0]");
+    assert!(Scene::from_yaml_str("- add: annulus\n  inner-radius: 0.5\n  outer-radius: 2\n").is_ok());
+}
+
+#[test]
+fn test_singular_transforms_and_cameras() {
+    let singular = "transform can't be inverted, like a scale by 0";
+    assert_eq!(error("- add: sphere\n  transform: [[scale, 0, 1, 1]]\n").message, singular);
+    assert_eq!(error("- define: flat\n  value: [[scale, 1, 0, 1]]\n").path, "[0].value");
+    let e = error("- add: cube\n  material:\n    pattern:\n      type: stripes\n      colors: [[1, 0, 0], [0, 0, 1]]\n      transform: [[shear, 1, 0, 1, 0, 0, 0]]\n");
+    assert_eq!((e.path.as_str(), e.message.as_str()), ("[0].material.pattern.transform", singular));
+
+    let camera = |fov: &str, from: &str, up: &str| error(&format!("- add: camera\n  width: 4\n  height: 4\n  field-of-view: {fov}\n  from: {from}\n  to: [0, 0, 0]\n  up: {up}\n"));
+    assert_eq!(camera("1", "[0, 0, 0]", "[0, 1, 0]").path, "[0].to");
+    assert_eq!(camera("1", "[0, 5, 0]", "[0, 1, 0]").path, "[0].up");
+    assert_eq!(camera("1", "[0, 0, -5]", "[0, 0, 0]").path, "[0].up");
+    assert_eq!(camera("1", "[0, 0, 1/0]", "[0, 1, 0]").message, "camera `from`, `to` and `up` need to be finite");
+    assert_eq!(camera("0", "[0, 0, -5]", "[0, 1, 0]").path, "[0].field-of-view");
+    assert_eq!(camera(".nan", "[0, 0, -5]", "[0, 1, 0]").path, "[0].field-of-view");
+}
+
+#[test]
+fn test_missing_camera_and_files() {
+    let dir = std::env::temp_dir().join(format!("scene-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("scene.yml");
+    std::fs::write(&path, "- add: sphere\n").unwrap();
+    assert_eq!(load(path.to_str().unwrap()).err().unwrap().message, "scene has no camera");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(Scene::from_path(Path::new("no-such-scene.yml")).err().unwrap().message.starts_with("can't read no-such-scene.yml"));
+}
+
+#[test]
+fn test_rendering_a_parsed_scene_at_several_sizes() {
+    let scene = Scene::from_yaml_str("
+- add: camera
+  width: 40
+  height: 20
+  field-of-view: 1
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+- add: light
+  at: [-10, 10, -10]
+  intensity: [1, 1, 1]
+- add: sphere
+").unwrap();
+    let full = scene.render().unwrap();
+    assert_eq!((full.width, full.height), (40, 20));
+    let c = scene.camera().unwrap().with_size(120, 60);
+    assert_eq!(c.transform(), scene.camera().unwrap().transform());
+    let large = render(&c, &scene.world);
+    assert_eq!((large.width, large.height), (120, 60));
+    // The middle of every 3x3 block of pixels sees the same as the smaller pixel it's in.
+    for (x, y) in [(0, 0), (20, 10), (25, 13)] {
+        assert_eq!(pixel_at(&large, 3 * x + 1, 3 * y + 1), pixel_at(&full, x, y));
+    }
+}
+
+#[test]
+fn test_csg_triangles_and_obj_files() {
+    let dir = std::env::temp_dir().join(format!("scene-obj-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("models")).unwrap();
+    std::fs::write(dir.join("models/tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    let path = dir.join("scene.yml");
+    std::fs::write(&path, "
+- add: csg
+  operation: difference
+  left:
+    add: cube
+  right:
+    add: sphere
+    transform:
+      - [translate, 0, 1, 0]
+- add: triangle
+  p1: [0, 0, 0]
+  p2: [1, 0, 0]
+  p3: [0, 1, 0]
+- add: smooth-triangle
+  p1: [0, 0, 0]
+  p2: [1, 0, 0]
+  p3: [0, 1, 0]
+  n1: [0, 0, -1]
+  n2: [0, 0, -1]
+  n3: [0, 0, -1]
+- add: obj
+  file: models/tri.obj
+").unwrap();
+    let scene = Scene::from_path(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let [c, t, st, obj] = [0, 1, 2, 3].map(|i| &scene.world.objects[i]);
+    assert_eq!(c.operation(), "difference");
+    assert_eq!(t.p2(), point(1., 0., 0.));
+    assert_eq!(st.n1(), vector(0., 0., -1.));
+    // The top of the cube is cut away.
+    let xs = intersections(c.intersect(&ray(&point(0., 5., 0.), &vector(0., -1., 0.))));
+    assert!(equal(xs.hit().unwrap().t, 5.));
+    assert_eq!(obj.children()[0].children()[0].p3(), point(0., 1., 0.));
+
+    assert_eq!(error("- add: csg\n  operation: xor\n  left: {add: cube}\n  right: {add: cube}\n").message, "unknown csg operation \"xor\", expected union, intersection or difference");
+    assert_eq!(error("- add: csg\n  operation: union\n  left: {add: cube}\n").message, "csg needs `left` and `right` shapes");
+    assert_eq!(error("- add: csg\n  operation: union\n  left: {add: cube}\n  right: {add: cube, material: m}\n").path, "[0].right.material");
+    assert_eq!(error("- add: triangle\n  p1: [0, 0, 0]\n  p2: [1, 0, 0]\n").message, "triangle is missing `p3`");
+    assert!(error("- add: obj\n  file: no-such-file.obj\n").message.starts_with("can't load no-such-file.obj"));
+}
+
+#[test]
+fn test_patterns_and_shearing() {
+    let scene = Scene::from_yaml_str("
+- add: sphere
+  material:
+    pattern:
+      type: gradient
+      colors: [[0, 0, 0], [1, 1, 1]]
+- add: sphere
+  material:
+    pattern:
+      type: rings
+      colors: [[1, 0, 0], [0, 0, 1]]
+- add: sphere
+  material:
+    pattern:
+      type: checkers
+      patterns:
+        - type: stripes
+          colors: [[1, 0, 0], [0, 1, 0]]
+        - type: stripes
+          colors: [[0, 0, 1], [1, 1, 1]]
+          transform:
+            - [translate, 0.5, 0, 0]
+  transform:
+    - [shear, 1, 0, 0, 0, 0, 0]
+").unwrap();
+    let pattern = |i: usize| scene.world.objects[i].material.pattern();
+    assert_eq!(pattern_at(&pattern(0), &point(0.25, 0., 0.)), color(0.25, 0.25, 0.25));
+    assert_eq!(pattern_at(&pattern(1), &point(1.5, 0., 0.)), color(0., 0., 1.));
+    let nested = pattern(2);
+    // Each square of the checkers has its own stripes.
+    assert_eq!(pattern_at(&nested, &point(0.25, 0.5, 0.5)), color(1., 0., 0.));
+    assert_eq!(pattern_at(&nested, &point(1.25, 0.5, 0.5)), color(0., 0., 1.));
+    assert_eq!(pattern_at(&nested, &point(1.75, 0.5, 0.5)), color(1., 1., 1.));
+    assert_eq!(nested.a, color(0.5, 0.5, 0.));
+    assert_eq!(scene.world.objects[2].transform(), shearing(1., 0., 0., 0., 0., 0.));
+
+    let nested_twice = "- add: cube\n  material:\n    pattern:\n      type: checkers\n      patterns:\n        - type: stripes\n          patterns: [{type: stripes, colors: [[0, 0, 0], [1, 1, 1]]}, {type: stripes, colors: [[0, 0, 0], [1, 1, 1]]}]\n        - type: stripes\n          colors: [[0, 0, 0], [1, 1, 1]]\n";
+    let e = error(nested_twice);
+    assert_eq!((e.path.as_str(), e.message.as_str()), ("[0].material.pattern.patterns[0]", "patterns can only be nested one level deep"));
+    assert_eq!(error("- add: cube\n  transform:\n    - [shear, 1, 0]\n").message, "shear takes 6 arguments, got 2");
+}
+
+#[test]
+fn test_parameters_and_expressions() {
+    let yaml = "
+- parameters:
+    radius: 2
+    angle: pi/4
+    height: radius * 3
+- add: camera
+  width: 10 * radius
+  height: 10
+  field-of-view: angle
+  from: [0, height, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+- define: big
+  value:
+    - [scale, radius, radius, radius]
+- add: sphere
+  transform:
+    - big
+    - [rotate-y, angle / 2]
+  material:
+    ambient: 1 / radius
+";
+    let scene = Scene::from_yaml_str(yaml).unwrap();
+    let c = scene.camera().unwrap();
+    assert_eq!((c.hsize, c.vsize, c.field_of_view), (20, 10, PI / 4.));
+    assert_eq!(c.inverse() * point(0., 0., 0.), point(0., 6., -5.));
+    let s = &scene.world.objects[0];
+    assert_eq!(s.transform(), rotation_y(PI / 8.) * scaling(2., 2., 2.));
+    assert_eq!(s.material.ambient, 0.5);
+
+    // Overrides win over the scene's values, and later parameters use them.
+    let overrides = HashMap::from([parse_parameter("radius=1/2").unwrap()]);
+    let scene = Scene::from_yaml_str_with_parameters(yaml, &overrides).unwrap();
+    assert_eq!(scene.camera().unwrap().inverse() * point(0., 0., 0.), point(0., 1.5, -5.));
+    assert_eq!(scene.world.objects[0].material.ambient, 2.);
+
+    let overrides = HashMap::from([("raduis".to_owned(), 1.)]);
+    assert_eq!(Scene::from_yaml_str_with_parameters(yaml, &overrides).err().unwrap().message, "the scene has no parameter called \"raduis\"");
+    assert_eq!(parse_parameter("radius"), Err("expected name=value, got \"radius\"".to_owned()));
+    let e = error("- add: sphere\n  material:\n    ambient: 2 * size\n");
+    assert_eq!((e.path.as_str(), e.message.as_str()), ("[0].material.ambient", "can't evaluate \"2 * size\": unknown name `size`"));
+    assert_eq!(error("- add: cube\n  transform:\n    - [translate, 1, 2, x]\n").path, "[0].transform[0]");
+    assert_eq!(error("- parameters:\n    field-of-view: 1\n").message, "invalid parameter name \"field-of-view\"");
+    assert_eq!(error("- add: camera\n  width: 10.5\n  height: 10\n  field-of-view: 1\n  from: [0, 0, 0]\n  to: [0, 0, 1]\n  up: [0, 1, 0]\n").message, "10.5 isn't a whole number of pixels");
+}
+
+#[test]
+fn test_includes() {
+    let dir = std::env::temp_dir().join(format!("scene-include-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("common")).unwrap();
+    std::fs::write(dir.join("common/materials.yml"), "
+- parameters:
+    shine: 50
+- define: white
+  value:
+    color: [1, 1, 1]
+    shininess: shine
+- include: models.yml
+").unwrap();
+    std::fs::write(dir.join("common/models.yml"), "- add: obj\n  file: tri.obj\n  material: white\n").unwrap();
+    std::fs::write(dir.join("common/tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+    std::fs::write(dir.join("scene.yml"), "
+- include: common/materials.yml
+- define: red
+  extend: white
+  value:
+    color: [1, 0, 0]
+- add: sphere
+  material: red
+").unwrap();
+    let scene = Scene::from_path(&dir.join("scene.yml")).unwrap();
+    assert_eq!(scene.world.objects.len(), 2);
+    // Included files are relative to the file including them.
+    assert_eq!(scene.world.objects[0].material.shininess, 50.);
+    let m = scene.world.objects[1].material;
+    assert_eq!((m.color, m.shininess), (color(1., 0., 0.), 50.));
+    let overrides = HashMap::from([("shine".to_owned(), 10.)]);
+    assert_eq!(Scene::from_path_with_parameters(&dir.join("scene.yml"), &overrides).unwrap().world.objects[1].material.shininess, 10.);
+
+    // Errors in included files say which file they're in.
+    std::fs::write(dir.join("common/models.yml"), "- add: obj\n  file: tri.obj\n  material: black\n").unwrap();
+    let e = Scene::from_path(&dir.join("scene.yml")).err().unwrap();
+    assert_eq!((e.file.ends_with("models.yml"), e.line, e.path.as_str()), (true, 1, "[0].material"));
+
+    std::fs::write(dir.join("common/models.yml"), "- include: ../scene.yml\n").unwrap();
+    let e = Scene::from_path(&dir.join("scene.yml")).err().unwrap();
+    assert!(e.file.ends_with("models.yml") && e.line == 1, "{e}");
+    assert!(e.message.starts_with("include cycle: ") && e.message.matches("scene.yml").count() == 2, "{e}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_writing_scenes_back_to_yaml() {
+    let mut w = world();
+    w.add_light(&point_light(&point(-10., 10., -10.), &color(1., 0.9, 0.8)));
+    let mut s = sphere();
+    s.set_transform(&rotation_y(PI / 5.).translate(1., 0., 0.));
+    s.material.reflective = 0.3;
+    let mut stripes = stripe_pattern(&color(1., 0., 0.), &color(0., 1., 0.));
+    stripes.set_transform(&scaling(0.2, 0.2, 0.2));
+    s.material.pattern = Some(nested_pattern(&checkers_pattern(&BLACK, &BLACK), &stripes, &ring_pattern(&BLACK, &WHITE)));
+    s.shadow = false;
+    w.add(&s);
+    let mut c = cone();
+    c.set_minimum(&-1.);
+    c.set_closed(&true);
+    let mut g = group();
+    add_child(&mut g, &c);
+    add_child(&mut g, &annulus(0.5, 2.));
+    let mut t = triangle(&point(0., 0., 0.), &point(1., 0., 0.), &point(0., 1., 0.));
+    t.set_uvs(&[(0., 0.), (1., 0.), (0., 1.)]);
+    add_child(&mut g, &t);
+    g.set_transform(&scaling(2., 2., 2.));
+    g.freeze_and_optimize();
+    w.add(&g);
+    w.add(&csg("union", &cylinder(), &quad()));
+    w.add(&lathe(&[(1., 0.), (0.5, 1.)]));
+    let mut q = hyperboloid(0.5, 1.);
+    q.set_clip(&point(-2., -1., -2.), &point(2., 1., 2.));
+    w.add(&q);
+    w.add(&heightfield(&[0., 1., 2., 3.], 2, 2));
+    w.add(&bezier_patch(&teapot_patches()[0]));
+    let mut cam = camera(40., 20., PI / 3.);
+    cam.set_transform(&view_transform(&point(1., 4., -8.), &point(0., 1., 0.), &vector(0., 1., 0.)));
+
+    let yaml = scene_to_yaml(&w, std::slice::from_ref(&cam)).unwrap();
+    let scene = Scene::from_yaml_str(&yaml).unwrap_or_else(|e| panic!("{e}\n{yaml}"));
+    // Reading it back and writing it again changes nothing but rounding in the camera.
+    assert_eq!(scene_to_yaml(&scene.world, &[]).unwrap(), scene_to_yaml(&w, &[]).unwrap());
+    assert_eq!(scene.world.objects.len(), w.objects.len());
+    assert_eq!(scene.world.lights[0].intensity, color(1., 0.9, 0.8));
+    assert_eq!(scene.world.objects[0].material, s.material);
+    assert!(!scene.world.objects[0].shadow);
+    assert_eq!(scene.world.objects[0].transform(), s.transform());
+    assert_eq!(scene.world.objects[1].children()[0].minimum(), -1.);
+    assert_eq!(scene.world.objects[1].children()[0].maximum(), f64::INFINITY);
+    assert_eq!(scene.world.objects[1].children()[2].uvs(), t.uvs());
+
+    let c = scene.camera().unwrap();
+    assert_eq!((c.hsize, c.vsize), (40, 20));
+    assert_eq!(c.transform(), cam.transform());
+    let (a, b) = (render(&cam, &w), render(c, &scene.world));
+    for y in 0..20 {
+        for x in 0..40 {
+            assert_eq!(pixel_at(&a, x, y), pixel_at(&b, x, y));
+        }
+    }
+
+    assert_eq!(error("- add: quadric\n  coefficients: [1, 0, 1]\n").message, "quadrics need 10 coefficients");
+    assert_eq!(error("- add: sphere\n  uvs: [[0, 0], [1, 0], [0, 1]]\n").message, "only triangles have uvs");
+    assert_eq!(error("- add: heightfield\n  width: 2\n  depth: 2\n  heights: [0, 1]\n").message, "a 2 by 2 heightfield needs 4 heights, got 2");
+}
+
+#[test]
+fn test_shapes_scenes_cant_hold_are_not_written() {
+    let mut w = world();
+    w.add(&sphere());
+    let mut g = group();
+    add_child(&mut g, &cube());
+    add_child(&mut g, &implicit_surface(Sdf::sphere(1.), BoundingBox::new(point(-1.5, -1.5, -1.5), point(1.5, 1.5, 1.5))));
+    w.add(&g);
+    let e = scene_to_yaml(&w, &[]).unwrap_err();
+    assert_eq!((e.path.as_str(), e.message.as_str()), ("objects[1].children[1]", "implicit surfaces can't be written as scene YAML"));
+
+    // Writing an instance as a copy of its prototype would lose the sharing.
+    let mut w = world();
+    w.add(&instance(&prototype(&cube())));
+    assert_eq!(scene_to_yaml(&w, &[]).unwrap_err().message, "instances can't be written as scene YAML");
+}
+
+#[test]
+fn test_named_cameras() {
+    let camera = |name: &str, width: usize| format!("- add: camera\n  {name}width: {width}\n  height: 10\n  field-of-view: 1\n  from: [0, 0, -5]\n  to: [0, 0, 0]\n  up: [0, 1, 0]\n");
+    let yaml = [camera("name: front\n  ", 20), camera("name: side\n  ", 30), camera("", 40)].concat();
+    let scene = Scene::from_yaml_str(&yaml).unwrap();
+    assert_eq!(scene.camera_labels(), ["front", "side", "2"]);
+    assert_eq!(scene.find_camera("side").unwrap().hsize, 30);
+    assert_eq!(scene.find_camera("2").unwrap().hsize, 40);
+    assert_eq!(scene.find_camera("top").err().unwrap().message, "no camera called \"top\", expected one of front, side, 2");
+    assert_eq!(scene.render_camera("front", &RenderOptions::default()).unwrap().width, 20);
+    let views = scene.render_all(&RenderOptions::default()).unwrap();
+    assert_eq!(views.iter().map(|(label, c)| (label.as_str(), c.width)).collect::<Vec<_>>(), [("front", 20), ("side", 30), ("2", 40)]);
+
+    // Names are kept when the scene is written out, and when resizing.
+    let written = Scene::from_yaml_str(&scene.to_yaml().unwrap()).unwrap();
+    assert_eq!(written.camera_labels(), ["front", "side", "2"]);
+    assert_eq!(scene.cameras[0].with_size(5, 5).name.as_deref(), Some("front"));
+
+    let e = error(&[camera("name: front\n  ", 20), camera("name: front\n  ", 30)].concat());
+    assert_eq!((e.path.as_str(), e.message.as_str()), ("[1].name", "there's already a camera called \"front\""));
+    assert_eq!(Scene::from_yaml_str("[]").unwrap().render_all(&RenderOptions::default()).err().unwrap().message, "scene has no camera");
+}
@@ -0,0 +1,71 @@
+use ray_tracer_challenge::*;
+
+const ASCII_STL: &str = "solid pyramid
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 0 0 1
+    endloop
+  endfacet
+endsolid pyramid
+";
+
+fn binary_stl(header: &[u8]) -> Vec<u8> {
+    let mut bytes = header.to_vec();
+    bytes.resize(80, 0);
+    bytes.extend(2_u32.to_le_bytes());
+    for tri in [[0., 0., 0., 1., 0., 0., 0., 1., 0.], [0., 0., 0., 0., 1., 0., 0., 0., 1_f32]] {
+        bytes.extend([0_u8; 12]);
+        for f in tri {
+            bytes.extend(f.to_le_bytes());
+        }
+        bytes.extend([0, 0]);
+    }
+    bytes
+}
+
+#[test]
+fn test_ascii_and_binary_agree() {
+    let expected = vec![
+        [point(0., 0., 0.), point(1., 0., 0.), point(0., 1., 0.)],
+        [point(0., 0., 0.), point(0., 1., 0.), point(0., 0., 1.)],
+    ];
+    assert_eq!(parse_stl(ASCII_STL.as_bytes()).unwrap(), expected);
+    assert_eq!(parse_stl(&binary_stl(b"binary")).unwrap(), expected);
+    // Binary files may start like ASCII ones.
+    assert_eq!(parse_stl(&binary_stl(b"solid but binary")).unwrap(), expected);
+}
+
+#[test]
+fn test_group_from_stl() {
+    let mut g = group_from_stl(ASCII_STL.as_bytes()).unwrap();
+    assert_eq!(g.children().len(), 2);
+    g.freeze_and_optimize();
+    let xs = g.intersect(&ray(&point(-1., 0.25, 0.25), &vector(1., 0., 0.)));
+    assert_eq!(xs.len(), 1);
+    assert_eq!(xs[0].t, 1.);
+}
+
+#[test]
+fn test_invalid_files() {
+    for (file, message) in [
+        ("hello", "not an STL file"),
+        ("solid x\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\nendfacet\n", "line 7: facets need 3 vertices, not 2"),
+        ("solid x\nfacet normal 0 0 1\nouter loop\nvertex 0 0 a\n", "line 4: invalid number \"a\""),
+        ("solid x\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\n", "unexpected end of file in a facet"),
+    ] {
+        assert_eq!(parse_stl(file.as_bytes()).err().unwrap().to_string(), message);
+    }
+    // A truncated binary file isn't mistaken for a valid one.
+    let mut bytes = binary_stl(b"binary");
+    bytes.pop();
+    assert!(parse_stl(&bytes).is_err());
+}
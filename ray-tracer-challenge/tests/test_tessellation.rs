@@ -0,0 +1,191 @@
+use ray_tracer_challenge::*;
+
+fn triangle_count(meshes: &[TriangleMesh]) -> usize {
+    meshes.iter().map(|m| m.triangles.len()).sum()
+}
+
+// Every triangle winds counter-clockwise around its vertex normals.
+fn assert_wound_outwards(meshes: &[TriangleMesh]) {
+    for m in meshes {
+        for &[a, b, c] in &m.triangles {
+            let face = (m.positions[b] - m.positions[a]).cross(&(m.positions[c] - m.positions[a]));
+            assert!(face.dot(&(m.normals[a] + m.normals[b] + m.normals[c])) > 0.);
+        }
+    }
+}
+
+// Every vertex is on the shape, where a ray coming down its normal hits it. We look just inside
+// each triangle, since vertices on edges can be just outside a shape's bounds.
+fn assert_on_surface(shape: &Shape, meshes: &[TriangleMesh]) {
+    for m in meshes {
+        for t in &m.triangles {
+            let [a, b, c] = t.map(|i| m.positions[i]);
+            let centroid = a + ((b - a) + (c - a)) / 3.;
+            for &i in t {
+                let (p, n) = (m.positions[i], m.normals[i]);
+                let q = p + (centroid - p) * 1e-4;
+                let xs = intersections(shape.intersect(&ray(&(q + n * 0.5), &-n)));
+                assert!(xs.data.iter().any(|x| (x.t - 0.5).abs() < 1e-3), "{p:?} isn't on the surface");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_spheres() {
+    let options = TessellationOptions::default();
+    let meshes = tessellate(&sphere(), &options);
+    assert_eq!(meshes.len(), 1);
+    // The triangles touching the poles have one each instead of two per quad.
+    assert_eq!(triangle_count(&meshes), 2 * options.segments * options.rings - 2 * options.segments);
+    for (p, n) in meshes[0].positions.iter().zip(&meshes[0].normals) {
+        assert!(equal(magnitude(&(*p - point(0., 0., 0.))), 1.));
+        assert_eq!(*n, *p - point(0., 0., 0.));
+    }
+    assert_wound_outwards(&meshes);
+}
+
+#[test]
+fn test_quadratic_shapes_lie_on_their_surfaces() {
+    let mut cyl = cylinder();
+    cyl.set_minimum(&-1.);
+    cyl.set_maximum(&2.);
+    cyl.set_closed(&true);
+    let mut cone = cone();
+    cone.set_minimum(&-1.);
+    cone.set_maximum(&0.5);
+    cone.set_closed(&true);
+    let lathe = lathe(&[(0.5, 0.), (1., 1.), (0.25, 2.)]);
+    for mut s in [sphere(), cyl, cone, lathe, cube(), annulus(0.5, 1.)] {
+        s.set_transform(&rotation_z(0.3).scale(1., 2., 0.5).translate(1., 0., 0.));
+        let meshes = tessellate(&s, &TessellationOptions::default());
+        assert!(triangle_count(&meshes) > 0);
+        assert_wound_outwards(&meshes);
+        assert_on_surface(&s, &meshes);
+    }
+}
+
+#[test]
+fn test_caps_and_unbounded_shapes() {
+    let options = TessellationOptions { segments: 8, ..TessellationOptions::default() };
+    let mut cyl = cylinder();
+    cyl.set_minimum(&0.);
+    cyl.set_maximum(&1.);
+    let open = triangle_count(&tessellate(&cyl, &options));
+    cyl.set_closed(&true);
+    // Each cap is a fan of one triangle per segment.
+    assert_eq!(triangle_count(&tessellate(&cyl, &options)), open + 2 * 8);
+
+    // The two nappes of a double cone meet at the apex.
+    let mut c = cone();
+    c.set_minimum(&-1.);
+    c.set_maximum(&1.);
+    assert_eq!(triangle_count(&tessellate(&c, &options)), 2 * 8);
+
+    let meshes = tessellate(&plane(), &options);
+    let xs: Vec<f64> = meshes[0].positions.iter().map(|p| p.x).collect();
+    assert_eq!(xs.iter().cloned().fold(f64::INFINITY, f64::min), -options.extent);
+    assert_eq!(xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max), options.extent);
+    let ys: Vec<f64> = tessellate(&cylinder(), &options)[0].positions.iter().map(|p| p.y).collect();
+    assert!(ys.iter().all(|y| y.abs() <= options.extent));
+}
+
+#[test]
+fn test_groups_instances_and_materials() {
+    let mut red = material();
+    red.color = color(1., 0., 0.);
+    let mut g = group();
+    g.set_transform(&translation(10., 0., 0.));
+    let mut s = sphere();
+    s.set_transform(&translation(0., 5., 0.));
+    add_child(&mut g, &s);
+    s.material = red;
+    add_child(&mut g, &s);
+    add_child(&mut g, &s);
+
+    // Runs of shapes with the same material share a mesh.
+    let meshes = tessellate(&g, &TessellationOptions::default());
+    assert_eq!(meshes.len(), 2);
+    assert_eq!(meshes[1].material, red);
+    assert_eq!(meshes[1].triangles.len(), 2 * meshes[0].triangles.len());
+    assert!(meshes.iter().flat_map(|m| &m.positions).all(|p| equal(magnitude(&(*p - point(10., 5., 0.))), 1.)));
+
+    let mut blue = material();
+    blue.color = color(0., 0., 1.);
+    let mut inst = instance_with_material(&prototype(&g), &blue);
+    inst.set_transform(&scaling(-1., 1., 1.));
+    let meshes = tessellate(&inst, &TessellationOptions::default());
+    assert_eq!(meshes.len(), 1);
+    assert_eq!(meshes[0].material, blue);
+    assert!(meshes[0].positions.iter().all(|p| equal(magnitude(&(*p - point(-10., 5., 0.))), 1.)));
+    // Mirroring doesn't turn the triangles inside out.
+    assert_wound_outwards(&meshes);
+}
+
+#[test]
+fn test_triangles_keep_their_order() {
+    let p = parse_obj_file(b"v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1 2 3\nf 1//1 2//1 3//1");
+    let g = obj_to_group(&p);
+    let meshes = tessellate(&g, &TessellationOptions::default());
+    let m = &meshes[0];
+    assert_eq!(m.triangles, vec![[0, 1, 2], [3, 4, 5]]);
+    assert_eq!(m.positions[1], point(1., 0., 0.));
+    assert!(m.normals.iter().all(|n| *n == vector(0., 0., 1.)));
+}
+
+#[test]
+fn test_csg() {
+    let mut s = sphere();
+    s.set_transform(&translation(0., 1., 0.));
+    let options = TessellationOptions::default();
+    let whole = triangle_count(&tessellate(&cube(), &options)) + triangle_count(&tessellate(&s, &options));
+    let c = csg("difference", &cube(), &s);
+    let meshes = tessellate(&c, &options);
+    assert!(triangle_count(&meshes) < whole);
+    assert_wound_outwards(&meshes);
+    for m in &meshes {
+        for t in &m.triangles {
+            let centroid = t.iter().fold(vector(0., 0., 0.), |acc, &i| acc + (m.positions[i] - point(0., 0., 0.))) / 3.;
+            // Nothing is left of the cube's top, and the sphere only lines the hole.
+            assert!(centroid.y < 1. - EPSILON);
+            assert!(centroid.x.abs() <= 1. + EPSILON && centroid.z.abs() <= 1. + EPSILON);
+        }
+    }
+}
+
+#[test]
+fn test_implicit_surfaces_heightfields_and_patches() {
+    let options = TessellationOptions { resolution: 16, ..TessellationOptions::default() };
+    let bounds = BoundingBox::new(point(-1.5, -1.5, -1.5), point(1.5, 1.5, 1.5));
+    let meshes = tessellate(&implicit_surface(Sdf::sphere(1.), bounds), &options);
+    assert!(triangle_count(&meshes) > 100);
+    assert_wound_outwards(&meshes);
+    for p in &meshes[0].positions {
+        assert!((magnitude(&(*p - point(0., 0., 0.))) - 1.).abs() < 0.05);
+    }
+
+    let meshes = tessellate(&ellipsoid(1., 2., 1.), &options);
+    assert_wound_outwards(&meshes);
+    assert!(meshes[0].positions.iter().all(|p| (p.x * p.x + p.y * p.y / 4. + p.z * p.z - 1.).abs() < 0.1));
+
+    let h = heightfield(&[0., 1., 0., 1., 2., 1.], 3, 2);
+    let meshes = tessellate(&h, &options);
+    assert_eq!(triangle_count(&meshes), 4);
+    assert_eq!(meshes[0].positions[4], point(0.5, 2., 1.));
+    assert_wound_outwards(&meshes);
+
+    let teapot = teapot(None);
+    let meshes = tessellate(&teapot, &TessellationOptions { resolution: 4, ..options });
+    let expected: usize = teapot.children()[0].children().iter().map(|p| tessellate_bezier_patch(p.as_bezier_patch().unwrap(), 4).children().len()).sum();
+    assert_eq!(triangle_count(&meshes), expected);
+    assert!(meshes[0].positions.iter().all(|p| p.y > -EPSILON));
+}
+
+#[test]
+fn test_worlds() {
+    let w = default_world();
+    let meshes = tessellate_world(&w, &TessellationOptions::default());
+    assert_eq!(meshes.len(), 2);
+    assert_eq!(meshes[0].material, w.objects[0].material);
+    assert!(meshes[1].positions.iter().all(|p| equal(magnitude(&(*p - point(0., 0., 0.))), 0.5)));
+}
@@ -0,0 +1,80 @@
+use ray_tracer_challenge::*;
+
+mod common;
+use common::*;
+
+fn busy_world() -> World {
+    let mut w = world();
+    for i in 0..10 {
+        for j in 0..10 {
+            let mut s = if (i + j) % 2 == 0 { sphere() } else { cube() };
+            s.set_transform(&translation(i as f64 * 3., j as f64 * 3., 0.).scale(0.8, 0.8, 0.8));
+            w.add(&s);
+        }
+    }
+    let mut p = plane();
+    p.set_transform(&translation(0., -5., 0.));
+    w.add(&p);
+    w
+}
+
+#[test]
+fn test_world_bvh_matches_brute_force() {
+    let w = busy_world();
+    for r in [
+        ray(&point(2.4, 4.8, -10.), &vector(0., 0., 1.)),
+        ray(&point(-5., -4.9, -5.), &vector(1., 1., 0.5)),
+        ray(&point(50., 20., 0.), &vector(-1., -0.2, 0.)),
+        // Only the plane.
+        ray(&point(-50., 0., -50.), &vector(0., -1., 0.)),
+        ray(&point(-50., 0., -50.), &vector(0., 1., 0.)),
+    ] {
+        let xs = w.intersect(&r);
+        assert_eq!(times(&xs.data), times(&brute_force(&w, &r).data));
+        let closest = w.intersect_closest_hit(&r);
+        assert_eq!(closest.hit().map(|i| i.t), xs.hit().map(|i| i.t));
+    }
+}
+
+#[test]
+fn test_world_bvh_updates() {
+    let mut w = busy_world();
+    let r = ray(&point(0., 0., -10.), &vector(0., 0., 1.));
+    assert!(equal(w.intersect(&r).hit().unwrap().t, 10. - 0.8));
+
+    // Adding an object rebuilds the BVH.
+    let mut s = sphere();
+    s.set_transform(&translation(0., 0., -5.));
+    w.add(&s);
+    assert!(equal(w.intersect(&r).hit().unwrap().t, 4.));
+
+    // So does changing objects in place, after the BVH was built.
+    w.objects[100].set_transform(&translation(0., 0., 5.));
+    w.objects[101].set_transform(&translation(100., 0., 0.));
+    assert!(equal(w.intersect(&r).hit().unwrap().t, 10. - 0.8));
+    // This one's box was off the ray, so a stale BVH would miss it.
+    w.objects[1].set_transform(&translation(0., 0., -8.));
+    assert!(equal(w.intersect(&r).hit().unwrap().t, 1.));
+
+    w.objects[1].material.ambient = 1.;
+    assert_eq!(w.intersect(&r).hit().unwrap().object.material.ambient, 1.);
+
+    // And pushing directly.
+    let mut s = sphere();
+    s.set_transform(&translation(0., 0., -8.5));
+    w.objects.push(s);
+    assert!(equal(w.intersect(&r).hit().unwrap().t, 0.5));
+}
+
+#[test]
+fn test_world_bvh_stats() {
+    let w = busy_world();
+    // The plane is unbounded, so it's tested outside the BVH.
+    let stats = w.bvh_stats();
+    assert_eq!(stats.primitives, 100);
+    assert_eq!(stats.leaf_sizes.iter().enumerate().map(|(n, count)| n * count).sum::<usize>(), 100);
+    assert!(stats.nodes > 1 && stats.sah_cost > 0.);
+    // Already built, so there's nothing to time.
+    assert_eq!(w.bvh_stats().build_time, std::time::Duration::ZERO);
+    assert_eq!(world().bvh_stats().primitives, 0);
+}